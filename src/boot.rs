@@ -0,0 +1,99 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::utils::crc::crc32;
+
+// See https://gbdev.io/pandocs/Power_Up_Sequence.html
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+// The CGB boot ROM is split around the cartridge header, which stays visible in between
+const HEADER_START: usize = 0x100;
+const HEADER_END: usize = 0x1FF;
+
+/// The hardware revisions, which each leave the machine in a slightly different state once
+/// their boot ROM is done.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Model {
+    Dmg0,
+    #[default]
+    Dmg,
+    Mgb,
+    Sgb,
+    Cgb,
+}
+
+impl Model {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "dmg0" => Some(Model::Dmg0),
+            "dmg" => Some(Model::Dmg),
+            "mgb" => Some(Model::Mgb),
+            "sgb" => Some(Model::Sgb),
+            "cgb" => Some(Model::Cgb),
+            _ => None,
+        }
+    }
+
+    /// The name `parse` takes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Cgb => "cgb",
+        }
+    }
+}
+
+/// A boot ROM, mapped over the start of the cartridge ROM until 0xFF50 is written.
+#[derive(Debug)]
+pub struct BootRom {
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    /// Takes either a 256 byte DMG boot ROM or a 2304 byte CGB one.
+    pub fn new(data: Vec<u8>) -> io::Result<Self> {
+        match data.len() {
+            DMG_BOOT_ROM_SIZE | CGB_BOOT_ROM_SIZE => Ok(Self { data }),
+            length => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid boot ROM size: {} bytes", length),
+            )),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        BootRom::new(fs::read(path)?)
+    }
+
+    /// The model the boot ROM is for, going by its size. The DMG, MGB and SGB ones are the same
+    /// size, so those all come out as the DMG.
+    pub fn model(&self) -> Model {
+        match self.data.len() {
+            CGB_BOOT_ROM_SIZE => Model::Cgb,
+            _ => Model::Dmg,
+        }
+    }
+
+    /// Whether the boot ROM can run on `model`.
+    pub fn runs_on(&self, model: Model) -> bool {
+        (model == Model::Cgb) == (self.model() == Model::Cgb)
+    }
+
+    /// CRC-32 of the whole boot ROM, to tell dumps apart.
+    pub fn checksum(&self) -> u32 {
+        crc32(self.data.iter())
+    }
+
+    /// The byte at `addr`, or None where the cartridge shows through.
+    pub fn read(&self, addr: usize) -> Option<u8> {
+        match addr {
+            HEADER_START..=HEADER_END => None,
+            _ => self.data.get(addr).copied(),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use super::{ram_offset, read_rom_bank, wrap_rom_bank, Mapper, ROM_BANK_SIZE};
+use crate::cartridge::header::NINTENDO_LOGO;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/MBC1.html
+#[derive(Debug)]
+pub struct MBC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 5-bit register selecting the ROM bank mapped at 0x4000..0x8000
+    bank1: u8,
+    // 2-bit register supplying the upper ROM bank bits or the RAM bank
+    bank2: u8,
+    // In mode 1, bank2 also applies to 0x0000..0x4000 and the RAM area
+    mode: bool,
+    // Multicarts wire bank2 to ROM address lines 18-19 instead of 19-20, ignoring bit 4 of bank1
+    multicart: bool,
+}
+
+impl Storage<usize, u8> for MBC1 {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            0x0000..=0x7FFF => read_rom_bank(&self.rom, self.rom_bank(src), src),
+            _ => match self.ram_offset(src) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        match dest {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can't be selected here, writing 0 selects bank 1 instead
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            0x6000..=0x7FFF => self.mode = value & 0x01 != 0,
+            _ => {
+                if let Some(offset) = self.ram_offset(dest) {
+                    self.ram[offset] = value;
+                }
+            }
+        }
+    }
+}
+
+impl Mapper for MBC1 {
+    fn rom_bank(&self, addr: usize) -> usize {
+        let bank = match addr {
+            0x0000..=0x3FFF if self.mode => self.upper_bits(),
+            0x0000..=0x3FFF => 0,
+            _ => self.upper_bits() | self.lower_bits(),
+        };
+
+        wrap_rom_bank(&self.rom, bank)
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for MBC1 {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.bank1);
+        state.write_u8(self.bank2);
+        state.write_bool(self.mode);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.bank1 = (state.read_u8()? & 0x1F).max(1);
+        self.bank2 = state.read_u8()? & 0x03;
+        self.mode = state.read_bool()?;
+
+        Ok(())
+    }
+}
+
+impl MBC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            multicart: MBC1::is_multicart(&rom),
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: false,
+        }
+    }
+
+    // Multicarts can't be told apart by their header, but every game in them starts with its own
+    // header at the beginning of a 256 KiB block, so the Nintendo logo shows up in bank 0x10 too.
+    fn is_multicart(rom: &[u8]) -> bool {
+        let offset = 0x10 * ROM_BANK_SIZE + 0x104;
+
+        rom.len() == 64 * ROM_BANK_SIZE
+            && rom[offset..offset + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+    }
+
+    fn lower_bits(&self) -> usize {
+        if self.multicart {
+            (self.bank1 & 0x0F) as usize
+        } else {
+            self.bank1 as usize
+        }
+    }
+
+    fn upper_bits(&self) -> usize {
+        if self.multicart {
+            (self.bank2 as usize) << 4
+        } else {
+            (self.bank2 as usize) << 5
+        }
+    }
+
+    fn ram_offset(&self, addr: usize) -> Option<usize> {
+        if !self.ram_enabled {
+            return None;
+        }
+
+        let bank = if self.mode { self.bank2 as usize } else { 0 };
+        ram_offset(&self.ram, bank, addr)
+    }
+}
@@ -0,0 +1,88 @@
+use super::{read_rom_bank, wrap_rom_bank, Mapper};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/MBC2.html
+#[derive(Debug)]
+pub struct MBC2 {
+    rom: Vec<u8>,
+    // 512 half-bytes of RAM built into the controller, only the lower nibble of each is used
+    ram: [u8; 0x200],
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Storage<usize, u8> for MBC2 {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            0x0000..=0x3FFF => read_rom_bank(&self.rom, 0, src),
+            0x4000..=0x7FFF => read_rom_bank(&self.rom, self.rom_bank as usize, src),
+            _ if self.ram_enabled => {
+                // Only 9 address lines are connected, so the RAM repeats through 0xA000..0xC000
+                // and the missing upper nibble reads back as 1s
+                self.ram[src & 0x1FF] | 0xF0
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        match dest {
+            // Bit 8 of the address decides which register is written to
+            0x0000..=0x3FFF if dest & 0x100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => {
+                self.rom_bank = value & 0x0F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x7FFF => (),
+            _ if self.ram_enabled => self.ram[dest & 0x1FF] = value & 0x0F,
+            _ => (),
+        }
+    }
+}
+
+impl Mapper for MBC2 {
+    fn rom_bank(&self, addr: usize) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => wrap_rom_bank(&self.rom, self.rom_bank as usize),
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for MBC2 {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = (state.read_u8()? & 0x0F).max(1);
+
+        Ok(())
+    }
+}
+
+impl MBC2 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            ram: [0; 0x200],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use super::rtc::Rtc;
+use super::{ram_offset, read_rom_bank, wrap_rom_bank, Mapper};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/MBC3.html
+#[derive(Debug)]
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    // 0x00-0x03 select a RAM bank, 0x08-0x0C select one of the clock registers
+    ram_bank: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Storage<usize, u8> for MBC3 {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            0x0000..=0x3FFF => read_rom_bank(&self.rom, 0, src),
+            0x4000..=0x7FFF => read_rom_bank(&self.rom, self.rom_bank as usize, src),
+            _ => match (self.ram_offset(src), &self.rtc) {
+                (Some(offset), _) => self.ram[offset],
+                (None, Some(rtc)) if self.ram_enabled => rtc.read(self.ram_bank),
+                _ => 0xFF,
+            },
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        match dest {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x7F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value,
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            }
+            _ => match (self.ram_offset(dest), self.rtc.as_mut()) {
+                (Some(offset), _) => self.ram[offset] = value,
+                (None, Some(rtc)) if self.ram_enabled => rtc.write(self.ram_bank, value),
+                _ => (),
+            },
+        }
+    }
+}
+
+impl Mapper for MBC3 {
+    fn rom_bank(&self, addr: usize) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => wrap_rom_bank(&self.rom, self.rom_bank as usize),
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+}
+
+impl Snapshot for MBC3 {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+
+        if let Some(rtc) = &self.rtc {
+            rtc.save(state);
+        }
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = (state.read_u8()? & 0x7F).max(1);
+        self.ram_bank = state.read_u8()?;
+
+        match self.rtc.as_mut() {
+            Some(rtc) => rtc.load(state),
+            None => Ok(()),
+        }
+    }
+}
+
+impl MBC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rtc: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rtc: has_rtc.then(Rtc::new),
+        }
+    }
+
+    fn ram_offset(&self, addr: usize) -> Option<usize> {
+        if !self.ram_enabled || self.ram_bank > 0x03 {
+            return None;
+        }
+
+        ram_offset(&self.ram, self.ram_bank as usize, addr)
+    }
+}
@@ -0,0 +1,114 @@
+use super::{ram_offset, read_rom_bank, wrap_rom_bank, Mapper};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/MBC5.html
+#[derive(Debug)]
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 9-bit ROM bank number, unlike the other controllers bank 0 can be mapped at 0x4000
+    rom_bank: u16,
+    ram_bank: u8,
+    // On rumble cartridges bit 3 of the RAM bank register drives the motor instead
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Storage<usize, u8> for MBC5 {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            0x0000..=0x3FFF => read_rom_bank(&self.rom, 0, src),
+            0x4000..=0x7FFF => read_rom_bank(&self.rom, self.rom_bank as usize, src),
+            _ => match self.ram_offset(src) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        match dest {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF if self.has_rumble => {
+                self.rumble = value & 0x08 != 0;
+                self.ram_bank = value & 0x07;
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            0x6000..=0x7FFF => (),
+            _ => {
+                if let Some(offset) = self.ram_offset(dest) {
+                    self.ram[offset] = value;
+                }
+            }
+        }
+    }
+}
+
+impl Mapper for MBC5 {
+    fn rom_bank(&self, addr: usize) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => wrap_rom_bank(&self.rom, self.rom_bank as usize),
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for MBC5 {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+        state.write_bool(self.ram_enabled);
+        state.write_u16(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.rumble);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.ram)?;
+        self.ram_enabled = state.read_bool()?;
+        self.rom_bank = state.read_u16()? & 0x1FF;
+        self.ram_bank = state.read_u8()? & 0x0F;
+        self.rumble = state.read_bool()?;
+
+        Ok(())
+    }
+}
+
+impl MBC5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    fn ram_offset(&self, addr: usize) -> Option<usize> {
+        if !self.ram_enabled {
+            return None;
+        }
+
+        ram_offset(&self.ram, self.ram_bank as usize, addr)
+    }
+}
@@ -0,0 +1,155 @@
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod rom_only;
+pub mod rtc;
+
+use std::fmt::Debug;
+
+use self::mbc1::MBC1;
+use self::mbc2::MBC2;
+use self::mbc3::MBC3;
+use self::mbc5::MBC5;
+use self::rom_only::RomOnly;
+use self::rtc::Rtc;
+use super::header::{CartridgeError, CartridgeHeader};
+use crate::state::Snapshot;
+use crate::utils::traits::Storage;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// A memory bank controller sitting between the bus and the cartridge. Reads and writes use the
+/// CPU's addresses, covering both the ROM area (0x0000..0x8000), where writes go to the bank
+/// registers, and the external RAM area (0xA000..0xC000).
+///
+/// Save states cover the bank registers, the RAM and the clock but not the ROM, which comes from
+/// the ROM file.
+///
+/// See https://gbdev.io/pandocs/MBCs.html
+pub trait Mapper: Storage<usize, u8> + Snapshot + Debug {
+    /// Whether the rumble motor is currently switched on.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// The raw contents of the cartridge RAM, in the layout used by `.sav` files.
+    fn ram(&self) -> &[u8];
+
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    /// The ROM bank currently mapped at `addr`, which is in 0x0000..0x8000.
+    fn rom_bank(&self, addr: usize) -> usize {
+        addr / ROM_BANK_SIZE
+    }
+
+    /// Advances anything on the cartridge that runs off the system clock.
+    fn tick(&mut self, _cycles: u32) {}
+
+    fn rtc(&self) -> Option<&Rtc> {
+        None
+    }
+
+    fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+}
+
+/// Picks the memory bank controller matching the cartridge type in the header.
+pub fn new(header: CartridgeHeader) -> Result<Box<dyn Mapper>, CartridgeError> {
+    let cartridge_type = header.cartridge_type;
+    let ram_size = ram_size(header.ram_size)?;
+    let rom: Vec<u8> = header.into();
+
+    let mapper: Box<dyn Mapper> = match cartridge_type {
+        0x00 | 0x08 | 0x09 => Box::new(RomOnly::new(rom, ram_size)),
+        0x01..=0x03 => Box::new(MBC1::new(rom, ram_size)),
+        0x05 | 0x06 => Box::new(MBC2::new(rom)),
+        0x0F | 0x10 => Box::new(MBC3::new(rom, ram_size, true)),
+        0x11..=0x13 => Box::new(MBC3::new(rom, ram_size, false)),
+        0x19..=0x1B => Box::new(MBC5::new(rom, ram_size, false)),
+        0x1C..=0x1E => Box::new(MBC5::new(rom, ram_size, true)),
+        _ => return Err(CartridgeError::UnsupportedCartridgeType(cartridge_type)),
+    };
+
+    Ok(mapper)
+}
+
+/// Whether the cartridge RAM is kept alive by a battery and should be saved to disk.
+pub fn has_battery(cartridge_type: u8) -> bool {
+    matches!(
+        cartridge_type,
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+    )
+}
+
+// See https://gbdev.io/pandocs/The_Cartridge_Header.html#0147--cartridge-type
+pub fn type_name(cartridge_type: u8) -> &'static str {
+    match cartridge_type {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0B => "MMM01",
+        0x0C => "MMM01+RAM",
+        0x0D => "MMM01+RAM+BATTERY",
+        0x0F => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1A => "MBC5+RAM",
+        0x1B => "MBC5+RAM+BATTERY",
+        0x1C => "MBC5+RUMBLE",
+        0x1D => "MBC5+RUMBLE+RAM",
+        0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "MBC6",
+        0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xFC => "POCKET CAMERA",
+        0xFD => "BANDAI TAMA5",
+        0xFE => "HuC3",
+        0xFF => "HuC1+RAM+BATTERY",
+        _ => "UNKNOWN",
+    }
+}
+
+// See https://gbdev.io/pandocs/The_Cartridge_Header.html#0149--ram-size
+pub fn ram_size(code: u8) -> Result<usize, CartridgeError> {
+    match code {
+        0x00 => Ok(0),
+        // Only used by a handful of homebrew ROMs
+        0x01 => Ok(0x800),
+        0x02 => Ok(RAM_BANK_SIZE),
+        0x03 => Ok(4 * RAM_BANK_SIZE),
+        0x04 => Ok(16 * RAM_BANK_SIZE),
+        0x05 => Ok(8 * RAM_BANK_SIZE),
+        _ => Err(CartridgeError::InvalidRamSize(code)),
+    }
+}
+
+/// Reads a byte from a ROM bank. Bank numbers wrap around the actual size of the ROM, the same
+/// way the unconnected upper bank lines are ignored on real cartridges.
+fn read_rom_bank(rom: &[u8], bank: usize, addr: usize) -> u8 {
+    let offset = wrap_rom_bank(rom, bank) * ROM_BANK_SIZE + (addr & (ROM_BANK_SIZE - 1));
+
+    rom[offset]
+}
+
+fn wrap_rom_bank(rom: &[u8], bank: usize) -> usize {
+    bank % (rom.len() / ROM_BANK_SIZE)
+}
+
+/// Works out the offset of a byte in cartridge RAM, or `None` if the cartridge has no RAM.
+fn ram_offset(ram: &[u8], bank: usize, addr: usize) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+
+    Some((bank * RAM_BANK_SIZE + (addr & (RAM_BANK_SIZE - 1))) % ram.len())
+}
@@ -0,0 +1,57 @@
+use super::{ram_offset, Mapper};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+/// A 32 KiB cartridge without a memory bank controller, optionally wired to a single bank of RAM.
+#[derive(Debug)]
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl Storage<usize, u8> for RomOnly {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            0x0000..=0x7FFF => self.rom.get(src).copied().unwrap_or(0xFF),
+            _ => match ram_offset(&self.ram, 0, src) {
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        if let (0xA000..=0xBFFF, Some(offset)) = (dest, ram_offset(&self.ram, 0, dest)) {
+            self.ram[offset] = value;
+        }
+    }
+}
+
+impl Mapper for RomOnly {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl Snapshot for RomOnly {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.ram)
+    }
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+// The MBC3 real time clock, see https://gbdev.io/pandocs/MBC3.html#the-clock-counter-registers
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+// Bits of the DH register
+const DAY_HIGH: u8 = 0b0000_0001;
+const HALT: u8 = 0b0100_0000;
+const DAY_CARRY: u8 = 0b1000_0000;
+
+// Registers as they are selected through 0x4000..0x6000, in between are the minutes, hours and
+// the low byte of the day counter
+const SECONDS: u8 = 0x08;
+const DAYS_HIGH: u8 = 0x0C;
+
+/// Size of the clock state appended to `.sav` files by VBA and BGB.
+pub const FOOTER_SIZE: usize = 48;
+
+#[derive(Debug)]
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+
+    // Copy of the registers taken when the clock is latched, this is what the game reads
+    latched: [u8; 5],
+    // Latching takes a write of 0x00 followed by a write of 0x01
+    latch_armed: bool,
+
+    // T-cycles elapsed since the last time the seconds register ticked
+    subsecond_cycles: u32,
+}
+
+// Unlike the save footer there's no timestamp, the clock only moves with the emulated time
+impl Snapshot for Rtc {
+    fn save(&self, state: &mut StateWriter) {
+        for register in self.registers().into_iter().chain(self.latched) {
+            state.write_u8(register);
+        }
+
+        state.write_bool(self.latch_armed);
+        state.write_u32(self.subsecond_cycles);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let mut registers = [0; 5];
+        for register in &mut registers {
+            *register = state.read_u8()?;
+        }
+        self.set_registers(registers);
+
+        for register in &mut self.latched {
+            *register = state.read_u8()?;
+        }
+
+        self.latch_armed = state.read_bool()?;
+        self.subsecond_cycles = state.read_u32()? % CYCLES_PER_SECOND;
+
+        Ok(())
+    }
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+            latched: [0; 5],
+            latch_armed: false,
+            subsecond_cycles: 0,
+        }
+    }
+
+    /// Advances the clock by a number of emulated T-cycles, which keeps it deterministic
+    /// regardless of how fast the emulator runs.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.halted {
+            return;
+        }
+
+        self.subsecond_cycles += cycles;
+
+        while self.subsecond_cycles >= CYCLES_PER_SECOND {
+            self.subsecond_cycles -= CYCLES_PER_SECOND;
+            self.tick_second();
+        }
+    }
+
+    /// Advances the clock by whole seconds, used to catch up with the real time that passed
+    /// while the emulator wasn't running.
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halted {
+            return;
+        }
+
+        // Out of range values (e.g. 61 seconds) count up to the register's limit and roll over
+        // without carrying, which only ticking one second at a time reproduces
+        while seconds > 0 && (self.seconds > 59 || self.minutes > 59 || self.hours > 23) {
+            self.tick_second();
+            seconds -= 1;
+        }
+
+        let total = self.seconds as u64
+            + 60 * (self.minutes as u64 + 60 * (self.hours as u64 + 24 * self.days as u64))
+            + seconds;
+
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.day_carry = true;
+        }
+        self.days = (days % 0x200) as u16;
+    }
+
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    /// The current value of the clock registers, in S/M/H/DL/DH order.
+    pub fn registers(&self) -> [u8; 5] {
+        let mut days_high = (self.days >> 8) as u8 & DAY_HIGH;
+
+        if self.halted {
+            days_high |= HALT;
+        }
+
+        if self.day_carry {
+            days_high |= DAY_CARRY;
+        }
+
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            days_high,
+        ]
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    fn set_registers(&mut self, registers: [u8; 5]) {
+        let [seconds, minutes, hours, days_low, days_high] = registers;
+
+        self.seconds = seconds & 0x3F;
+        self.minutes = minutes & 0x3F;
+        self.hours = hours & 0x1F;
+        self.days = ((days_high & DAY_HIGH) as u16) << 8 | days_low as u16;
+        self.halted = days_high & HALT != 0;
+        self.day_carry = days_high & DAY_CARRY != 0;
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            SECONDS..=DAYS_HIGH => self.latched[(register - SECONDS) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        if !(SECONDS..=DAYS_HIGH).contains(&register) {
+            return;
+        }
+
+        // Writing the seconds also resets the divider feeding them
+        if register == SECONDS {
+            self.subsecond_cycles = 0;
+        }
+
+        let mut registers = self.registers();
+        registers[(register - SECONDS) as usize] = value;
+        self.set_registers(registers);
+
+        // Games read back what they wrote without latching again
+        self.latched[(register - SECONDS) as usize] =
+            self.registers()[(register - SECONDS) as usize];
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.registers();
+        }
+
+        self.latch_armed = value == 0x00;
+    }
+
+    /// Serializes the clock in the footer format shared by VBA and BGB: the live and latched
+    /// registers as little endian 32-bit words, followed by a 64-bit UNIX timestamp.
+    pub fn to_footer(&self, timestamp: u64) -> [u8; FOOTER_SIZE] {
+        let mut footer = [0; FOOTER_SIZE];
+
+        let words = self.registers().into_iter().chain(self.latched);
+        for (i, value) in words.enumerate() {
+            footer[i * 4..i * 4 + 4].copy_from_slice(&(value as u32).to_le_bytes());
+        }
+
+        footer[40..48].copy_from_slice(&timestamp.to_le_bytes());
+
+        footer
+    }
+
+    /// Restores the clock from a save footer and catches up with the time that passed since
+    /// `timestamp`. Some emulators write a 44 byte footer with a 32-bit timestamp instead.
+    pub fn load_footer(&mut self, footer: &[u8], now: u64) {
+        if footer.len() < 44 {
+            return;
+        }
+
+        let word = |i: usize| {
+            let bytes = [
+                footer[i * 4],
+                footer[i * 4 + 1],
+                footer[i * 4 + 2],
+                footer[i * 4 + 3],
+            ];
+            u32::from_le_bytes(bytes)
+        };
+
+        self.set_registers([
+            word(0) as u8,
+            word(1) as u8,
+            word(2) as u8,
+            word(3) as u8,
+            word(4) as u8,
+        ]);
+
+        for i in 0..5 {
+            self.latched[i] = word(5 + i) as u8;
+        }
+
+        let timestamp = if footer.len() >= FOOTER_SIZE {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&footer[40..48]);
+            u64::from_le_bytes(bytes)
+        } else {
+            word(10) as u64
+        };
+
+        self.advance(now.saturating_sub(timestamp));
+    }
+}
@@ -1 +1,3 @@
 pub mod header;
+pub mod mbc;
+pub mod save;
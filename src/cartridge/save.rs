@@ -0,0 +1,110 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::mbc::Mapper;
+
+/// Battery backed cartridge RAM stored next to the ROM as a raw `.sav` dump, the same format
+/// most other emulators use. Cartridges with a clock get its state appended as a footer.
+#[derive(Debug)]
+pub struct SaveFile {
+    path: PathBuf,
+    // The part of what's on disk which counts as a change, see `SaveFile::changes`. None until
+    // there's something on disk
+    last_saved: Option<Vec<u8>>,
+}
+
+impl SaveFile {
+    /// The save for `game.gb` is `game.sav`, either next to the ROM or in `save_dir`.
+    pub fn new(rom_path: &Path, save_dir: Option<&Path>) -> Self {
+        let path = match (save_dir, rom_path.file_stem()) {
+            (Some(dir), Some(stem)) => dir.join(format!("{}.sav", stem.to_string_lossy())),
+            _ => rom_path.with_extension("sav"),
+        };
+
+        Self {
+            path,
+            last_saved: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Copies an existing save into the cartridge RAM. A missing file isn't an error since
+    /// that's what a fresh game looks like.
+    pub fn load(&mut self, mapper: &mut dyn Mapper) -> io::Result<()> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let ram = mapper.ram_mut();
+        let length = ram.len().min(data.len());
+        ram[..length].copy_from_slice(&data[..length]);
+
+        if let Some(rtc) = mapper.rtc_mut() {
+            rtc.load_footer(&data[length..], SaveFile::now());
+        }
+
+        self.last_saved = Some(SaveFile::changes(mapper));
+
+        Ok(())
+    }
+
+    /// Writes the cartridge RAM to disk if it changed since the last flush. A running clock
+    /// alone doesn't count as a change.
+    pub fn flush(&mut self, mapper: &dyn Mapper) -> io::Result<()> {
+        if mapper.ram().is_empty() && mapper.rtc().is_none() {
+            return Ok(());
+        }
+
+        let changes = SaveFile::changes(mapper);
+        if self.last_saved.as_ref() == Some(&changes) {
+            return Ok(());
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(&self.path, SaveFile::contents(mapper, SaveFile::now()))?;
+        self.last_saved = Some(changes);
+
+        Ok(())
+    }
+
+    // The cartridge RAM, followed by the clock footer if there's a clock
+    fn contents(mapper: &dyn Mapper, timestamp: u64) -> Vec<u8> {
+        let mut data = mapper.ram().to_vec();
+
+        if let Some(rtc) = mapper.rtc() {
+            data.extend_from_slice(&rtc.to_footer(timestamp));
+        }
+
+        data
+    }
+
+    // The cartridge RAM, and the clock while it's halted. A running clock changes every second,
+    // but it's worked out again from the footer's timestamp on load, so rewriting the save for it
+    // gains nothing
+    fn changes(mapper: &dyn Mapper) -> Vec<u8> {
+        let mut data = mapper.ram().to_vec();
+
+        if let Some(rtc) = mapper.rtc().filter(|rtc| rtc.halted()) {
+            data.extend_from_slice(&rtc.registers());
+        }
+
+        data
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
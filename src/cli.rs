@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+
+use emulator::boot::Model;
+use emulator::state::SLOTS;
+
+const USAGE: &str = "Usage: emulator [options] <rom_path>
+       emulator disasm <rom_path> [start] [end]
+
+Options:
+    --save-dir <dir>         Directory for battery backed saves, defaults to the ROM's directory
+    --headless               Run without a display
+    --frames <n>             Number of frames to run in headless mode (default: 60)
+    --screenshot <file>      Save the last frame as a PNG when running headless
+    --trace <file>           Log the CPU state before every instruction, in Gameboy Doctor's format
+    --trace-labels           Label the traced instructions which are in the ROM's .sym file
+    --doctor                 Make LY always read $90 like Gameboy Doctor expects, to compare traces
+    --debug                  Start in the interactive debugger, type `help` for its commands
+    --gdb <port>             Wait for a GDB remote debugger to connect on localhost:port
+    --load-state <slot>      Start from the save state in a slot (0-9)
+    --save-state <slot>      Save the state to a slot (0-9) when the emulator stops
+    --record <file>          Record the buttons held every frame of a headless run into a movie
+    --movie <file>           Play a movie back headlessly, from power on or its save state, as the
+                             model it was recorded on
+    --boot-rom <file>        Run a DMG (256 bytes) or CGB (2304 bytes) boot ROM before the cartridge
+    --model <model>          Hardware to emulate: dmg0, dmg, mgb, sgb or cgb. Defaults to cgb with a
+                             CGB boot ROM and dmg otherwise
+
+Disassembly:
+    start and end are hexadecimal offsets into the ROM, with end excluded. By default the listing
+    covers the ROM bank containing start.";
+
+const DEFAULT_HEADLESS_FRAMES: u64 = 60;
+
+#[derive(Debug)]
+pub enum Command {
+    Run(Options),
+    Disassemble {
+        rom_path: PathBuf,
+        start: Option<usize>,
+        end: Option<usize>,
+    },
+}
+
+impl Command {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+        let args: Vec<String> = args.into_iter().collect();
+
+        if args.get(1).map(String::as_str) != Some("disasm") {
+            return Options::parse(args).map(Command::Run);
+        }
+
+        let mut args = args.into_iter().skip(2);
+        let rom_path = args.next().ok_or(USAGE)?;
+        let start = args.next().map(|arg| Command::address(&arg)).transpose()?;
+        let end = args.next().map(|arg| Command::address(&arg)).transpose()?;
+
+        if let Some(arg) = args.next() {
+            return Err(format!("Unexpected argument: {}\n{}", arg, USAGE));
+        }
+
+        Ok(Command::Disassemble {
+            rom_path: PathBuf::from(rom_path),
+            start,
+            end,
+        })
+    }
+
+    // Addresses are in hexadecimal, optionally prefixed with 0x or $
+    fn address(arg: &str) -> Result<usize, String> {
+        let digits = arg
+            .strip_prefix("0x")
+            .or_else(|| arg.strip_prefix('$'))
+            .unwrap_or(arg);
+
+        usize::from_str_radix(digits, 16)
+            .map_err(|_| format!("Invalid address: {}\n{}", arg, USAGE))
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub rom_path: PathBuf,
+    // Where battery backed saves go, defaults to the directory of the ROM
+    pub save_dir: Option<PathBuf>,
+    pub headless: bool,
+    pub frames: u64,
+    pub screenshot: Option<PathBuf>,
+    pub trace: Option<PathBuf>,
+    // Gameboy Doctor doesn't expect labels, so they're only added when asked for
+    pub trace_labels: bool,
+    pub doctor: bool,
+    pub debug: bool,
+    pub gdb: Option<u16>,
+    // Save state slots, states are stored next to battery saves
+    pub load_state: Option<u8>,
+    pub save_state: Option<u8>,
+    // Movies are recorded and played back without battery saves, so they always start the same
+    pub record: Option<PathBuf>,
+    pub movie: Option<PathBuf>,
+    pub boot_rom: Option<PathBuf>,
+    // The state left behind by this model's boot ROM is set up when there's no boot ROM to run.
+    // None unless given, since the default depends on the boot ROM
+    pub model: Option<Model>,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut args = args.into_iter().skip(1);
+        let mut rom_path = None;
+        let mut save_dir = None;
+        let mut headless = false;
+        let mut frames = DEFAULT_HEADLESS_FRAMES;
+        let mut screenshot = None;
+        let mut trace = None;
+        let mut trace_labels = false;
+        let mut doctor = false;
+        let mut debug = false;
+        let mut gdb = None;
+        let mut load_state = None;
+        let mut save_state = None;
+        let mut record = None;
+        let mut movie = None;
+        let mut boot_rom = None;
+        let mut model = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--save-dir" => save_dir = Some(PathBuf::from(Options::value(&mut args, &arg)?)),
+                "--headless" => headless = true,
+                "--frames" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    frames = value
+                        .parse()
+                        .map_err(|_| format!("Invalid frame count: {}\n{}", value, USAGE))?;
+                }
+                "--screenshot" => {
+                    screenshot = Some(PathBuf::from(Options::value(&mut args, &arg)?))
+                }
+                "--trace" => trace = Some(PathBuf::from(Options::value(&mut args, &arg)?)),
+                "--trace-labels" => trace_labels = true,
+                "--doctor" => doctor = true,
+                "--debug" => debug = true,
+                "--gdb" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    gdb = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid port: {}\n{}", value, USAGE))?,
+                    );
+                }
+                "--load-state" => load_state = Some(Options::slot(&mut args, &arg)?),
+                "--save-state" => save_state = Some(Options::slot(&mut args, &arg)?),
+                "--record" => record = Some(PathBuf::from(Options::value(&mut args, &arg)?)),
+                "--movie" => movie = Some(PathBuf::from(Options::value(&mut args, &arg)?)),
+                "--boot-rom" => boot_rom = Some(PathBuf::from(Options::value(&mut args, &arg)?)),
+                "--model" => {
+                    let value = Options::value(&mut args, &arg)?;
+                    model = Some(
+                        Model::parse(&value)
+                            .ok_or_else(|| format!("Invalid model: {}\n{}", value, USAGE))?,
+                    );
+                }
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                _ if arg.starts_with("--") => {
+                    return Err(format!("Unknown option: {}\n{}", arg, USAGE))
+                }
+                _ if rom_path.is_none() => rom_path = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument: {}\n{}", arg, USAGE)),
+            }
+        }
+
+        // Movies carry their own starting point
+        if movie.is_some() && (load_state.is_some() || record.is_some()) {
+            return Err(format!(
+                "--movie can't be combined with --load-state or --record\n{}",
+                USAGE
+            ));
+        }
+
+        Ok(Options {
+            rom_path: rom_path.ok_or(USAGE)?,
+            save_dir,
+            headless,
+            frames,
+            screenshot,
+            trace,
+            trace_labels,
+            doctor,
+            debug,
+            gdb,
+            load_state,
+            save_state,
+            record,
+            movie,
+            boot_rom,
+            model,
+        })
+    }
+
+    fn slot(args: &mut impl Iterator<Item = String>, option: &str) -> Result<u8, String> {
+        let value = Options::value(args, option)?;
+
+        match value.parse() {
+            Ok(slot) if slot < SLOTS => Ok(slot),
+            _ => Err(format!("Invalid save state slot: {}\n{}", value, USAGE)),
+        }
+    }
+
+    fn value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+        args.next()
+            .ok_or_else(|| format!("Missing value for {}\n{}", option, USAGE))
+    }
+}
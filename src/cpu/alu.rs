@@ -65,10 +65,10 @@ impl ALU {
 
     pub fn adc(&self, a: u8, b: u8, carry: u8) -> (u8, Flags) {
         // We will use wrapping_add here because we don't care about the overflow
-        let result = a.wrapping_add(b).wrapping_add(carry as u8);
+        let result = a.wrapping_add(b).wrapping_add(carry);
 
         let zero = result == 0;
-        let half_carry = (a & 0xF) + (b & 0xF) + (carry as u8) > 0xF;
+        let half_carry = (a & 0xF) + (b & 0xF) + carry > 0xF;
         let carry = (a as u16) + (b as u16) + (carry as u16) > 0xFF;
 
         (
@@ -84,10 +84,10 @@ impl ALU {
 
     pub fn sbc(&self, a: u8, b: u8, carry: u8) -> (u8, Flags) {
         // We will use wrapping_sub here because we don't care about the overflow
-        let result = a.wrapping_sub(b).wrapping_sub(carry as u8);
+        let result = a.wrapping_sub(b).wrapping_sub(carry);
 
         let zero = result == 0;
-        let half_carry = (a & 0xf).wrapping_sub(b & 0xf).wrapping_sub(carry as u8) & (0xf + 1) != 0;
+        let half_carry = (a & 0xf).wrapping_sub(b & 0xf).wrapping_sub(carry) & (0xf + 1) != 0;
         let carry = (a as u16) < (b as u16) + (carry as u16);
 
         (
@@ -117,7 +117,7 @@ impl ALU {
         )
     }
 
-    pub fn dec(&self, a: u8) -> (u8, Flags) {
+    pub fn dec(&self, a: u8, carry: u8) -> (u8, Flags) {
         let result = a.wrapping_sub(1);
         let zero = result == 0;
         let half_carry = a & 0xF == 0x0;
@@ -128,7 +128,7 @@ impl ALU {
                 zero,
                 subtract: true,
                 half_carry,
-                carry: false,
+                carry: carry == 1,
             },
         )
     }
@@ -151,7 +151,7 @@ impl ALU {
     }
 
     pub fn rr(&self, a: u8, carry: u8) -> (u8, Flags) {
-        let result = (a >> 1) | ((carry as u8) << 7);
+        let result = (a >> 1) | (carry << 7);
         let zero = result == 0;
         let half_carry = false;
         let carry = a & 0x01 == 0x01;
@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use super::instruction::{Immediate, Instruction};
+use crate::utils::traits::Storage;
+
+const PREFIX: u8 = 0xCB;
+
+/// Assembles SM83 source into a [`Program`](crate::cpu::assembler::Program), panicking if it
+/// doesn't assemble. Meant for tests, e.g. `asm!("ld a, $10\n add a, b\n halt")`.
+#[macro_export]
+macro_rules! asm {
+    ($source:expr) => {
+        match $crate::cpu::assembler::assemble($source) {
+            Ok(program) => program,
+            Err(error) => panic!("{}", error),
+        }
+    };
+}
+
+#[derive(Debug)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// Machine code along with the address it was assembled for.
+#[derive(Debug)]
+pub struct Program {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Program {
+    /// Copies the program into memory, starting at its origin.
+    pub fn load(&self, memory: &mut impl Storage<usize, u8>) {
+        for (offset, byte) in self.bytes.iter().enumerate() {
+            memory.write(self.origin as usize + offset, *byte);
+        }
+    }
+}
+
+// An opcode along with its operands as the disassembler writes them, e.g. `LD A,(a16)`
+#[derive(Debug)]
+struct Encoding {
+    opcode: Vec<u8>,
+    mnemonic: String,
+    operands: Vec<String>,
+    immediate: Option<Immediate>,
+    length: u16,
+    // JR's offset is written as the address it jumps to
+    relative: bool,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Instruction {
+        opcode: Vec<u8>,
+        length: u16,
+        operand: Option<(Immediate, String)>,
+        relative: bool,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+impl Statement {
+    fn length(&self) -> u16 {
+        match self {
+            Statement::Instruction { length, .. } => *length,
+            Statement::Bytes(items) => items
+                .iter()
+                .map(|item| string_literal(item).map_or(1, str::len) as u16)
+                .sum(),
+            Statement::Words(items) => 2 * items.len() as u16,
+        }
+    }
+
+    fn emit(
+        &self,
+        address: u16,
+        labels: &HashMap<String, u16>,
+        bytes: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        match self {
+            Statement::Instruction {
+                opcode,
+                length,
+                operand,
+                relative,
+            } => {
+                let start = bytes.len();
+                bytes.extend(opcode);
+
+                if let Some((immediate, expression)) = operand {
+                    let value = evaluate(expression, labels)?;
+
+                    match immediate {
+                        Immediate::N8 => bytes.push(byte(value)?),
+                        // The high byte is implied, so both $44 and $FF44 work
+                        Immediate::A8 => match value {
+                            0x00..=0xFF | 0xFF00..=0xFFFF => bytes.push(value as u8),
+                            _ => return Err(format!("Value out of range: {}", expression)),
+                        },
+                        Immediate::N16 | Immediate::A16 => bytes.extend(word(value)?.to_le_bytes()),
+                        Immediate::E8 => {
+                            let offset = match relative {
+                                true => value - (address as i32 + *length as i32),
+                                false => value,
+                            };
+
+                            if !(-128..=127).contains(&offset) {
+                                return Err(format!("Offset out of range: {}", expression));
+                            }
+
+                            bytes.push(offset as u8);
+                        }
+                    }
+                }
+
+                // STOP's padding byte
+                bytes.resize(start + *length as usize, 0);
+            }
+            Statement::Bytes(items) => {
+                for item in items {
+                    match string_literal(item) {
+                        Some(text) => bytes.extend(text.bytes()),
+                        None => bytes.push(byte(evaluate(item, labels)?)?),
+                    }
+                }
+            }
+            Statement::Words(items) => {
+                for item in items {
+                    bytes.extend(word(evaluate(item, labels)?)?.to_le_bytes());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Assembles SM83 source, written the way the disassembler prints it (`LD A,(HL+)`, `LDH A,(a8)`,
+/// `JR NZ,label`, ...). Mnemonics, registers and labels are case insensitive, and the usual
+/// shorthands like `[hl]`, `(hli)` or `cp b` are accepted too.
+///
+/// Besides instructions, lines can define `label:`s and use the `db`, `dw` and `org` directives.
+/// Numbers are decimal, or hexadecimal with a `$` or `0x` prefix, or binary with a `%` prefix.
+pub fn assemble(source: &str) -> Result<Program, AssemblyError> {
+    let encodings = encodings();
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut origin: Option<u16> = None;
+    let mut address: u32 = 0;
+
+    // Every statement has a known size, so the first pass lays them out and collects the labels
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |message| AssemblyError {
+            line: number,
+            message,
+        };
+        let mut text = strip_comment(line).trim();
+
+        if let Some((label, rest)) = split_label(text) {
+            if labels
+                .insert(label.to_uppercase(), address as u16)
+                .is_some()
+            {
+                return Err(error(format!("Duplicate label: {}", label)));
+            }
+
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic.to_uppercase(), split_operands(operands)),
+            None => (text.to_uppercase(), Vec::new()),
+        };
+
+        let statement = match mnemonic.as_str() {
+            "ORG" => {
+                let [operand] = operands.as_slice() else {
+                    return Err(error(format!("Expected an address: {}", text)));
+                };
+                let value = evaluate(operand, &labels).map_err(error)?;
+
+                // Anything already assembled can't be overwritten
+                if !(0..=0xFFFF).contains(&value) || (origin.is_some() && (value as u32) < address)
+                {
+                    return Err(error(format!("Invalid origin: {}", operand)));
+                }
+
+                address = value as u32;
+                continue;
+            }
+            "DB" | "DW" if operands.is_empty() => {
+                return Err(error(format!("Expected data: {}", text)));
+            }
+            "DB" => Statement::Bytes(operands.iter().map(|item| item.to_string()).collect()),
+            "DW" => Statement::Words(operands.iter().map(|item| item.to_string()).collect()),
+            _ => instruction(&encodings, &mnemonic, &operands)
+                .ok_or_else(|| error(format!("Unknown instruction: {}", text)))?,
+        };
+
+        let length = statement.length() as u32;
+
+        if address + length > 0x10000 {
+            return Err(error(
+                "Program doesn't fit in the address space".to_string(),
+            ));
+        }
+
+        origin.get_or_insert(address as u16);
+        statements.push((number, address as u16, statement));
+        address += length;
+    }
+
+    let origin = origin.unwrap_or(address as u16);
+    let mut bytes = Vec::new();
+
+    for (line, address, statement) in statements {
+        bytes.resize((address - origin) as usize, 0);
+        statement
+            .emit(address, &labels, &mut bytes)
+            .map_err(|message| AssemblyError { line, message })?;
+    }
+
+    Ok(Program { origin, bytes })
+}
+
+// Every defined opcode, in both tables, so the assembler stays the inverse of the decoder
+fn encodings() -> Vec<Encoding> {
+    let unprefixed =
+        (0..=u8::MAX).filter_map(|byte| Instruction::from_byte(byte).map(|i| (vec![byte], i)));
+    let prefixed = (0..=u8::MAX)
+        .filter_map(|byte| Instruction::from_byte_prefixed(byte).map(|i| (vec![PREFIX, byte], i)));
+
+    unprefixed
+        .chain(prefixed)
+        .filter(|(_, instruction)| !matches!(instruction, Instruction::PREFIXCB))
+        .map(|(opcode, instruction)| {
+            let text = instruction.to_string();
+            let (mnemonic, operands) = match text.split_once(' ') {
+                Some((mnemonic, operands)) => (mnemonic, operands.split(',').collect()),
+                None => (text.as_str(), Vec::new()),
+            };
+
+            Encoding {
+                opcode,
+                mnemonic: mnemonic.to_string(),
+                operands: operands.iter().map(|operand| operand.to_string()).collect(),
+                immediate: instruction.immediate(),
+                length: instruction.length(),
+                relative: matches!(instruction, Instruction::JR | Instruction::JRCC(_)),
+            }
+        })
+        .collect()
+}
+
+fn instruction(encodings: &[Encoding], mnemonic: &str, operands: &[&str]) -> Option<Statement> {
+    let (mnemonic, operands) = canonical(mnemonic, operands);
+
+    encodings
+        .iter()
+        .filter(|encoding| encoding.mnemonic == mnemonic)
+        .filter(|encoding| encoding.operands.len() == operands.len())
+        .filter_map(|encoding| {
+            let mut expression = None;
+
+            for (pattern, operand) in encoding.operands.iter().zip(&operands) {
+                if let Some(value) = match_operand(pattern, operand, encoding.immediate)? {
+                    expression = Some(value);
+                }
+            }
+
+            Some((encoding, expression))
+        })
+        // `LD A,(HL)` also fits `LD A,(a16)` with a label named HL, registers take precedence
+        .min_by_key(|(_, expression)| expression.is_some())
+        .map(|(encoding, expression)| Statement::Instruction {
+            opcode: encoding.opcode.clone(),
+            length: encoding.length,
+            operand: encoding
+                .immediate
+                .zip(expression.map(|expression| expression.to_string())),
+            relative: encoding.relative,
+        })
+}
+
+// Rewrites an instruction into the disassembler's notation, e.g. `cp [hl]` into `CP A,(HL)`
+fn canonical(mnemonic: &str, operands: &[&str]) -> (String, Vec<String>) {
+    let mut mnemonic = mnemonic.to_string();
+    let mut operands: Vec<String> = operands
+        .iter()
+        .map(|operand| normalize_operand(operand))
+        .collect();
+    let no_labels = HashMap::new();
+
+    match mnemonic.as_str() {
+        "ADD" | "ADC" | "SUB" | "SBC" | "AND" | "OR" | "XOR" | "CP" if operands.len() == 1 => {
+            operands.insert(0, "A".to_string());
+        }
+        "JP" if operands == ["(HL)"] => operands[0] = "HL".to_string(),
+        "LD" if operands.iter().any(|operand| operand == "(C)") => mnemonic = "LDH".to_string(),
+        "RST" => {
+            if let Some(Ok(target)) = operands.first().map(|o| evaluate(o, &no_labels)) {
+                operands[0] = format!("${:02X}", target);
+            }
+        }
+        "BIT" | "RES" | "SET" => {
+            if let Some(Ok(bit)) = operands.first().map(|o| evaluate(o, &no_labels)) {
+                operands[0] = bit.to_string();
+            }
+        }
+        _ => {}
+    }
+
+    (mnemonic, operands)
+}
+
+fn normalize_operand(operand: &str) -> String {
+    let operand: String = operand
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '[' => '(',
+            ']' => ')',
+            _ => c.to_ascii_uppercase(),
+        })
+        .collect();
+
+    match operand.as_str() {
+        "(HLI)" => "(HL+)".to_string(),
+        "(HLD)" => "(HL-)".to_string(),
+        "($FF00+C)" | "(0XFF00+C)" => "(C)".to_string(),
+        // Negative offsets are written `SP-$05` rather than `SP+-$05`
+        _ => match operand.strip_prefix("SP-") {
+            Some(offset) => format!("SP+-{}", offset),
+            None => operand,
+        },
+    }
+}
+
+// Returns None if the operand doesn't fit the pattern, or the expression filling in the
+// pattern's placeholder if it has one
+fn match_operand<'a>(
+    pattern: &str,
+    operand: &'a str,
+    immediate: Option<Immediate>,
+) -> Option<Option<&'a str>> {
+    let placeholder = immediate.map(Immediate::placeholder);
+
+    match placeholder.and_then(|placeholder| pattern.split_once(placeholder)) {
+        Some((prefix, suffix)) => {
+            let expression = operand.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            is_expression(expression).then_some(Some(expression))
+        }
+        None => (pattern == operand).then_some(None),
+    }
+}
+
+// Names which can't be used as labels, so `LD HL,SP+2` isn't mistaken for `LD HL,n16`
+const RESERVED: [&str; 15] = [
+    "A", "B", "C", "D", "E", "H", "L", "AF", "BC", "DE", "HL", "SP", "NZ", "Z", "NC",
+];
+
+// Splits an expression like `label+2` or `-$10` into its terms, along with whether they're negated
+fn terms(expression: &str) -> Vec<(bool, &str)> {
+    let mut terms = Vec::new();
+    let mut rest = expression.trim();
+
+    loop {
+        let (negative, term) = match rest.strip_prefix('-') {
+            Some(term) => (true, term),
+            None => (false, rest.strip_prefix('+').unwrap_or(rest)),
+        };
+        let end = term.find(['+', '-']).unwrap_or(term.len());
+
+        terms.push((negative, term[..end].trim()));
+        rest = &term[end..];
+
+        if rest.is_empty() {
+            return terms;
+        }
+    }
+}
+
+fn is_expression(expression: &str) -> bool {
+    terms(expression)
+        .iter()
+        .all(|(_, term)| is_label(term) || number(term).is_some())
+}
+
+fn evaluate(expression: &str, labels: &HashMap<String, u16>) -> Result<i32, String> {
+    terms(expression)
+        .into_iter()
+        .try_fold(0i32, |total, (negative, term)| {
+            let value = match is_label(term) {
+                true => labels
+                    .get(&term.to_uppercase())
+                    .map(|address| *address as i32)
+                    .ok_or_else(|| format!("Undefined label: {}", term))?,
+                false => number(term).ok_or_else(|| format!("Invalid number: {}", term))?,
+            };
+
+            Ok(match negative {
+                true => total.wrapping_sub(value),
+                false => total.wrapping_add(value),
+            })
+        })
+}
+
+fn number(term: &str) -> Option<i32> {
+    if let Some(digits) = term
+        .strip_prefix('$')
+        .or_else(|| term.strip_prefix("0x"))
+        .or_else(|| term.strip_prefix("0X"))
+    {
+        i32::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = term.strip_prefix('%') {
+        i32::from_str_radix(digits, 2).ok()
+    } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+        term.parse().ok()
+    } else {
+        None
+    }
+}
+
+// Negative values are stored in two's complement
+fn byte(value: i32) -> Result<u8, String> {
+    match value {
+        -0x80..=0xFF => Ok(value as u8),
+        _ => Err(format!("Value out of range: {}", value)),
+    }
+}
+
+fn word(value: i32) -> Result<u16, String> {
+    match value {
+        -0x8000..=0xFFFF => Ok(value as u16),
+        _ => Err(format!("Value out of range: {}", value)),
+    }
+}
+
+fn is_label(text: &str) -> bool {
+    is_identifier(text) && !RESERVED.contains(&text.to_uppercase().as_str())
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn string_literal(item: &str) -> Option<&str> {
+    item.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+// Labels are terminated by a colon (or two, for exported labels) and may share their line
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+
+    is_label(label).then(|| (label, rest.strip_prefix(':').unwrap_or(rest)))
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    operands.push(text[start..].trim());
+    operands
+}
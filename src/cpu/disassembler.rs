@@ -0,0 +1,94 @@
+use std::fmt::{self, Display};
+
+use super::instruction::{Immediate, Instruction};
+
+const PREFIX: u8 = 0xCB;
+
+/// An instruction decoded from memory, along with the bytes it's made of.
+#[derive(Debug)]
+pub struct Disassembly {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    // None for the opcodes which aren't defined
+    pub instruction: Option<Instruction>,
+    text: String,
+}
+
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Disassembly {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+}
+
+/// Decodes the instruction at `address`, with `read` fetching a byte from memory.
+pub fn disassemble(address: u16, read: impl FnMut(u16) -> u8) -> Disassembly {
+    disassemble_with(address, read, |_| None)
+}
+
+/// Like `disassemble`, but shows the addresses jumped to or accessed with the name `label`
+/// returns for them, if any.
+pub fn disassemble_with(
+    address: u16,
+    mut read: impl FnMut(u16) -> u8,
+    mut label: impl FnMut(u16) -> Option<String>,
+) -> Disassembly {
+    let opcode = read(address);
+
+    let instruction = match opcode {
+        PREFIX => Instruction::from_byte_prefixed(read(address.wrapping_add(1))),
+        _ => Instruction::from_byte(opcode),
+    };
+
+    let Some(instruction) = instruction else {
+        return Disassembly {
+            address,
+            bytes: vec![opcode],
+            instruction: None,
+            text: format!("DB ${:02X}", opcode),
+        };
+    };
+
+    let bytes: Vec<u8> = (0..instruction.length())
+        .map(|i| read(address.wrapping_add(i)))
+        .collect();
+
+    let operand = match instruction.immediate() {
+        Some(Immediate::N8) => format!("${:02X}", bytes[1]),
+        Some(Immediate::A8) => {
+            let target = 0xFF00 | bytes[1] as u16;
+            label(target).unwrap_or_else(|| format!("${:04X}", target))
+        }
+        Some(Immediate::N16) => format!("${:04X}", u16::from_le_bytes([bytes[1], bytes[2]])),
+        Some(Immediate::A16) => {
+            let target = u16::from_le_bytes([bytes[1], bytes[2]]);
+            label(target).unwrap_or_else(|| format!("${:04X}", target))
+        }
+        Some(Immediate::E8) => {
+            let offset = bytes[1] as i8;
+
+            match instruction {
+                // Relative jumps are shown with the address they land on
+                Instruction::JR | Instruction::JRCC(_) => {
+                    let target = address.wrapping_add(2).wrapping_add_signed(offset.into());
+                    label(target).unwrap_or_else(|| format!("${:04X}", target))
+                }
+                _ if offset < 0 => format!("-${:02X}", offset.unsigned_abs()),
+                _ => format!("${:02X}", offset),
+            }
+        }
+        None => String::new(),
+    };
+
+    Disassembly {
+        address,
+        text: instruction.format(&operand),
+        bytes,
+        instruction: Some(instruction),
+    }
+}
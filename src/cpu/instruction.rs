@@ -1,3 +1,5 @@
+use std::fmt::{self, Display};
+
 use super::registers::{Reg16, Reg8};
 
 #[derive(Debug)]
@@ -12,6 +14,8 @@ pub enum Condition {
 pub enum Instruction {
     // 8 bit loads
     LDRR(Reg8, Reg8),
+    // LD B,B does nothing, test ROMs use it as a software breakpoint
+    LDBB,
     LDRN(Reg8),
     LDRHL(Reg8),
     LDHLR(Reg8),
@@ -34,6 +38,7 @@ pub enum Instruction {
     LD16SP,
     LDNNSP,
     LDSPHL,
+    LDHLSPE,
     PUSH(Reg16),
     POP(Reg16),
 
@@ -143,7 +148,7 @@ impl Instruction {
             0x7c => Some(Instruction::LDRR(Reg8::A, Reg8::H)),
             0x7d => Some(Instruction::LDRR(Reg8::A, Reg8::L)),
             0x47 => Some(Instruction::LDRR(Reg8::B, Reg8::A)),
-            // 0x40 => self.ld_b_b(ctx),
+            0x40 => Some(Instruction::LDBB),
             0x41 => Some(Instruction::LDRR(Reg8::B, Reg8::C)),
             0x42 => Some(Instruction::LDRR(Reg8::B, Reg8::D)),
             0x43 => Some(Instruction::LDRR(Reg8::B, Reg8::E)),
@@ -218,8 +223,8 @@ impl Instruction {
             0x22 => Some(Instruction::LDHLINCA),
             0xf2 => Some(Instruction::LDHAC),
             0xe2 => Some(Instruction::LDHCA),
-            0xf0 => Some(Instruction::LDHNA),
-            0xe0 => Some(Instruction::LDHAN),
+            0xf0 => Some(Instruction::LDHAN),
+            0xe0 => Some(Instruction::LDHNA),
 
             // 16-bit loads
             0x01 => Some(Instruction::LD16NN(Reg16::BC)),
@@ -228,7 +233,7 @@ impl Instruction {
             0x31 => Some(Instruction::LD16SP),
             0x08 => Some(Instruction::LDNNSP),
             0xf9 => Some(Instruction::LDSPHL),
-            // 0xf8 => self.load16_hl_sp_e(ctx),
+            0xf8 => Some(Instruction::LDHLSPE),
             0xc5 => Some(Instruction::PUSH(Reg16::BC)),
             0xd5 => Some(Instruction::PUSH(Reg16::DE)),
             0xe5 => Some(Instruction::PUSH(Reg16::HL)),
@@ -393,10 +398,8 @@ impl Instruction {
             // Prefix CB
             0xcb => Some(Instruction::PREFIXCB),
 
-            // Undefined opcodes
-            0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd | _ => {
-                panic!("Undefined opcode: {:#04x}", byte)
-            }
+            // Undefined opcodes: 0xd3, 0xdb, 0xdd, 0xe3, 0xe4, 0xeb, 0xec, 0xed, 0xf4, 0xfc, 0xfd
+            _ => None,
         }
     }
 
@@ -661,3 +664,251 @@ impl Instruction {
         }
     }
 }
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Condition::NZ => "NZ",
+            Condition::Z => "Z",
+            Condition::NC => "NC",
+            Condition::C => "C",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// The kind of immediate operand following an opcode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Immediate {
+    // 8-bit value
+    N8,
+    // 16-bit value
+    N16,
+    // 16-bit address
+    A16,
+    // Offset into 0xFF00..=0xFFFF
+    A8,
+    // Signed offset
+    E8,
+}
+
+impl Immediate {
+    /// Name used for the operand in Pan Docs' instruction tables.
+    pub fn placeholder(self) -> &'static str {
+        match self {
+            Immediate::N8 => "n8",
+            Immediate::N16 => "n16",
+            Immediate::A16 => "a16",
+            Immediate::A8 => "a8",
+            Immediate::E8 => "e8",
+        }
+    }
+}
+
+impl Instruction {
+    /// The immediate operand which follows the opcode, if any.
+    pub fn immediate(&self) -> Option<Immediate> {
+        match self {
+            Instruction::LDRN(_)
+            | Instruction::LDHLN
+            | Instruction::ADDNN
+            | Instruction::ADCNN
+            | Instruction::SUBNN
+            | Instruction::SBCNN
+            | Instruction::ANDNN
+            | Instruction::ORNN
+            | Instruction::XORNN
+            | Instruction::CPNN => Some(Immediate::N8),
+            Instruction::LD16NN(_) | Instruction::LD16SP => Some(Immediate::N16),
+            Instruction::LDANN
+            | Instruction::LDNNA
+            | Instruction::LDNNSP
+            | Instruction::JP
+            | Instruction::JPCC(_)
+            | Instruction::CALL
+            | Instruction::CALLCC(_) => Some(Immediate::A16),
+            Instruction::LDHAN | Instruction::LDHNA => Some(Immediate::A8),
+            Instruction::JR | Instruction::JRCC(_) | Instruction::ADDSPE | Instruction::LDHLSPE => {
+                Some(Immediate::E8)
+            }
+            _ => None,
+        }
+    }
+
+    /// Size of the instruction in bytes, including the opcode and its prefix.
+    pub fn length(&self) -> u16 {
+        match self.immediate() {
+            Some(Immediate::N16 | Immediate::A16) => 3,
+            Some(_) => 2,
+            // STOP is followed by a padding byte
+            None if self.is_prefixed() || matches!(self, Instruction::STOP) => 2,
+            None => 1,
+        }
+    }
+
+    // Whether the instruction comes from the 0xCB prefixed table
+    fn is_prefixed(&self) -> bool {
+        matches!(
+            self,
+            Instruction::PREFIXCB
+                | Instruction::RLC(_)
+                | Instruction::RLCHL
+                | Instruction::RRC(_)
+                | Instruction::RRCHL
+                | Instruction::RL(_)
+                | Instruction::RLHL
+                | Instruction::RR(_)
+                | Instruction::RRHL
+                | Instruction::SLA(_)
+                | Instruction::SLAHL
+                | Instruction::SRA(_)
+                | Instruction::SRAHL
+                | Instruction::SWAP(_)
+                | Instruction::SWAPHL
+                | Instruction::SRL(_)
+                | Instruction::SRLHL
+                | Instruction::BIT(..)
+                | Instruction::BITHL(_)
+                | Instruction::SET(..)
+                | Instruction::SETHL(_)
+                | Instruction::RESET(..)
+                | Instruction::RESETHL(_)
+        )
+    }
+
+    /// The instruction in assembly syntax, with `operand` standing in for its immediate operand.
+    pub fn format(&self, operand: &str) -> String {
+        match self {
+            // 8-bit loads
+            Instruction::LDRR(target, source) => format!("LD {},{}", target, source),
+            Instruction::LDBB => "LD B,B".to_string(),
+            Instruction::LDRN(target) => format!("LD {},{}", target, operand),
+            Instruction::LDRHL(target) => format!("LD {},(HL)", target),
+            Instruction::LDHLR(source) => format!("LD (HL),{}", source),
+            Instruction::LDHLN => format!("LD (HL),{}", operand),
+            Instruction::LDA16(source) => format!("LD A,({})", source),
+            Instruction::LD16A(target) => format!("LD ({}),A", target),
+            Instruction::LDANN => format!("LD A,({})", operand),
+            Instruction::LDNNA => format!("LD ({}),A", operand),
+            Instruction::LDHAC => "LDH A,(C)".to_string(),
+            Instruction::LDHCA => "LDH (C),A".to_string(),
+            Instruction::LDHAN => format!("LDH A,({})", operand),
+            Instruction::LDHNA => format!("LDH ({}),A", operand),
+            Instruction::LDAHLDEC => "LD A,(HL-)".to_string(),
+            Instruction::LDHLDECA => "LD (HL-),A".to_string(),
+            Instruction::LDAHLINC => "LD A,(HL+)".to_string(),
+            Instruction::LDHLINCA => "LD (HL+),A".to_string(),
+
+            // 16-bit loads
+            Instruction::LD16NN(target) => format!("LD {},{}", target, operand),
+            Instruction::LD16SP => format!("LD SP,{}", operand),
+            Instruction::LDNNSP => format!("LD ({}),SP", operand),
+            Instruction::LDSPHL => "LD SP,HL".to_string(),
+            Instruction::LDHLSPE if operand.starts_with('-') => format!("LD HL,SP{}", operand),
+            Instruction::LDHLSPE => format!("LD HL,SP+{}", operand),
+            Instruction::PUSH(source) => format!("PUSH {}", source),
+            Instruction::POP(target) => format!("POP {}", target),
+
+            // 8-bit arithmetic
+            Instruction::ADD(source) => format!("ADD A,{}", source),
+            Instruction::ADDHL => "ADD A,(HL)".to_string(),
+            Instruction::ADDNN => format!("ADD A,{}", operand),
+            Instruction::ADC(source) => format!("ADC A,{}", source),
+            Instruction::ADCHL => "ADC A,(HL)".to_string(),
+            Instruction::ADCNN => format!("ADC A,{}", operand),
+            Instruction::SUB(source) => format!("SUB A,{}", source),
+            Instruction::SUBHL => "SUB A,(HL)".to_string(),
+            Instruction::SUBNN => format!("SUB A,{}", operand),
+            Instruction::SBC(source) => format!("SBC A,{}", source),
+            Instruction::SBCHL => "SBC A,(HL)".to_string(),
+            Instruction::SBCNN => format!("SBC A,{}", operand),
+            Instruction::AND(source) => format!("AND A,{}", source),
+            Instruction::ANDHL => "AND A,(HL)".to_string(),
+            Instruction::ANDNN => format!("AND A,{}", operand),
+            Instruction::OR(source) => format!("OR A,{}", source),
+            Instruction::ORHL => "OR A,(HL)".to_string(),
+            Instruction::ORNN => format!("OR A,{}", operand),
+            Instruction::XOR(source) => format!("XOR A,{}", source),
+            Instruction::XORHL => "XOR A,(HL)".to_string(),
+            Instruction::XORNN => format!("XOR A,{}", operand),
+            Instruction::CP(source) => format!("CP A,{}", source),
+            Instruction::CPHL => "CP A,(HL)".to_string(),
+            Instruction::CPNN => format!("CP A,{}", operand),
+            Instruction::INC(target) => format!("INC {}", target),
+            Instruction::INCHL => "INC (HL)".to_string(),
+            Instruction::DEC(target) => format!("DEC {}", target),
+            Instruction::DECHL => "DEC (HL)".to_string(),
+            Instruction::RRA => "RRA".to_string(),
+            Instruction::RLA => "RLA".to_string(),
+            Instruction::RRCA => "RRCA".to_string(),
+            Instruction::RLCA => "RLCA".to_string(),
+
+            // Prefixed rotations, shifts and bit operations
+            Instruction::RR(target) => format!("RR {}", target),
+            Instruction::RL(target) => format!("RL {}", target),
+            Instruction::RLC(target) => format!("RLC {}", target),
+            Instruction::RRC(target) => format!("RRC {}", target),
+            Instruction::RLHL => "RL (HL)".to_string(),
+            Instruction::RRHL => "RR (HL)".to_string(),
+            Instruction::RLCHL => "RLC (HL)".to_string(),
+            Instruction::RRCHL => "RRC (HL)".to_string(),
+            Instruction::BIT(bit, target) => format!("BIT {},{}", bit, target),
+            Instruction::BITHL(bit) => format!("BIT {},(HL)", bit),
+            Instruction::SET(bit, target) => format!("SET {},{}", bit, target),
+            Instruction::SETHL(bit) => format!("SET {},(HL)", bit),
+            Instruction::RESET(bit, target) => format!("RES {},{}", bit, target),
+            Instruction::RESETHL(bit) => format!("RES {},(HL)", bit),
+            Instruction::SWAP(target) => format!("SWAP {}", target),
+            Instruction::SWAPHL => "SWAP (HL)".to_string(),
+            Instruction::SRL(target) => format!("SRL {}", target),
+            Instruction::SRLHL => "SRL (HL)".to_string(),
+            Instruction::SRA(target) => format!("SRA {}", target),
+            Instruction::SRAHL => "SRA (HL)".to_string(),
+            Instruction::SLA(target) => format!("SLA {}", target),
+            Instruction::SLAHL => "SLA (HL)".to_string(),
+
+            // Control flow
+            Instruction::CALL => format!("CALL {}", operand),
+            Instruction::CALLCC(condition) => format!("CALL {},{}", condition, operand),
+            Instruction::JP => format!("JP {}", operand),
+            Instruction::JPCC(condition) => format!("JP {},{}", condition, operand),
+            Instruction::JPHL => "JP HL".to_string(),
+            Instruction::JR => format!("JR {}", operand),
+            Instruction::JRCC(condition) => format!("JR {},{}", condition, operand),
+            Instruction::RET => "RET".to_string(),
+            Instruction::RETCC(condition) => format!("RET {}", condition),
+            Instruction::RETI => "RETI".to_string(),
+            Instruction::RST(target) => format!("RST ${:02X}", target),
+
+            // 16-bit arithmetic
+            Instruction::ADDHLR16(source) => format!("ADD HL,{}", source),
+            Instruction::ADDHLRSP => "ADD HL,SP".to_string(),
+            Instruction::ADDSPE => format!("ADD SP,{}", operand),
+            Instruction::DEC16(target) => format!("DEC {}", target),
+            Instruction::INC16(target) => format!("INC {}", target),
+            Instruction::DEC16SP => "DEC SP".to_string(),
+            Instruction::INC16SP => "INC SP".to_string(),
+
+            // Misc
+            Instruction::CPL => "CPL".to_string(),
+            Instruction::CCF => "CCF".to_string(),
+            Instruction::SCF => "SCF".to_string(),
+            Instruction::DAA => "DAA".to_string(),
+            Instruction::DI => "DI".to_string(),
+            Instruction::EI => "EI".to_string(),
+            Instruction::HALT => "HALT".to_string(),
+            Instruction::STOP => "STOP".to_string(),
+            Instruction::NOP => "NOP".to_string(),
+            Instruction::PREFIXCB => "PREFIX CB".to_string(),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let placeholder = self.immediate().map_or("", Immediate::placeholder);
+
+        f.write_str(&self.format(placeholder))
+    }
+}
@@ -0,0 +1,54 @@
+// The interrupt sources are described here:
+// https://gbdev.io/pandocs/Interrupt_Sources.html
+pub const INTERRUPT_FLAG: u16 = 0xFF0F;
+pub const INTERRUPT_ENABLE: u16 = 0xFFFF;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    // Ordered from highest to lowest priority
+    const PRIORITY: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    /// The bit corresponding to this interrupt in the IF and IE registers.
+    pub fn mask(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0b0000_0001,
+            Interrupt::Stat => 0b0000_0010,
+            Interrupt::Timer => 0b0000_0100,
+            Interrupt::Serial => 0b0000_1000,
+            Interrupt::Joypad => 0b0001_0000,
+        }
+    }
+
+    /// The address the CPU jumps to when servicing this interrupt.
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x40,
+            Interrupt::Stat => 0x48,
+            Interrupt::Timer => 0x50,
+            Interrupt::Serial => 0x58,
+            Interrupt::Joypad => 0x60,
+        }
+    }
+
+    /// Picks the interrupt that gets serviced first out of a set of pending requests, where
+    /// lower bits take precedence over higher ones.
+    pub fn highest_priority(pending: u8) -> Option<Interrupt> {
+        Interrupt::PRIORITY
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+}
@@ -1,73 +1,467 @@
 pub mod alu;
+pub mod assembler;
+pub mod disassembler;
 pub mod instruction;
+pub mod interrupts;
 pub mod registers;
+pub mod trace;
+
+use std::io;
 
 use self::alu::ALU;
 use self::instruction::{Condition, Instruction};
+use self::interrupts::Interrupt;
 use self::registers::{Flags, Reg16, Reg8, Registers};
-use crate::memory::bus::MemoryBus;
+use self::trace::Trace;
+use crate::joypad::Button;
+use crate::memory::bus::{Bus, MemoryBus};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
 use crate::utils::traits::Storage;
 
 #[derive(Debug)]
 pub enum Mode {
     Halted,
+    Stopped,
     Running,
     InterruptDispatch,
+    // Undefined opcodes hang the CPU until it's powered off, see
+    // https://gbdev.io/pandocs/CPU_Instruction_Set.html
+    Locked(u8),
+}
+
+/// A memory address watched for reads, writes or both.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub read: bool,
+    pub write: bool,
 }
 
+/// A memory access made by the CPU, along with the value read or written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+const JOYPAD: u16 = 0xFF00;
+const DIVIDER: u16 = 0xFF04;
+
+/// Number of T-cycles the LCD needs to draw a single frame.
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
 #[derive(Debug)]
-pub struct CPU<'a> {
+pub struct CPU<'a, B: Bus = MemoryBus> {
     ime: bool,
+    // EI only takes effect after the instruction following it, this counts down to that point
+    ime_delay: u8,
+    // Set when HALT is executed with IME disabled and an interrupt already pending, in which case
+    // the CPU fails to increment PC after fetching the next opcode.
+    halt_bug: bool,
     alu: ALU,
     registers: Registers,
-    bus: &'a mut MemoryBus,
+    bus: &'a mut B,
     mode: Mode,
+
+    // Whether LD B,B acts as a breakpoint, and whether one was hit since the last check
+    software_breakpoints: bool,
+    breakpoint_hit: bool,
+
+    // Accesses to these addresses are recorded until the next check
+    watchpoints: Vec<Watchpoint>,
+    watchpoint_hit: Option<Access>,
+
+    trace: Option<Trace>,
+
+    // Total number of T-cycles executed since power on
+    cycles: u64,
+}
+
+// Breakpoints, watchpoints and the trace are set up by the debugging tools and stay as they are
+impl<B: Bus + Snapshot> Snapshot for CPU<'_, B> {
+    fn save(&self, state: &mut StateWriter) {
+        self.registers.save(state);
+        state.write_bool(self.ime);
+        state.write_u8(self.ime_delay);
+        state.write_bool(self.halt_bug);
+
+        let (mode, opcode) = match self.mode {
+            Mode::Halted => (0, 0),
+            Mode::Stopped => (1, 0),
+            Mode::Running => (2, 0),
+            Mode::InterruptDispatch => (3, 0),
+            Mode::Locked(opcode) => (4, opcode),
+        };
+        state.write_u8(mode);
+        state.write_u8(opcode);
+
+        state.write_u64(self.cycles);
+        self.bus.save(state);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load(state)?;
+        self.ime = state.read_bool()?;
+        self.ime_delay = state.read_u8()?;
+        self.halt_bug = state.read_bool()?;
+
+        let (mode, opcode) = (state.read_u8()?, state.read_u8()?);
+        self.mode = match mode {
+            0 => Mode::Halted,
+            1 => Mode::Stopped,
+            2 => Mode::Running,
+            3 => Mode::InterruptDispatch,
+            4 => Mode::Locked(opcode),
+            _ => return Err(StateError::InvalidValue("CPU mode")),
+        };
+
+        self.cycles = state.read_u64()?;
+        self.bus.load(state)
+    }
+}
+
+impl<B: Bus + Snapshot> CPU<'_, B> {
+    /// Serializes the whole machine into a save state for the ROM with the given global
+    /// checksum, as found in its header.
+    pub fn save_state(&self, checksum: [u8; 2]) -> Vec<u8> {
+        let mut state = StateWriter::new(checksum);
+        self.save(&mut state);
+
+        state.finish()
+    }
+
+    /// Restores a save state, which has to belong to the ROM with the given global checksum.
+    /// The machine is left untouched if the state can't be loaded.
+    pub fn load_state(&mut self, data: &[u8], checksum: [u8; 2]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data, checksum)?;
+        let backup = self.save_state(checksum);
+
+        let result = self.load(&mut state).and_then(|_| state.finish());
+        if result.is_err() {
+            let mut state = StateReader::new(&backup, checksum)?;
+            self.load(&mut state)?;
+        }
+
+        result
+    }
 }
 
 impl CPU<'_> {
-    pub fn new<'a>(bus: &'a mut MemoryBus) -> CPU<'a> {
+    /// Presses or releases one of the buttons, which is how frontends drive the input.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.bus.set_button(button, pressed);
+    }
+
+    /// Sets every button at once, from a mask as returned by `Joypad::held`.
+    pub fn set_held(&mut self, held: u8) {
+        self.bus.set_held(held);
+    }
+}
+
+impl<B: Bus> CPU<'_, B> {
+    pub fn new(bus: &mut B) -> CPU<'_, B> {
         CPU {
             bus,
             ime: false,
+            ime_delay: 0,
+            halt_bug: false,
             alu: ALU {},
             registers: Registers::new(),
             mode: Mode::Running,
+            software_breakpoints: false,
+            breakpoint_hit: false,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            trace: None,
+            cycles: 0,
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn bus(&mut self) -> &mut B {
+        self.bus
+    }
+
+    pub fn registers(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    /// The interrupt master enable flag.
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn set_ime(&mut self, ime: bool) {
+        self.ime = ime;
+        self.ime_delay = 0;
+    }
+
+    /// Whether an EI is waiting to take effect after the current instruction.
+    pub fn ime_pending(&self) -> bool {
+        self.ime_delay > 0
+    }
+
+    /// Makes `LD B,B` act as a breakpoint, which test suites like mooneye-gb use to signal that
+    /// they're done.
+    pub fn set_software_breakpoints(&mut self, enabled: bool) {
+        self.software_breakpoints = enabled;
+    }
+
+    /// Logs the state of the CPU before every instruction it executes.
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    /// Writes out the part of the trace which is still buffered, if there's a trace.
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match self.trace.as_mut() {
+            Some(trace) => trace.flush(),
+            None => Ok(()),
         }
     }
 
-    fn check_interrupt_requests(&mut self) -> u8 {
-        let interrupt_requests: u8 = self.bus.read(0xFF0F as usize);
-        let interrupt_enable: u8 = self.bus.read(0xFFFF as usize);
+    /// Whether a software breakpoint was hit since the last call.
+    pub fn take_breakpoint(&mut self) -> bool {
+        std::mem::take(&mut self.breakpoint_hit)
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Watches an address, replacing any existing watchpoint on it.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.remove_watchpoint(watchpoint.address);
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Returns whether there was a watchpoint on the address.
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.address != address);
+
+        self.watchpoints.len() != count
+    }
 
-        interrupt_requests & interrupt_enable & 0x1F
+    /// The first watched access since the last call, if any.
+    pub fn take_watchpoint(&mut self) -> Option<Access> {
+        self.watchpoint_hit.take()
     }
 
-    pub fn step(&mut self) {
-        let opcode = self.registers.pc.read(&mut self.bus) as u8;
+    /// Executes a single instruction and returns the number of T-cycles it took.
+    /// Every instruction takes a multiple of 4 T-cycles (one M-cycle).
+    pub fn step(&mut self) -> u32 {
+        let start = self.cycles;
+
+        if let Mode::Locked(_) = self.mode {
+            self.idle();
+
+            return (self.cycles - start) as u32;
+        }
+
+        // Only a button press gets the CPU out of STOP, other interrupts wait until then
+        let stopped = matches!(self.mode, Mode::Stopped);
+        if self.ime && !stopped && self.bus.pending_interrupts() != 0 {
+            self.mode = Mode::InterruptDispatch;
+        }
 
         match self.mode {
             Mode::Halted => {
-                let interrupt_requests = self.check_interrupt_requests();
-                if interrupt_requests != 0 {
-                    self.mode = Mode::InterruptDispatch;
+                // The CPU keeps ticking while halted, waiting for an interrupt
+                self.idle();
+
+                if self.bus.pending_interrupts() != 0 {
+                    self.mode = if self.ime {
+                        Mode::InterruptDispatch
+                    } else {
+                        Mode::Running
+                    };
                 }
-                return;
+
+                return (self.cycles - start) as u32;
             }
-            Mode::Running => (),
-            Mode::InterruptDispatch => unimplemented!(),
+            Mode::Stopped => {
+                self.idle();
+
+                // Pressing any button pulls one of the selected P1 input lines low
+                let joypad: u8 = self.bus.read(JOYPAD as usize);
+                if joypad & 0x0F != 0x0F {
+                    self.mode = Mode::Running;
+                }
+
+                return (self.cycles - start) as u32;
+            }
+            Mode::InterruptDispatch => {
+                self.dispatch_interrupt();
+
+                return (self.cycles - start) as u32;
+            }
+            Mode::Running | Mode::Locked(_) => (),
+        }
+
+        if self.trace.is_some() {
+            self.log_state();
+        }
+
+        let opcode = self.fetch_byte();
+
+        match Instruction::from_byte(opcode) {
+            Some(instruction) => self.execute(instruction),
+            None => self.mode = Mode::Locked(opcode),
+        }
+
+        if self.ime_delay > 0 {
+            self.ime_delay -= 1;
+
+            if self.ime_delay == 0 {
+                self.ime = true;
+            }
+        }
+
+        (self.cycles - start) as u32
+    }
+
+    /// Runs instructions until the cycle counter reaches `target`. Since instructions can't be
+    /// interrupted halfway through, this may overshoot by a few cycles.
+    pub fn run_until(&mut self, target: u64) {
+        while self.cycles < target {
+            self.step();
+        }
+    }
+
+    /// Runs for one frame worth of cycles and returns the number of T-cycles executed.
+    pub fn run_frame(&mut self) -> u64 {
+        let start = self.cycles;
+        self.run_until(start + CYCLES_PER_FRAME);
+
+        self.cycles - start
+    }
+
+    // Every memory access takes one M-cycle, so all of the CPU's bus traffic goes through the
+    // following functions to keep track of time.
+    fn idle(&mut self) {
+        self.cycles += 4;
+        self.bus.tick(4);
+    }
+
+    fn read_byte(&mut self, addr: u16) -> u8 {
+        self.idle();
+        let value = self.bus.read(addr as usize);
+
+        if !self.watchpoints.is_empty() {
+            self.watch(Access::Read(addr, value));
+        }
+
+        value
+    }
+
+    fn write_byte(&mut self, addr: u16, value: u8) {
+        self.idle();
+        self.bus.write(addr as usize, value);
+
+        if !self.watchpoints.is_empty() {
+            self.watch(Access::Write(addr, value));
+        }
+    }
+
+    fn watch(&mut self, access: Access) {
+        let watched = self.watchpoints.iter().any(|watchpoint| match access {
+            Access::Read(addr, _) => watchpoint.read && watchpoint.address == addr,
+            Access::Write(addr, _) => watchpoint.write && watchpoint.address == addr,
+        });
+
+        if watched && self.watchpoint_hit.is_none() {
+            self.watchpoint_hit = Some(access);
         }
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let data = self.read_byte(self.registers.pc.pointer.0);
 
-        if let Some(instruction) = Instruction::from_byte(opcode) {
-            dbg!(
-                "{:#X}: {:#X} {:?}",
-                self.registers.pc.pointer.0,
-                opcode,
-                &instruction
-            );
-            self.execute(instruction);
+        if self.halt_bug {
+            self.halt_bug = false;
         } else {
-            panic!("Invalid opcode: {:#X}", opcode);
-        };
+            self.registers.pc.pointer += 1;
+        }
+
+        data
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let lower = self.fetch_byte();
+        let upper = self.fetch_byte();
+
+        u16::from_le_bytes([lower, upper])
+    }
+
+    fn log_state(&mut self) {
+        let [a, f] = self.registers.read(Reg16::AF).to_be_bytes();
+        let registers = [
+            a,
+            f,
+            self.registers.read(Reg8::B),
+            self.registers.read(Reg8::C),
+            self.registers.read(Reg8::D),
+            self.registers.read(Reg8::E),
+            self.registers.read(Reg8::H),
+            self.registers.read(Reg8::L),
+        ];
+
+        // Peeking at memory doesn't take any time
+        let pc = self.registers.pc.pointer.0;
+        let memory = [0, 1, 2, 3].map(|i| self.bus.read(pc.wrapping_add(i) as usize));
+
+        let bank = self.bus.rom_bank(pc);
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace.log(registers, self.registers.sp.pointer.0, pc, bank, memory);
+        }
+    }
+
+    // See https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
+    fn dispatch_interrupt(&mut self) {
+        self.ime = false;
+        self.mode = Mode::Running;
+
+        // With `EI; HALT` and an interrupt pending, the handler returns to the HALT itself
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.registers.pc.pointer -= 1;
+        }
+
+        // Two wait states, then the program counter is pushed one byte at a time
+        self.idle();
+        self.idle();
+
+        let [lower, upper] = self.registers.pc.pointer.0.to_le_bytes();
+
+        self.registers.sp.pointer -= 1;
+        self.write_byte(self.registers.sp.pointer.0, upper);
+
+        // The interrupt to service is only picked after the upper byte has been pushed, so a push
+        // that overwrites IE can change it or cancel the dispatch altogether, jumping to 0x0000.
+        let pending = self.bus.pending_interrupts();
+
+        self.registers.sp.pointer -= 1;
+        self.write_byte(self.registers.sp.pointer.0, lower);
+
+        self.idle();
+
+        match Interrupt::highest_priority(pending) {
+            Some(interrupt) => {
+                self.bus.acknowledge_interrupt(interrupt);
+                self.registers.pc.pointer.0 = interrupt.vector();
+            }
+            None => self.registers.pc.pointer.0 = 0x0000,
+        }
     }
 
     fn execute(&mut self, instruction: Instruction) {
@@ -77,125 +471,151 @@ impl CPU<'_> {
                 let value = self.registers.read(source);
                 self.registers.write(target, value);
             }
+            Instruction::LDBB => {
+                if self.software_breakpoints {
+                    self.breakpoint_hit = true;
+                }
+            }
             Instruction::LDRN(target) => {
-                let value = self.registers.pc.read(&mut self.bus) as u8;
+                let value = self.fetch_byte();
                 self.registers.write(target, value);
             }
             Instruction::LDRHL(target) => {
                 let addr = self.registers.read(Reg16::HL);
-                let value = self.bus.read(addr as usize);
+                let value = self.read_byte(addr);
                 self.registers.write(target, value);
             }
             Instruction::LDHLR(source) => {
                 let addr = self.registers.read(Reg16::HL);
                 let value = self.registers.read(source);
-                self.bus.write(addr as usize, value);
+                self.write_byte(addr, value);
             }
             Instruction::LDHLN => {
                 let addr = self.registers.read(Reg16::HL);
-                let value = self.registers.pc.read(&mut self.bus) as u8;
-                self.bus.write(addr as usize, value);
+                let value = self.fetch_byte();
+                self.write_byte(addr, value);
             }
             Instruction::LDA16(source) => {
-                let addr = self.registers.read(source) as usize;
-                let value = self.bus.read(addr);
+                let addr = self.registers.read(source);
+                let value = self.read_byte(addr);
                 self.registers.write(Reg8::A, value);
             }
             Instruction::LD16A(target) => {
-                let addr = self.registers.read(target) as usize;
+                let addr = self.registers.read(target);
                 let value = self.registers.read(Reg8::A);
-                self.bus.write(addr, value);
+                self.write_byte(addr, value);
             }
             Instruction::LDANN => {
-                let value = self.registers.pc.read(&mut self.bus) as u8;
+                let addr = self.fetch_word();
+                let value = self.read_byte(addr);
                 self.registers.write(Reg8::A, value);
             }
             Instruction::LDNNA => {
-                let lower_byte = self.registers.pc.read(&mut self.bus) as u8;
-                let upper_byte = self.registers.pc.read(&mut self.bus) as u8;
+                let lower_byte = self.fetch_byte();
+                let upper_byte = self.fetch_byte();
 
-                let addr = u16::from_le_bytes([lower_byte, upper_byte]) as usize;
+                let addr = u16::from_le_bytes([lower_byte, upper_byte]);
 
                 let value = self.registers.read(Reg8::A);
-                self.bus.write(addr, value);
+                self.write_byte(addr, value);
             }
             Instruction::LDHAC => {
                 let addr = 0xFF00 | self.registers.read(Reg8::C) as u16;
-                let value = self.bus.read(addr as usize);
+                let value = self.read_byte(addr);
                 self.registers.write(Reg8::A, value);
             }
             Instruction::LDHCA => {
                 let addr = 0xFF00 | self.registers.read(Reg8::C) as u16;
                 let value = self.registers.read(Reg8::A);
-                self.bus.write(addr as usize, value);
+                self.write_byte(addr, value);
             }
             Instruction::LDHAN => {
-                let addr = 0xFF00 | self.registers.pc.read(&mut self.bus);
-                let value = self.bus.read(addr as usize);
+                let addr = 0xFF00 | self.fetch_byte() as u16;
+                let value = self.read_byte(addr);
                 self.registers.write(Reg8::A, value);
             }
             Instruction::LDHNA => {
-                let addr = 0xFF00 | self.registers.pc.read(&mut self.bus);
+                let addr = 0xFF00 | self.fetch_byte() as u16;
                 let value = self.registers.read(Reg8::A);
-                self.bus.write(addr as usize, value);
+                self.write_byte(addr, value);
             }
             Instruction::LDHLDECA => {
                 let addr = self.registers.read(Reg16::HL);
                 let value = self.registers.read(Reg8::A);
-                self.bus.write(addr as usize, value);
+                self.write_byte(addr, value);
                 self.registers.write(Reg16::HL, addr.wrapping_sub(1));
             }
             Instruction::LDHLINCA => {
                 let addr = self.registers.read(Reg16::HL);
                 let value = self.registers.read(Reg8::A);
-                self.bus.write(addr as usize, value);
+                self.write_byte(addr, value);
                 self.registers.write(Reg16::HL, addr.wrapping_add(1));
             }
             Instruction::LDAHLDEC => {
                 let addr = self.registers.read(Reg16::HL);
-                let value = self.bus.read(addr as usize);
+                let value = self.read_byte(addr);
                 self.registers.write(Reg16::HL, addr.wrapping_sub(1));
                 self.registers.write(Reg8::A, value);
             }
             Instruction::LDAHLINC => {
                 let addr = self.registers.read(Reg16::HL);
-                let value = self.bus.read(addr as usize);
+                let value = self.read_byte(addr);
                 self.registers.write(Reg16::HL, addr.wrapping_add(1));
                 self.registers.write(Reg8::A, value);
             }
             Instruction::LD16NN(target) => {
-                let lower_byte = self.registers.pc.read(&mut self.bus) as u8;
-                let upper_byte = self.registers.pc.read(&mut self.bus) as u8;
+                let lower_byte = self.fetch_byte();
+                let upper_byte = self.fetch_byte();
 
                 let data = u16::from_le_bytes([lower_byte, upper_byte]);
 
                 self.registers.write(target, data);
             }
             Instruction::LD16SP => {
-                let lower_byte = self.registers.pc.read(&mut self.bus) as u8;
-                let upper_byte = self.registers.pc.read(&mut self.bus) as u8;
+                let lower_byte = self.fetch_byte();
+                let upper_byte = self.fetch_byte();
 
                 let data = u16::from_le_bytes([lower_byte, upper_byte]);
 
-                self.registers.sp.write(&mut self.bus, data);
+                self.registers.sp.pointer.0 = data;
             }
             Instruction::LDNNSP => {
-                let lower_byte = self.registers.pc.read(&mut self.bus) as u8;
-                let upper_byte = self.registers.pc.read(&mut self.bus) as u8;
+                let lower_byte = self.fetch_byte();
+                let upper_byte = self.fetch_byte();
 
-                let data = u16::from_le_bytes([lower_byte, upper_byte]);
-                self.registers.sp.write(&mut self.bus, data);
+                let addr = u16::from_le_bytes([lower_byte, upper_byte]);
+                let [lower, upper] = self.registers.sp.pointer.0.to_le_bytes();
+
+                self.write_byte(addr, lower);
+                self.write_byte(addr.wrapping_add(1), upper);
             }
             Instruction::LDSPHL => {
                 let data = self.registers.read(Reg16::HL);
-                self.registers.sp.write(&mut self.bus, data);
+                self.idle();
+                self.registers.sp.pointer.0 = data;
+            }
+            Instruction::LDHLSPE => {
+                let a = self.registers.sp.pointer.0;
+                let b = self.fetch_byte();
+
+                // Same flags as ADD SP, e
+                let (_, flags) = self.alu.add(a as u8, b);
+                let result = a.wrapping_add_signed((b as i8).into());
+
+                self.idle();
+                self.registers.set_flags(Flags {
+                    zero: false,
+                    subtract: false,
+                    ..flags
+                });
+                self.registers.write(Reg16::HL, result);
             }
             Instruction::PUSH(target) => {
                 let data = self.registers.read(target);
-                self.registers.sp.write(&mut self.bus, data);
+                self.push_16(data);
             }
             Instruction::POP(target) => {
-                let data = self.registers.sp.read(&mut self.bus);
+                let data = self.pop_16();
                 self.registers.write(target, data);
             }
 
@@ -209,9 +629,9 @@ impl CPU<'_> {
                 self.registers.write(Reg8::A, result);
             }
             Instruction::ADDHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b = self.bus.read(addr);
+                let b = self.read_byte(addr);
                 let (result, flags) = self.alu.add(a, b);
 
                 self.registers.set_flags(flags);
@@ -219,7 +639,7 @@ impl CPU<'_> {
             }
             Instruction::ADDNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let (result, flags) = self.alu.add(a, b);
 
                 self.registers.set_flags(flags);
@@ -235,9 +655,9 @@ impl CPU<'_> {
                 self.registers.write(Reg8::A, result);
             }
             Instruction::ADCHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b = self.bus.read(addr);
+                let b = self.read_byte(addr);
                 let carry = self.registers.get_flags().carry as u8;
                 let (result, flags) = self.alu.adc(a, b, carry);
 
@@ -246,7 +666,7 @@ impl CPU<'_> {
             }
             Instruction::ADCNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let carry = self.registers.get_flags().carry as u8;
                 let (result, flags) = self.alu.adc(a, b, carry);
 
@@ -262,9 +682,9 @@ impl CPU<'_> {
                 self.registers.write(Reg8::A, result);
             }
             Instruction::SUBHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b = self.bus.read(addr);
+                let b = self.read_byte(addr);
                 let (result, flags) = self.alu.sub(a, b);
 
                 self.registers.set_flags(flags);
@@ -272,7 +692,7 @@ impl CPU<'_> {
             }
             Instruction::SUBNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let (result, flags) = self.alu.sub(a, b);
 
                 self.registers.set_flags(flags);
@@ -288,9 +708,9 @@ impl CPU<'_> {
                 self.registers.write(Reg8::A, result);
             }
             Instruction::SBCHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b = self.bus.read(addr);
+                let b = self.read_byte(addr);
                 let carry = self.registers.get_flags().carry as u8;
                 let (result, flags) = self.alu.sbc(a, b, carry);
 
@@ -299,7 +719,7 @@ impl CPU<'_> {
             }
             Instruction::SBCNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let carry = self.registers.get_flags().carry as u8;
                 let (result, flags) = self.alu.sbc(a, b, carry);
 
@@ -308,9 +728,9 @@ impl CPU<'_> {
             }
             Instruction::AND(target) => self.and(target),
             Instruction::ANDHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b: u8 = self.bus.read(addr);
+                let b: u8 = self.read_byte(addr);
                 let result = a & b;
                 let flags = Flags {
                     zero: result == 0,
@@ -324,7 +744,7 @@ impl CPU<'_> {
             }
             Instruction::ANDNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let result = a & b;
                 let flags = Flags {
                     zero: result == 0,
@@ -338,9 +758,9 @@ impl CPU<'_> {
             }
             Instruction::OR(target) => self.or(target),
             Instruction::ORHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b: u8 = self.bus.read(addr);
+                let b: u8 = self.read_byte(addr);
                 let result = a | b;
                 let flags = Flags {
                     zero: result == 0,
@@ -354,7 +774,7 @@ impl CPU<'_> {
             }
             Instruction::ORNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let result = a | b;
                 let flags = Flags {
                     zero: result == 0,
@@ -368,9 +788,9 @@ impl CPU<'_> {
             }
             Instruction::XOR(target) => self.xor(target),
             Instruction::XORHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b: u8 = self.bus.read(addr);
+                let b: u8 = self.read_byte(addr);
                 let result = a ^ b;
                 let flags = Flags {
                     zero: result == 0,
@@ -384,7 +804,7 @@ impl CPU<'_> {
             }
             Instruction::XORNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let result = a ^ b;
                 let flags = Flags {
                     zero: result == 0,
@@ -396,51 +816,55 @@ impl CPU<'_> {
                 self.registers.set_flags(flags);
                 self.registers.write(Reg8::A, result);
             }
-            Instruction::CP(target) => self.cp(target, Reg8::A),
+            Instruction::CP(target) => self.cp(Reg8::A, target),
             Instruction::CPHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
+                let addr = self.registers.read(Reg16::HL);
                 let a = self.registers.read(Reg8::A);
-                let b = self.bus.read(addr);
+                let b = self.read_byte(addr);
                 let (_, flags) = self.alu.sub(a, b);
 
                 self.registers.set_flags(flags);
             }
             Instruction::CPNN => {
                 let a = self.registers.read(Reg8::A);
-                let b = self.registers.pc.read(&mut self.bus) as u8;
+                let b = self.fetch_byte();
                 let (_, flags) = self.alu.sub(a, b);
 
                 self.registers.set_flags(flags);
             }
             Instruction::INC(target) => {
                 let a = self.registers.read(target);
-                let (result, flags) = self.alu.add(a, 1);
+                let carry = self.registers.get_flags().carry as u8;
+                let (result, flags) = self.alu.inc(a, carry);
 
                 self.registers.set_flags(flags);
                 self.registers.write(target, result);
             }
             Instruction::INCHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let a = self.bus.read(addr);
-                let (result, flags) = self.alu.add(a, 1);
+                let addr = self.registers.read(Reg16::HL);
+                let a = self.read_byte(addr);
+                let carry = self.registers.get_flags().carry as u8;
+                let (result, flags) = self.alu.inc(a, carry);
 
                 self.registers.set_flags(flags);
-                self.bus.write(addr, result);
+                self.write_byte(addr, result);
             }
             Instruction::DEC(target) => {
                 let a = self.registers.read(target);
-                let (result, flags) = self.alu.sub(a, 1);
+                let carry = self.registers.get_flags().carry as u8;
+                let (result, flags) = self.alu.dec(a, carry);
 
                 self.registers.set_flags(flags);
                 self.registers.write(target, result);
             }
             Instruction::DECHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let a = self.bus.read(addr);
-                let (result, flags) = self.alu.sub(a, 1);
+                let addr = self.registers.read(Reg16::HL);
+                let a = self.read_byte(addr);
+                let carry = self.registers.get_flags().carry as u8;
+                let (result, flags) = self.alu.dec(a, carry);
 
                 self.registers.set_flags(flags);
-                self.bus.write(addr, result);
+                self.write_byte(addr, result);
             }
 
             // 16-bit Arithmetic and Logical Operations
@@ -448,48 +872,65 @@ impl CPU<'_> {
                 let a = self.registers.read(Reg16::HL);
                 let b = self.registers.read(target);
                 let (result, flags) = self.alu.add16(a, b);
+                let zero = self.registers.get_flags().zero;
 
-                self.registers.set_flags(flags);
+                self.idle();
+                self.registers.set_flags(Flags { zero, ..flags });
                 self.registers.write(Reg16::HL, result);
             }
             Instruction::ADDHLRSP => {
                 let a = self.registers.read(Reg16::HL);
                 let b = self.registers.sp.pointer.0;
                 let (result, flags) = self.alu.add16(a, b);
+                let zero = self.registers.get_flags().zero;
 
-                self.registers.set_flags(flags);
+                self.idle();
+                self.registers.set_flags(Flags { zero, ..flags });
                 self.registers.write(Reg16::HL, result);
             }
             Instruction::ADDSPE => {
                 let a = self.registers.sp.pointer.0;
-                let b: u16 = 0xE8;
-                let (result, flags) = self.alu.add16(a, b);
+                let b = self.fetch_byte();
 
-                self.registers.set_flags(flags);
+                // The flags come from the unsigned addition of the lower byte
+                let (_, flags) = self.alu.add(a as u8, b);
+                let result = a.wrapping_add_signed((b as i8).into());
+
+                self.idle();
+                self.idle();
+                self.registers.set_flags(Flags {
+                    zero: false,
+                    subtract: false,
+                    ..flags
+                });
                 self.registers.sp.pointer.0 = result;
             }
             Instruction::INC16(target) => {
                 let a = self.registers.read(target);
                 let result = a.wrapping_add(1);
 
+                self.idle();
                 self.registers.write(target, result);
             }
             Instruction::INC16SP => {
                 let a = self.registers.sp.pointer.0;
                 let result = a.wrapping_add(1);
 
+                self.idle();
                 self.registers.sp.pointer.0 = result;
             }
             Instruction::DEC16(target) => {
                 let a = self.registers.read(target);
                 let result = a.wrapping_sub(1);
 
+                self.idle();
                 self.registers.write(target, result);
             }
             Instruction::DEC16SP => {
                 let a = self.registers.sp.pointer.0;
                 let result = a.wrapping_sub(1);
 
+                self.idle();
                 self.registers.sp.pointer.0 = result;
             }
 
@@ -499,8 +940,8 @@ impl CPU<'_> {
                 self.bit(bit, data)
             }
             Instruction::BITHL(bit) => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let data = self.read_byte(addr);
                 self.bit(bit, data)
             }
             Instruction::SET(bit, target) => {
@@ -509,21 +950,21 @@ impl CPU<'_> {
                 self.registers.write(target, data);
             }
             Instruction::SETHL(bit) => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.set(bit, &mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::RESET(bit, target) => {
                 let mut data = self.registers.read(target);
                 self.reset(bit, &mut data);
-                self.reset(bit, &mut data);
+                self.registers.write(target, data);
             }
             Instruction::RESETHL(bit) => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.reset(bit, &mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::SWAP(target) => {
                 let mut data = self.registers.read(target);
@@ -531,10 +972,10 @@ impl CPU<'_> {
                 self.registers.write(target, data);
             }
             Instruction::SWAPHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.swap(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
 
             // Bit Shifts
@@ -544,10 +985,10 @@ impl CPU<'_> {
                 self.registers.write(target, data);
             }
             Instruction::SRLHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.srl(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::SRA(target) => {
                 let mut data = self.registers.read(target);
@@ -555,10 +996,10 @@ impl CPU<'_> {
                 self.registers.write(target, data);
             }
             Instruction::SRAHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.sra(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::SLA(target) => {
                 let mut data = self.registers.read(target);
@@ -566,30 +1007,34 @@ impl CPU<'_> {
                 self.registers.write(target, data);
             }
             Instruction::SLAHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.sla(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::RRA => {
                 let mut data = self.registers.read(Reg8::A);
                 self.rr(&mut data);
                 self.registers.write(Reg8::A, data);
+                self.clear_zero();
             }
             Instruction::RLA => {
                 let mut data = self.registers.read(Reg8::A);
                 self.rl(&mut data);
                 self.registers.write(Reg8::A, data);
+                self.clear_zero();
             }
             Instruction::RRCA => {
                 let mut data = self.registers.read(Reg8::A);
                 self.rrc(&mut data);
                 self.registers.write(Reg8::A, data);
+                self.clear_zero();
             }
             Instruction::RLCA => {
                 let mut data = self.registers.read(Reg8::A);
                 self.rlc(&mut data);
                 self.registers.write(Reg8::A, data);
+                self.clear_zero();
             }
             Instruction::RR(target) => {
                 let mut data = self.registers.read(target);
@@ -612,28 +1057,28 @@ impl CPU<'_> {
                 self.registers.write(target, data);
             }
             Instruction::RRHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.rr(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::RLHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.rl(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::RRCHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.rrc(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
             Instruction::RLCHL => {
-                let addr = self.registers.read(Reg16::HL) as usize;
-                let mut data = self.bus.read(addr);
+                let addr = self.registers.read(Reg16::HL);
+                let mut data = self.read_byte(addr);
                 self.rlc(&mut data);
-                self.bus.write(addr, data);
+                self.write_byte(addr, data);
             }
 
             // Misc Operations
@@ -642,10 +1087,7 @@ impl CPU<'_> {
             Instruction::CPL => self.cpl(),
             Instruction::DAA => self.daa(),
             Instruction::NOP => self.nop(),
-            Instruction::HALT => {
-                dbg!("halted @ 0x{:04X}", self.registers.pc.pointer.0);
-                self.mode = Mode::Halted;
-            }
+            Instruction::HALT => self.halt(),
             Instruction::STOP => self.stop(),
             Instruction::DI => self.di(),
             Instruction::EI => self.ei(),
@@ -654,33 +1096,37 @@ impl CPU<'_> {
             Instruction::JP => self.jp(),
             Instruction::JPHL => {
                 let addr = self.registers.read(Reg16::HL);
-                self.registers.pc.write(&mut self.bus, addr);
+                self.registers.pc.pointer.0 = addr;
             }
             Instruction::JPCC(condition) => {
+                // The operand is always fetched, only the jump itself is conditional
+                let addr = self.fetch_word();
+
                 if self.evaluate_condition(condition) {
-                    self.jp()
-                } else {
-                    self.registers.pc.pointer += 2;
+                    self.jump(addr)
                 }
             }
             Instruction::JR => self.jr(),
             Instruction::JRCC(condition) => {
+                let offset = self.fetch_byte() as i8;
+
                 if self.evaluate_condition(condition) {
-                    self.jr()
-                } else {
-                    self.registers.pc.pointer += 1;
+                    self.jump_relative(offset)
                 }
             }
             Instruction::CALL => self.call(),
             Instruction::CALLCC(condition) => {
+                let addr = self.fetch_word();
+
                 if self.evaluate_condition(condition) {
-                    self.call()
-                } else {
-                    self.registers.pc.pointer += 2;
+                    self.call_addr(addr)
                 }
             }
             Instruction::RET => self.ret(),
             Instruction::RETCC(condition) => {
+                // Evaluating the condition costs an extra cycle, taken or not
+                self.idle();
+
                 if self.evaluate_condition(condition) {
                     self.ret()
                 }
@@ -690,21 +1136,18 @@ impl CPU<'_> {
                 self.ret()
             }
             Instruction::RST(target) => {
-                self.registers
-                    .sp
-                    .write(&mut self.bus, self.registers.pc.pointer.0);
-
+                self.push_16(self.registers.pc.pointer.0);
                 self.registers.pc.pointer.0 = target.into();
             }
 
             // Prefix Operations
             Instruction::PREFIXCB => {
-                let opcode = self.registers.pc.read(&mut self.bus) as u8;
+                let opcode = self.fetch_byte();
 
-                if let Some(instruction) = Instruction::from_byte_prefixed(opcode) {
-                    self.execute(instruction)
-                } else {
-                    panic!("Invalid instruction: {:02X}{:02X}", 0xcb, opcode)
+                // All 256 CB opcodes are defined, unlike the unprefixed ones which lock up the CPU
+                match Instruction::from_byte_prefixed(opcode) {
+                    Some(instruction) => self.execute(instruction),
+                    None => unreachable!("undecoded CB opcode {:02X}", opcode),
                 }
             }
         }
@@ -764,10 +1207,10 @@ impl CPU<'_> {
     }
 
     fn ccf(&mut self) {
-        let carry = self.registers.get_flags().carry;
+        let Flags { zero, carry, .. } = self.registers.get_flags();
 
         self.registers.set_flags(Flags {
-            zero: false,
+            zero,
             subtract: false,
             half_carry: false,
             carry: !carry,
@@ -819,14 +1262,24 @@ impl CPU<'_> {
         self.registers.set_flags(flags);
     }
 
+    // The accumulator rotations always clear Z, unlike their CB prefixed versions
+    fn clear_zero(&mut self) {
+        let flags = self.registers.get_flags();
+
+        self.registers.set_flags(Flags {
+            zero: false,
+            ..flags
+        });
+    }
+
     fn cpl(&mut self) {
         let a = self.registers.read(Reg8::A);
         let flags = self.registers.get_flags();
         let result = !a;
 
         self.registers.set_flags(Flags {
-            zero: flags.zero,
             subtract: true,
+            half_carry: true,
             ..flags
         });
 
@@ -846,11 +1299,11 @@ impl CPU<'_> {
     }
 
     fn reset(&mut self, bit: u8, data: &mut u8) {
-        *data = *data & (0 << bit);
+        *data &= !(1 << bit);
     }
 
     fn set(&mut self, bit: u8, data: &mut u8) {
-        *data = *data | (1 << bit);
+        *data |= 1 << bit;
     }
 
     fn srl(&mut self, data: &mut u8) {
@@ -894,7 +1347,7 @@ impl CPU<'_> {
     }
 
     fn swap(&mut self, data: &mut u8) {
-        *data = (*data << 4) | (*data >> 4);
+        *data = data.rotate_left(4);
         let zero = *data == 0;
 
         self.registers.set_flags(Flags {
@@ -931,24 +1384,61 @@ impl CPU<'_> {
         flags.carry = carry;
 
         self.registers.set_flags(flags);
+        self.registers.write(Reg8::A, a);
     }
 
     fn di(&mut self) {
         self.ime = false;
+        self.ime_delay = 0;
     }
 
     fn ei(&mut self) {
-        self.ime = true;
+        // Counting down from 2 lets the instruction following EI run before IME is set
+        if !self.ime && self.ime_delay == 0 {
+            self.ime_delay = 2;
+        }
+    }
+
+    fn halt(&mut self) {
+        if !self.ime && self.bus.pending_interrupts() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.mode = Mode::Halted;
+        }
     }
 
     fn nop(&mut self) {}
 
     fn stop(&mut self) {
-        panic!("STOP instruction received");
+        // STOP is followed by a padding byte which is skipped
+        self.fetch_byte();
+
+        // On the CGB, arming bit 0 of KEY1 turns STOP into a switch between normal and double speed
+        if self.bus.switch_speed() {
+            return;
+        }
+
+        self.bus.write(DIVIDER as usize, 0u8);
+        self.mode = Mode::Stopped;
     }
 
     fn push_16(&mut self, data: u16) {
-        self.registers.sp.write(&mut self.bus, data);
+        let [lower, upper] = data.to_le_bytes();
+
+        self.idle();
+        self.registers.sp.pointer -= 1;
+        self.write_byte(self.registers.sp.pointer.0, upper);
+        self.registers.sp.pointer -= 1;
+        self.write_byte(self.registers.sp.pointer.0, lower);
+    }
+
+    fn pop_16(&mut self) -> u16 {
+        let lower = self.read_byte(self.registers.sp.pointer.0);
+        self.registers.sp.pointer += 1;
+        let upper = self.read_byte(self.registers.sp.pointer.0);
+        self.registers.sp.pointer += 1;
+
+        u16::from_le_bytes([lower, upper])
     }
 
     fn evaluate_condition(&self, condition: Condition) -> bool {
@@ -961,36 +1451,44 @@ impl CPU<'_> {
     }
 
     fn jp(&mut self) {
-        let lower = self.registers.pc.read(&mut self.bus) as u8;
-        let upper = self.registers.pc.read(&mut self.bus) as u8;
+        let addr = self.fetch_word();
+        self.jump(addr);
+    }
 
-        let addr = u16::from_le_bytes([lower, upper]);
-        self.registers.pc.write(&mut self.bus, addr);
+    fn jump(&mut self, addr: u16) {
+        self.idle();
+        self.registers.pc.pointer.0 = addr;
     }
 
     fn jr(&mut self) {
-        let offset = self.registers.pc.read(&mut self.bus) as i8;
+        let offset = self.fetch_byte() as i8;
+        self.jump_relative(offset);
+    }
 
-        if offset < 0 {
-            self.registers.pc.pointer -= (-offset) as u16;
-        } else {
-            self.registers.pc.pointer += offset as u16;
-        }
+    fn jump_relative(&mut self, offset: i8) {
+        self.idle();
+        self.registers.pc.pointer.0 = self
+            .registers
+            .pc
+            .pointer
+            .0
+            .wrapping_add_signed(offset.into());
     }
 
     fn call(&mut self) {
-        let lower = self.registers.pc.read(&mut self.bus) as u8;
-        let upper = self.registers.pc.read(&mut self.bus) as u8;
-
-        let data = u16::from_le_bytes([lower, upper]);
+        let addr = self.fetch_word();
+        self.call_addr(addr);
+    }
 
-        self.registers.sp.write(&mut self.bus, data);
-        self.registers.pc.write(&mut self.bus, data);
+    fn call_addr(&mut self, addr: u16) {
+        self.push_16(self.registers.pc.pointer.0);
+        self.registers.pc.pointer.0 = addr;
     }
 
     fn ret(&mut self) {
-        let addr = self.registers.sp.read(&mut self.bus);
+        let addr = self.pop_16();
 
-        self.registers.pc.write(&mut self.bus, addr);
+        self.idle();
+        self.registers.pc.pointer.0 = addr;
     }
 }
@@ -1,5 +1,9 @@
+use std::fmt::{self, Display};
 use std::num::Wrapping;
 
+use crate::boot::Model;
+use crate::cartridge::header::CartridgeHeader;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
 use crate::{memory::bus::MemoryBus, utils::traits::Storage};
 
 // The operations represented by the following functions are described here:
@@ -102,6 +106,36 @@ pub enum Reg16 {
     HL,
 }
 
+impl Display for Reg8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Reg8::A => "A",
+            Reg8::B => "B",
+            Reg8::C => "C",
+            Reg8::D => "D",
+            Reg8::E => "E",
+            Reg8::F => "F",
+            Reg8::H => "H",
+            Reg8::L => "L",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl Display for Reg16 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Reg16::AF => "AF",
+            Reg16::BC => "BC",
+            Reg16::DE => "DE",
+            Reg16::HL => "HL",
+        };
+
+        f.write_str(name)
+    }
+}
+
 impl Storage<Reg8, u8> for Registers {
     fn read(&mut self, src: Reg8) -> u8 {
         match src {
@@ -133,20 +167,21 @@ impl Storage<Reg8, u8> for Registers {
 impl Storage<Reg16, u16> for Registers {
     fn read(&mut self, src: Reg16) -> u16 {
         match src {
-            Reg16::AF => u16::from_le_bytes([self.data[0], self.data[5]]),
-            Reg16::BC => u16::from_le_bytes([self.data[1], self.data[2]]),
-            Reg16::DE => u16::from_le_bytes([self.data[3], self.data[4]]),
-            Reg16::HL => u16::from_le_bytes([self.data[6], self.data[7]]),
+            Reg16::AF => u16::from_be_bytes([self.data[0], self.data[5]]),
+            Reg16::BC => u16::from_be_bytes([self.data[1], self.data[2]]),
+            Reg16::DE => u16::from_be_bytes([self.data[3], self.data[4]]),
+            Reg16::HL => u16::from_be_bytes([self.data[6], self.data[7]]),
         }
     }
 
     fn write(&mut self, dest: Reg16, value: u16) {
-        let [high, low] = value.to_le_bytes();
+        let [high, low] = value.to_be_bytes();
 
         match dest {
             Reg16::AF => {
                 self.data[0] = high;
-                self.data[5] = low;
+                // The lower nibble of F doesn't exist and always reads back as 0
+                self.data[5] = low & 0xF0;
             }
             Reg16::BC => {
                 self.data[1] = high;
@@ -164,16 +199,91 @@ impl Storage<Reg16, u16> for Registers {
     }
 }
 
+impl Snapshot for Registers {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+        state.write_u16(self.pc.pointer.0);
+        state.write_u16(self.sp.pointer.0);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.data)?;
+        self.pc.pointer.0 = state.read_u16()?;
+        self.sp.pointer.0 = state.read_u16()?;
+
+        Ok(())
+    }
+}
+
 impl Registers {
+    // Starts out with the values the DMG boot ROM leaves behind for most cartridges, see
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
     pub fn new() -> Self {
+        // A, B, C, D, E, F, H, L
+        Registers::with(
+            0x100,
+            0xFFFE,
+            [0x01, 0x00, 0x13, 0x00, 0xD8, 0xB0, 0x01, 0x4D],
+        )
+    }
+
+    /// The registers as the CPU powers on, about to run the boot ROM.
+    pub fn power_on() -> Self {
+        Registers::with(0x0000, 0x0000, [0; 8])
+    }
+
+    /// The values the boot ROM of a model leaves behind, some of which depend on the cartridge.
+    pub fn post_boot(model: Model, header: &CartridgeHeader) -> Self {
+        // A, B, C, D, E, F, H, L
+        let data = match model {
+            Model::Dmg0 => [0x01, 0xFF, 0x13, 0x00, 0xC1, 0x00, 0x84, 0x03],
+            Model::Dmg | Model::Mgb => {
+                // A tells games they're running on a Game Boy Pocket
+                let a = if model == Model::Mgb { 0xFF } else { 0x01 };
+                // The header checksum check leaves H and C set, unless the checksum is 0
+                let f = if header.header_checksum == 0 {
+                    0x80
+                } else {
+                    0xB0
+                };
+                [a, 0x00, 0x13, 0x00, 0xD8, f, 0x01, 0x4D]
+            }
+            Model::Sgb => [0x01, 0x00, 0x14, 0x00, 0x00, 0x00, 0xC0, 0x60],
+            Model::Cgb if header.cgb_flag.is_some() => {
+                [0x11, 0x00, 0x00, 0xFF, 0x56, 0x80, 0x00, 0x0D]
+            }
+            Model::Cgb => {
+                // Running an older game, the boot ROM sums up its title to pick a palette for it
+                // if it was published by Nintendo, and leaves the sum in B
+                let nintendo = header.old_licensee_code == 0x01
+                    || (header.old_licensee_code == 0x33 && header.new_licensee_code == *b"01");
+                let b = match nintendo {
+                    true => header
+                        .title
+                        .iter()
+                        .fold(0u8, |sum, byte| sum.wrapping_add(*byte)),
+                    false => 0x00,
+                };
+                let [h, l] = match b {
+                    0x43 | 0x58 => [0x99, 0x1A],
+                    _ => [0x00, 0x7C],
+                };
+                [0x11, b, 0x00, 0x00, 0x08, 0x80, h, l]
+            }
+        };
+
+        Registers::with(0x100, 0xFFFE, data)
+    }
+
+    fn with(pc: u16, sp: u16, data: [u8; 8]) -> Self {
         Self {
             sp: StackPointer {
-                pointer: Wrapping(0xFFFF),
+                pointer: Wrapping(sp),
             },
             pc: ProgramCounter {
-                pointer: Wrapping(0x100),
+                pointer: Wrapping(pc),
             },
-            data: [0; 8],
+            data,
         }
     }
 
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::symbols::Symbols;
+
+// Lines written between flushes, a few frames' worth, so little is lost if the emulator is killed
+const FLUSH_INTERVAL: u32 = 0x10000;
+
+/// Destination of the instruction trace, one line per instruction in the format used by
+/// Gameboy Doctor, see https://github.com/robert/gameboy-doctor
+pub struct Trace {
+    writer: Box<dyn Write>,
+    symbols: Symbols,
+    lines: u32,
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace").finish_non_exhaustive()
+    }
+}
+
+impl Trace {
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            symbols: Symbols::new(),
+            lines: 0,
+        }
+    }
+
+    /// Labels the instructions which have a symbol, after the state. Gameboy Doctor doesn't
+    /// expect these, so they're best left out when comparing against its logs.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// Logs the CPU state right before an instruction executes, `registers` being in
+    /// A/F/B/C/D/E/H/L order, `bank` the ROM bank mapped at PC and `memory` the four bytes
+    /// starting at PC.
+    pub fn log(&mut self, registers: [u8; 8], sp: u16, pc: u16, bank: u16, memory: [u8; 4]) {
+        let [a, f, b, c, d, e, h, l] = registers;
+        let [m0, m1, m2, m3] = memory;
+
+        // A trace is a debugging aid, losing some of it isn't worth stopping the emulator
+        let _ = write!(
+            self.writer,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} \
+             PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            a, f, b, c, d, e, h, l, sp, pc, m0, m1, m2, m3
+        );
+
+        let _ = match self.symbols.label(bank, pc) {
+            Some(label) => writeln!(self.writer, " ; {}", label),
+            None => writeln!(self.writer),
+        };
+
+        self.lines = self.lines.wrapping_add(1);
+        if self.lines.is_multiple_of(FLUSH_INTERVAL) {
+            let _ = self.writer.flush();
+        }
+    }
+
+    /// Writes out whatever is still buffered.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
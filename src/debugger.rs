@@ -0,0 +1,525 @@
+use std::io::{self, BufRead, Write};
+
+use crate::cpu::disassembler::disassemble_with;
+use crate::cpu::registers::{Reg16, Reg8};
+use crate::cpu::{Access, Mode, Watchpoint, CPU};
+use crate::memory::bus::Bus;
+use crate::signal;
+use crate::symbols::Symbols;
+use crate::utils::traits::Storage;
+
+const HELP: &str = "Commands:
+    step [n]                 Execute n instructions (default: 1)
+    continue                 Run until a breakpoint or watchpoint is hit, or Ctrl-C is pressed
+    finish                   Run until the current function returns
+    break [addr]             Stop before executing addr, or list the breakpoints
+    watch <addr> [r|w]       Stop when addr is read and/or written (default: both)
+    delete <addr>            Remove the breakpoint and watchpoint on addr
+    regs                     Show the registers
+    mem <addr> [len]         Dump len bytes of memory (default: 16)
+    disasm [addr]            Disassemble from addr (default: PC)
+    set <reg> <value>        Set a register, e.g. `set hl c000`
+    quit                     Exit the emulator
+
+Addresses and values are hexadecimal, optionally prefixed with 0x or $, while counts are decimal.
+Labels from the ROM's .sym file can be used in place of addresses. An empty line repeats the last
+command.";
+
+// Instructions listed by `disasm`
+const DISASSEMBLY_LINES: usize = 10;
+
+const DEFAULT_DUMP_LENGTH: u16 = 16;
+const DUMP_WIDTH: u16 = 16;
+
+// RET, RETI and the conditional returns
+const RETURNS: [u8; 6] = [0xC9, 0xD9, 0xC0, 0xC8, 0xD0, 0xD8];
+
+/// An address to stop at, optionally only while a given ROM bank is mapped there.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub bank: Option<u16>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Register {
+    R8(Reg8),
+    R16(Reg16),
+    SP,
+    PC,
+}
+
+#[derive(Debug)]
+enum Command {
+    Step(u32),
+    Continue,
+    Finish,
+    Break(Option<Breakpoint>),
+    Watch(Watchpoint),
+    Delete(u16),
+    Regs,
+    Mem(u16, u16),
+    Disasm(Option<u16>),
+    Set(Register, u16),
+    Help,
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str, symbols: &Symbols) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+
+        let command = match (name, args.as_slice()) {
+            ("step" | "s", []) => Command::Step(1),
+            ("step" | "s", [count]) => Command::Step(Command::count(count)?),
+            ("continue" | "c", []) => Command::Continue,
+            ("finish" | "f", []) => Command::Finish,
+            ("break" | "b", []) => Command::Break(None),
+            ("break" | "b", [addr]) => Command::Break(Some(Command::location(addr, symbols)?)),
+            ("watch" | "w", [addr, kind @ ..]) if kind.len() <= 1 => {
+                let (read, write) = match kind.first().copied() {
+                    None | Some("rw") => (true, true),
+                    Some("r") => (true, false),
+                    Some("w") => (false, true),
+                    Some(kind) => return Err(format!("Invalid watchpoint kind: {}", kind)),
+                };
+
+                Command::Watch(Watchpoint {
+                    address: Command::address(addr, symbols)?,
+                    read,
+                    write,
+                })
+            }
+            ("delete" | "d", [addr]) => Command::Delete(Command::address(addr, symbols)?),
+            ("regs" | "r", []) => Command::Regs,
+            ("mem" | "m", [addr]) => {
+                Command::Mem(Command::address(addr, symbols)?, DEFAULT_DUMP_LENGTH)
+            }
+            ("mem" | "m", [addr, len]) => {
+                let len = Command::count(len)?;
+                let len = u16::try_from(len).map_err(|_| format!("Invalid length: {}", len))?;
+
+                Command::Mem(Command::address(addr, symbols)?, len)
+            }
+            ("disasm" | "di", []) => Command::Disasm(None),
+            ("disasm" | "di", [addr]) => Command::Disasm(Some(Command::address(addr, symbols)?)),
+            ("set", [register, value]) => Command::Set(
+                Command::register(register)?,
+                Command::address(value, symbols)?,
+            ),
+            ("help" | "h", []) => Command::Help,
+            ("quit" | "q", []) => Command::Quit,
+            _ => return Err(format!("Invalid command: {}, try `help`", line)),
+        };
+
+        Ok(command)
+    }
+
+    // Labels name a bank as well, which only matters for the switchable ROM area
+    fn location(arg: &str, symbols: &Symbols) -> Result<Breakpoint, String> {
+        match symbols.address(arg) {
+            Some((bank, address)) => Ok(Breakpoint {
+                address,
+                bank: (0x4000..0x8000).contains(&address).then_some(bank),
+            }),
+            None => Command::value(arg).map(|address| Breakpoint {
+                address,
+                bank: None,
+            }),
+        }
+    }
+
+    fn address(arg: &str, symbols: &Symbols) -> Result<u16, String> {
+        Command::location(arg, symbols).map(|location| location.address)
+    }
+
+    // Hexadecimal, optionally prefixed with 0x or $
+    fn value(arg: &str) -> Result<u16, String> {
+        let digits = arg
+            .strip_prefix("0x")
+            .or_else(|| arg.strip_prefix('$'))
+            .unwrap_or(arg);
+
+        u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid value: {}", arg))
+    }
+
+    fn count(arg: &str) -> Result<u32, String> {
+        arg.parse().map_err(|_| format!("Invalid count: {}", arg))
+    }
+
+    fn register(arg: &str) -> Result<Register, String> {
+        let register = match arg.to_lowercase().as_str() {
+            "a" => Register::R8(Reg8::A),
+            "b" => Register::R8(Reg8::B),
+            "c" => Register::R8(Reg8::C),
+            "d" => Register::R8(Reg8::D),
+            "e" => Register::R8(Reg8::E),
+            "h" => Register::R8(Reg8::H),
+            "l" => Register::R8(Reg8::L),
+            "af" => Register::R16(Reg16::AF),
+            "bc" => Register::R16(Reg16::BC),
+            "de" => Register::R16(Reg16::DE),
+            "hl" => Register::R16(Reg16::HL),
+            "sp" => Register::SP,
+            "pc" => Register::PC,
+            _ => return Err(format!("Invalid register: {}", arg)),
+        };
+
+        Ok(register)
+    }
+}
+
+// Why execution came to a halt
+#[derive(Debug)]
+pub(crate) enum Stop {
+    Done,
+    Breakpoint(u16),
+    Watchpoint(Access),
+    SoftwareBreakpoint,
+    Locked(u8),
+}
+
+/// An interactive debugger, reading commands from a line based input.
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    symbols: Symbols,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            symbols: Symbols::new(),
+            last_command: String::new(),
+        }
+    }
+
+    /// Shows labels in place of addresses, and accepts them in commands.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// Executes commands from `input` until it runs out or `quit` is entered. LD B,B acts as a
+    /// breakpoint while debugging.
+    pub fn run<B: Bus>(
+        &mut self,
+        cpu: &mut CPU<B>,
+        input: impl BufRead,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        cpu.set_software_breakpoints(true);
+        self.show_location(cpu, out)?;
+        Debugger::prompt(out)?;
+
+        for line in input.lines() {
+            let line = line?;
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+
+            if line.is_empty() {
+                Debugger::prompt(out)?;
+                continue;
+            }
+
+            match Command::parse(&line, &self.symbols) {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(command) => self.execute(cpu, command, out)?,
+                Err(message) => writeln!(out, "{}", message)?,
+            }
+
+            self.last_command = line;
+            Debugger::prompt(out)?;
+        }
+
+        Ok(())
+    }
+
+    fn prompt(out: &mut impl Write) -> io::Result<()> {
+        write!(out, "> ")?;
+        out.flush()
+    }
+
+    fn execute<B: Bus>(
+        &mut self,
+        cpu: &mut CPU<B>,
+        command: Command,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        match command {
+            Command::Step(count) => {
+                let mut left = count;
+
+                if left > 0 {
+                    let stop = resume(cpu, &self.breakpoints, |_| {
+                        left -= 1;
+                        left == 0
+                    });
+                    self.report(cpu, stop, out)?;
+                }
+            }
+            Command::Continue => {
+                // A Ctrl-C pressed at the prompt shouldn't stop it straight away
+                signal::take();
+
+                let stop = resume(cpu, &self.breakpoints, |_| signal::take());
+                if let Stop::Done = stop {
+                    writeln!(out, "Interrupted")?;
+                }
+                self.report(cpu, stop, out)?;
+            }
+            Command::Finish => {
+                // The function has returned once a return pops the stack past where it started
+                let sp = cpu.registers().sp.pointer.0;
+                let mut returning = Debugger::at_return(cpu);
+
+                let stop = resume(cpu, &self.breakpoints, |cpu| {
+                    let returned = returning && cpu.registers().sp.pointer.0 > sp;
+                    returning = Debugger::at_return(cpu);
+
+                    returned
+                });
+                self.report(cpu, stop, out)?;
+            }
+            Command::Break(None) => {
+                for breakpoint in &self.breakpoints {
+                    match breakpoint.bank {
+                        Some(bank) => writeln!(
+                            out,
+                            "Breakpoint at ${:04X} in bank {:02X}",
+                            breakpoint.address, bank
+                        )?,
+                        None => writeln!(out, "Breakpoint at ${:04X}", breakpoint.address)?,
+                    }
+                }
+
+                for watchpoint in cpu.watchpoints() {
+                    let kind = match (watchpoint.read, watchpoint.write) {
+                        (true, true) => "read/write",
+                        (true, false) => "read",
+                        _ => "write",
+                    };
+                    writeln!(out, "Watchpoint on ${:04X} ({})", watchpoint.address, kind)?;
+                }
+            }
+            Command::Break(Some(breakpoint)) => {
+                if !self.breakpoints.contains(&breakpoint) {
+                    self.breakpoints.push(breakpoint);
+                }
+            }
+            Command::Watch(watchpoint) => cpu.add_watchpoint(watchpoint),
+            Command::Delete(address) => {
+                let count = self.breakpoints.len();
+                self.breakpoints
+                    .retain(|breakpoint| breakpoint.address != address);
+
+                let removed = cpu.remove_watchpoint(address) || self.breakpoints.len() != count;
+                if !removed {
+                    writeln!(out, "Nothing set on ${:04X}", address)?;
+                }
+            }
+            Command::Regs => Debugger::show_registers(cpu, out)?,
+            Command::Mem(address, length) => {
+                for row in (0..length).step_by(DUMP_WIDTH as usize) {
+                    let start = address.wrapping_add(row);
+                    let bytes: Vec<String> = (0..DUMP_WIDTH.min(length - row))
+                        .map(|i| format!("{:02X}", Debugger::peek(cpu, start.wrapping_add(i))))
+                        .collect();
+
+                    writeln!(out, "{:04X}  {}", start, bytes.join(" "))?;
+                }
+            }
+            Command::Disasm(address) => {
+                let mut address = address.unwrap_or(cpu.registers().pc.pointer.0);
+
+                for _ in 0..DISASSEMBLY_LINES {
+                    address = self.show_instruction(cpu, address, out)?;
+                }
+            }
+            Command::Set(register, value) => Debugger::set(cpu, register, value, out)?,
+            Command::Help => writeln!(out, "{}", HELP)?,
+            Command::Quit => (),
+        }
+
+        Ok(())
+    }
+
+    fn report<B: Bus>(&self, cpu: &mut CPU<B>, stop: Stop, out: &mut impl Write) -> io::Result<()> {
+        match stop {
+            Stop::Done => (),
+            Stop::Breakpoint(address) => writeln!(out, "Breakpoint at ${:04X}", address)?,
+            Stop::Watchpoint(Access::Read(address, value)) => {
+                writeln!(out, "Read ${:02X} from ${:04X}", value, address)?
+            }
+            Stop::Watchpoint(Access::Write(address, value)) => {
+                writeln!(out, "Wrote ${:02X} to ${:04X}", value, address)?
+            }
+            Stop::SoftwareBreakpoint => writeln!(out, "Software breakpoint (LD B,B)")?,
+            Stop::Locked(opcode) => {
+                writeln!(out, "Invalid opcode ${:02X}, the CPU locked up", opcode)?
+            }
+        }
+
+        self.show_location(cpu, out)
+    }
+
+    fn show_location<B: Bus>(&self, cpu: &mut CPU<B>, out: &mut impl Write) -> io::Result<()> {
+        let pc = cpu.registers().pc.pointer.0;
+        self.show_instruction(cpu, pc, out)?;
+
+        Ok(())
+    }
+
+    // Prints the instruction at `address` and returns the address of the next one
+    fn show_instruction<B: Bus>(
+        &self,
+        cpu: &mut CPU<B>,
+        address: u16,
+        out: &mut impl Write,
+    ) -> io::Result<u16> {
+        // Instructions are at most 3 bytes long
+        let memory = [0, 1, 2].map(|i| Debugger::peek(cpu, address.wrapping_add(i)));
+        let bus = cpu.bus();
+
+        if let Some(label) = self.symbols.label(bus.rom_bank(address), address) {
+            writeln!(out, "{}:", label)?;
+        }
+
+        let line = disassemble_with(
+            address,
+            |addr| memory[addr.wrapping_sub(address) as usize],
+            |target| {
+                self.symbols
+                    .label(bus.rom_bank(target), target)
+                    .map(str::to_string)
+            },
+        );
+        let bytes: Vec<String> = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+
+        writeln!(out, "{:04X}  {:<9}  {}", address, bytes.join(" "), line)?;
+
+        Ok(address.wrapping_add(line.length()))
+    }
+
+    fn show_registers<B: Bus>(cpu: &mut CPU<B>, out: &mut impl Write) -> io::Result<()> {
+        let ime = cpu.ime();
+        let cycles = cpu.cycles();
+        let registers = cpu.registers();
+        let [a, f] = registers.read(Reg16::AF).to_be_bytes();
+        let flags: String = ["Z", "N", "H", "C"]
+            .iter()
+            .enumerate()
+            .map(|(i, flag)| match f & (0x80 >> i) {
+                0 => "-",
+                _ => flag,
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+            a,
+            f,
+            registers.read(Reg8::B),
+            registers.read(Reg8::C),
+            registers.read(Reg8::D),
+            registers.read(Reg8::E),
+            registers.read(Reg8::H),
+            registers.read(Reg8::L),
+            registers.sp.pointer.0,
+            registers.pc.pointer.0,
+        )?;
+        writeln!(
+            out,
+            "Flags: {}  IME: {}  Cycles: {}",
+            flags, ime as u8, cycles
+        )
+    }
+
+    fn set<B: Bus>(
+        cpu: &mut CPU<B>,
+        register: Register,
+        value: u16,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let registers = cpu.registers();
+
+        match register {
+            Register::R8(register) => match u8::try_from(value) {
+                Ok(value) => registers.write(register, value),
+                Err(_) => writeln!(out, "Value doesn't fit in {}: ${:04X}", register, value)?,
+            },
+            Register::R16(register) => registers.write(register, value),
+            Register::SP => registers.sp.pointer.0 = value,
+            Register::PC => registers.pc.pointer.0 = value,
+        }
+
+        Ok(())
+    }
+
+    // Whether the next instruction is a return
+    fn at_return<B: Bus>(cpu: &mut CPU<B>) -> bool {
+        let pc = cpu.registers().pc.pointer.0;
+
+        matches!(cpu.mode(), Mode::Running) && RETURNS.contains(&Debugger::peek(cpu, pc))
+    }
+
+    // Looking at memory doesn't take any time
+    fn peek<B: Bus>(cpu: &mut CPU<B>, address: u16) -> u8 {
+        cpu.bus().read(address as usize)
+    }
+}
+
+// Steps through instructions until `done` returns true after one of them, or something
+// else stops execution first. A breakpoint on the current instruction doesn't count, so
+// it's possible to continue from one.
+pub(crate) fn resume<B: Bus>(
+    cpu: &mut CPU<B>,
+    breakpoints: &[Breakpoint],
+    mut done: impl FnMut(&mut CPU<B>) -> bool,
+) -> Stop {
+    cpu.take_breakpoint();
+    cpu.take_watchpoint();
+
+    let mut first = true;
+
+    loop {
+        if let Mode::Locked(opcode) = cpu.mode() {
+            return Stop::Locked(*opcode);
+        }
+
+        let pc = cpu.registers().pc.pointer.0;
+        let bank = cpu.bus().rom_bank(pc);
+        let hit = breakpoints.iter().any(|breakpoint| {
+            breakpoint.address == pc && breakpoint.bank.is_none_or(|b| b == bank)
+        });
+
+        if !first && hit {
+            return Stop::Breakpoint(pc);
+        }
+        first = false;
+
+        cpu.step();
+
+        if let Some(access) = cpu.take_watchpoint() {
+            return Stop::Watchpoint(access);
+        }
+
+        if cpu.take_breakpoint() {
+            return Stop::SoftwareBreakpoint;
+        }
+
+        if done(cpu) {
+            return Stop::Done;
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::io::{self, Write};
+
+use crate::cartridge::header::CartridgeHeader;
+use crate::cartridge::mbc::{self, ROM_BANK_SIZE};
+use crate::cpu::disassembler;
+use crate::symbols::Symbols;
+
+// Addresses with a special meaning to the hardware, see
+// https://gbdev.io/pandocs/Memory_Map.html#jump-vectors-in-first-rom-bank
+const VECTORS: [(u16, &str); 14] = [
+    (0x00, "RST $00"),
+    (0x08, "RST $08"),
+    (0x10, "RST $10"),
+    (0x18, "RST $18"),
+    (0x20, "RST $20"),
+    (0x28, "RST $28"),
+    (0x30, "RST $30"),
+    (0x38, "RST $38"),
+    (0x40, "VBlank interrupt"),
+    (0x48, "STAT interrupt"),
+    (0x50, "Timer interrupt"),
+    (0x58, "Serial interrupt"),
+    (0x60, "Joypad interrupt"),
+    (0x100, "Entry point"),
+];
+
+// The cartridge header holds data rather than code, see
+// https://gbdev.io/pandocs/The_Cartridge_Header.html
+const HEADER_FIELDS: [(u16, u16, &str); 15] = [
+    (0x104, 0x114, "Nintendo logo"),
+    (0x114, 0x124, "Nintendo logo"),
+    (0x124, 0x134, "Nintendo logo"),
+    (0x134, 0x143, "Title"),
+    (0x143, 0x144, "CGB flag"),
+    (0x144, 0x146, "New licensee code"),
+    (0x146, 0x147, "SGB flag"),
+    (0x147, 0x148, "Cartridge type"),
+    (0x148, 0x149, "ROM size"),
+    (0x149, 0x14A, "RAM size"),
+    (0x14A, 0x14B, "Destination code"),
+    (0x14B, 0x14C, "Old licensee code"),
+    (0x14C, 0x14D, "Mask ROM version number"),
+    (0x14D, 0x14E, "Header checksum"),
+    (0x14E, 0x150, "Global checksum"),
+];
+
+// Widths of the columns holding the instruction bytes and the instruction itself
+const BYTES_WIDTH: usize = 9;
+const TEXT_WIDTH: usize = 20;
+
+/// Prints the ROM's header followed by an annotated listing of `start..end`, where both are
+/// offsets into the ROM file. By default the listing covers the bank containing `start`.
+/// Addresses with a symbol are shown by name.
+pub fn run(
+    header: &CartridgeHeader,
+    symbols: &Symbols,
+    start: Option<usize>,
+    end: Option<usize>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let rom = &header.data;
+    let start = start.unwrap_or(0).min(rom.len());
+    let end = end
+        .unwrap_or((start / ROM_BANK_SIZE + 1) * ROM_BANK_SIZE)
+        .min(rom.len());
+
+    write_header(header, out)?;
+
+    let mut offset = start;
+
+    while offset < end {
+        let bank = offset / ROM_BANK_SIZE;
+        let address = cpu_address(offset);
+        let location = format!("{:02X}:{:04X}", bank, address);
+
+        if let Some(label) = symbols.label(bank as u16, address) {
+            writeln!(out, "{}:", label)?;
+        }
+
+        if let Some((_, field_end, name)) = HEADER_FIELDS
+            .iter()
+            .find(|(field_start, _, _)| offset == *field_start as usize)
+        {
+            let length = (*field_end - address) as usize;
+            let data: Vec<String> = rom[offset..offset + length]
+                .iter()
+                .map(|byte| format!("${:02X}", byte))
+                .collect();
+
+            let text = format!("DB {}", data.join(","));
+            write_line(out, &location, "", &text, Some(name))?;
+            offset += length;
+            continue;
+        }
+
+        let line = disassembler::disassemble_with(
+            address,
+            |addr| {
+                let index = offset + addr.wrapping_sub(address) as usize;
+                rom.get(index).copied().unwrap_or(0xFF)
+            },
+            |target| {
+                symbols
+                    .label(target_bank(bank, target), target)
+                    .map(str::to_string)
+            },
+        );
+
+        let bytes: Vec<String> = line
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let comment = VECTORS
+            .iter()
+            .find(|(vector, _)| offset == *vector as usize)
+            .map(|(_, name)| *name);
+
+        write_line(out, &location, &bytes.join(" "), &line.to_string(), comment)?;
+
+        offset += line.length() as usize;
+    }
+
+    Ok(())
+}
+
+fn write_line(
+    out: &mut impl Write,
+    location: &str,
+    bytes: &str,
+    text: &str,
+    comment: Option<&str>,
+) -> io::Result<()> {
+    match comment {
+        Some(comment) => writeln!(
+            out,
+            "{}  {:<bw$}  {:<tw$}  ; {}",
+            location,
+            bytes,
+            text,
+            comment,
+            bw = BYTES_WIDTH,
+            tw = TEXT_WIDTH
+        ),
+        None => writeln!(
+            out,
+            "{}  {:<w$}  {}",
+            location,
+            bytes,
+            text,
+            w = BYTES_WIDTH
+        ),
+    }
+}
+
+// Bank 0 is always mapped at 0x0000, while every other bank shows up at 0x4000
+fn cpu_address(offset: usize) -> u16 {
+    match offset / ROM_BANK_SIZE {
+        0 => offset as u16,
+        _ => (ROM_BANK_SIZE + offset % ROM_BANK_SIZE) as u16,
+    }
+}
+
+// Code in bank 0 can't know which bank is switched in, so it's assumed to be bank 1
+fn target_bank(bank: usize, target: u16) -> u16 {
+    match target as usize {
+        0..ROM_BANK_SIZE => 0,
+        ROM_BANK_SIZE..0x8000 => bank.max(1) as u16,
+        _ => 0,
+    }
+}
+
+fn write_header(header: &CartridgeHeader, out: &mut impl Write) -> io::Result<()> {
+    let ram_size = mbc::ram_size(header.ram_size).map_or(0, |size| size / 1024);
+
+    writeln!(out, "; Title:           {}", header.name())?;
+    writeln!(
+        out,
+        "; Cartridge type:  ${:02X} ({})",
+        header.cartridge_type,
+        mbc::type_name(header.cartridge_type)
+    )?;
+    writeln!(
+        out,
+        "; ROM size:        ${:02X} ({} KiB)",
+        header.rom_size,
+        32 << header.rom_size
+    )?;
+    writeln!(
+        out,
+        "; RAM size:        ${:02X} ({} KiB)",
+        header.ram_size, ram_size
+    )?;
+    writeln!(
+        out,
+        "; CGB flag:        ${:02X}",
+        header.cgb_flag.unwrap_or(0)
+    )?;
+    writeln!(out, "; SGB flag:        ${:02X}", header.sgb_flag)?;
+    writeln!(out, "; Destination:     ${:02X}", header.destination_code)?;
+    writeln!(
+        out,
+        "; Version:         ${:02X}",
+        header.mask_rom_version_number
+    )?;
+    writeln!(out, "; Header checksum: ${:02X}", header.header_checksum)?;
+    writeln!(
+        out,
+        "; Global checksum: ${:04X}",
+        u16::from_be_bytes(header.global_checksum)
+    )?;
+    writeln!(out)
+}
@@ -0,0 +1,475 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+use crate::cpu::registers::{Reg16, Reg8};
+use crate::cpu::{Access, Watchpoint, CPU};
+use crate::debugger::{self, Breakpoint, Stop};
+use crate::memory::bus::Bus;
+use crate::utils::traits::Storage;
+
+// Describes the register file, since GDB has no built in SM83 support. Registers are numbered in
+// the order they appear here, see https://sourceware.org/gdb/current/onlinedocs/gdb.html/Target-Descriptions.html
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="f" bitsize="8" type="uint8"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const REGISTERS: usize = 10;
+
+// The 8-bit registers, in the target description's order. F comes right after A
+const REG8: [Reg8; 6] = [Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L];
+
+// Sent by GDB to interrupt the target while it's running
+const INTERRUPT: u8 = 0x03;
+
+// Instructions executed between checks for an interrupt from GDB
+const POLL_INTERVAL: u32 = 4096;
+
+// Signals used in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/// Waits for a debugger to connect on localhost, then serves it until it detaches.
+pub fn serve<B: Bus>(cpu: &mut CPU<B>, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    eprintln!("Waiting for GDB on {}", listener.local_addr()?);
+
+    let (stream, _) = listener.accept()?;
+
+    GdbStub::new(stream).run(cpu)
+}
+
+/// Speaks GDB's remote serial protocol over a connection, see
+/// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+#[derive(Debug)]
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: Vec<Breakpoint>,
+    // Acknowledgements are turned off by QStartNoAckMode
+    acks: bool,
+    // Bytes other than interrupts which arrived while the target was running
+    pending: VecDeque<u8>,
+    // Set when the connection closes while the target is running
+    disconnected: bool,
+}
+
+impl GdbStub {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            breakpoints: Vec::new(),
+            acks: true,
+            pending: VecDeque::new(),
+            disconnected: false,
+        }
+    }
+
+    /// Handles packets until the debugger detaches, kills the target or disconnects. LD B,B
+    /// acts as a breakpoint while attached.
+    pub fn run<B: Bus>(&mut self, cpu: &mut CPU<B>) -> io::Result<()> {
+        cpu.set_software_breakpoints(true);
+
+        while let Some(packet) = self.read_packet()? {
+            match packet.as_bytes().first() {
+                Some(b'D') => return self.write_packet("OK"),
+                Some(b'k') => return Ok(()),
+                _ => {
+                    let reply = self.handle(cpu, &packet);
+                    if self.disconnected {
+                        return Ok(());
+                    }
+                    self.write_packet(&reply)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle<B: Bus>(&mut self, cpu: &mut CPU<B>, packet: &str) -> String {
+        let (command, args) = packet.split_at(packet.len().min(1));
+
+        let reply = match command {
+            "?" => format!("S{:02X}", SIGTRAP),
+            "g" => (0..REGISTERS)
+                .map(|register| GdbStub::read_register(cpu, register))
+                .collect(),
+            "G" => {
+                let mut args = args;
+
+                for register in 0..REGISTERS {
+                    let width = if register < 8 { 2 } else { 4 };
+                    let Some(value) = args.get(..width) else {
+                        return "E01".to_string();
+                    };
+
+                    GdbStub::write_register(cpu, register, value);
+                    args = &args[width..];
+                }
+
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < REGISTERS => GdbStub::read_register(cpu, register),
+                _ => "E01".to_string(),
+            },
+            "P" => match args.split_once('=') {
+                Some((register, value)) => match usize::from_str_radix(register, 16) {
+                    Ok(register) if register < REGISTERS => {
+                        GdbStub::write_register(cpu, register, value);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                },
+                None => "E01".to_string(),
+            },
+            "m" => match GdbStub::range(args) {
+                Some((address, length)) => (0..length)
+                    .map(|i| format!("{:02x}", cpu.bus().read(address.wrapping_add(i) as usize)))
+                    .collect(),
+                None => "E01".to_string(),
+            },
+            "M" => match args.split_once(':') {
+                Some((range, data)) => match (GdbStub::range(range), hex_bytes(data)) {
+                    (Some((address, _)), Some(bytes)) => {
+                        for (i, byte) in bytes.iter().enumerate() {
+                            cpu.bus()
+                                .write(address.wrapping_add(i as u16) as usize, *byte);
+                        }
+
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                },
+                None => "E01".to_string(),
+            },
+            "Z" | "z" => self.set_breakpoint(cpu, command == "Z", args),
+            "s" | "c" => {
+                // Both can resume at a different address
+                if let Ok(address) = u16::from_str_radix(args, 16) {
+                    cpu.registers().pc.pointer.0 = address;
+                }
+
+                match command {
+                    "s" => {
+                        let stop = debugger::resume(cpu, &self.breakpoints, |_| true);
+                        GdbStub::stop_reply(cpu, stop)
+                    }
+                    // Only an interrupt ends a continue without anything else stopping it
+                    _ => match self.resume(cpu) {
+                        Stop::Done => format!("S{:02X}", SIGINT),
+                        stop => GdbStub::stop_reply(cpu, stop),
+                    },
+                }
+            }
+            "q" | "Q" | "v" | "H" => GdbStub::query(packet),
+            _ => String::new(),
+        };
+
+        if packet == "QStartNoAckMode" {
+            self.acks = false;
+        }
+
+        reply
+    }
+
+    // Runs until something stops execution, GDB interrupts it or the connection closes.
+    // Anything else GDB sends in the meantime is kept for `read_packet`.
+    fn resume<B: Bus>(&mut self, cpu: &mut CPU<B>) -> Stop {
+        let mut steps = 0;
+        let stream = &mut self.stream;
+        let pending = &mut self.pending;
+        let disconnected = &mut self.disconnected;
+
+        debugger::resume(cpu, &self.breakpoints, |_| {
+            steps += 1;
+
+            if steps % POLL_INTERVAL != 0 {
+                return false;
+            }
+
+            match GdbStub::poll(stream) {
+                Ok(Some(INTERRUPT)) => true,
+                Ok(Some(byte)) => {
+                    pending.push_back(byte);
+                    false
+                }
+                Ok(None) => false,
+                // There's no one left to report to either way
+                Err(_) => {
+                    *disconnected = true;
+                    true
+                }
+            }
+        })
+    }
+
+    // Reads a byte without waiting for one, a closed connection being an error
+    fn poll(stream: &mut TcpStream) -> io::Result<Option<u8>> {
+        stream.set_nonblocking(true)?;
+
+        let mut byte = [0];
+        let result = match stream.read(&mut byte) {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(error),
+        };
+
+        stream.set_nonblocking(false)?;
+        result
+    }
+
+    fn stop_reply<B: Bus>(cpu: &mut CPU<B>, stop: Stop) -> String {
+        match stop {
+            Stop::Done | Stop::Breakpoint(_) | Stop::SoftwareBreakpoint => {
+                format!("S{:02X}", SIGTRAP)
+            }
+            Stop::Watchpoint(access) => {
+                let address = match access {
+                    Access::Read(address, _) | Access::Write(address, _) => address,
+                };
+                let kind = match cpu
+                    .watchpoints()
+                    .iter()
+                    .find(|watchpoint| watchpoint.address == address)
+                {
+                    Some(Watchpoint {
+                        read: true,
+                        write: true,
+                        ..
+                    }) => "awatch",
+                    Some(Watchpoint { read: true, .. }) => "rwatch",
+                    _ => "watch",
+                };
+
+                format!("T{:02X}{}:{:04x};", SIGTRAP, kind, address)
+            }
+            Stop::Locked(_) => format!("S{:02X}", SIGILL),
+        }
+    }
+
+    // Z/z<type>,<addr>,<kind>, where the types are software and hardware breakpoints followed by
+    // write, read and access watchpoints
+    fn set_breakpoint<B: Bus>(&mut self, cpu: &mut CPU<B>, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(Ok(address))) = (
+            fields.next(),
+            fields
+                .next()
+                .map(|address| u16::from_str_radix(address, 16)),
+        ) else {
+            return "E01".to_string();
+        };
+
+        match kind {
+            "0" | "1" => {
+                self.breakpoints
+                    .retain(|breakpoint| breakpoint.address != address);
+
+                if insert {
+                    self.breakpoints.push(Breakpoint {
+                        address,
+                        bank: None,
+                    });
+                }
+            }
+            "2" | "3" | "4" => {
+                let (read, write) = match kind {
+                    "2" => (false, true),
+                    "3" => (true, false),
+                    _ => (true, true),
+                };
+                let mut watchpoint = cpu
+                    .watchpoints()
+                    .iter()
+                    .find(|watchpoint| watchpoint.address == address)
+                    .copied()
+                    .unwrap_or(Watchpoint {
+                        address,
+                        read: false,
+                        write: false,
+                    });
+
+                if insert {
+                    watchpoint.read |= read;
+                    watchpoint.write |= write;
+                } else {
+                    watchpoint.read &= !read;
+                    watchpoint.write &= !write;
+                }
+
+                match watchpoint.read || watchpoint.write {
+                    true => cpu.add_watchpoint(watchpoint),
+                    false => {
+                        cpu.remove_watchpoint(address);
+                    }
+                }
+            }
+            _ => return String::new(),
+        }
+
+        "OK".to_string()
+    }
+
+    fn query(packet: &str) -> String {
+        if let Some(annex) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = GdbStub::range(annex) else {
+                return "E01".to_string();
+            };
+
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = (start + length as usize).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+
+            return format!("{}{}", marker, &TARGET_XML[start..end]);
+        }
+
+        let name = packet.split([':', ';', ',']).next().unwrap_or_default();
+
+        match name {
+            "qSupported" => "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string(),
+            "QStartNoAckMode" => "OK".to_string(),
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ if name.starts_with('H') => "OK".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // Registers are sent in target byte order, which is little endian
+    fn read_register<B: Bus>(cpu: &mut CPU<B>, register: usize) -> String {
+        let registers = cpu.registers();
+        let [a, f] = registers.read(Reg16::AF).to_be_bytes();
+
+        match register {
+            0 => format!("{:02x}", a),
+            1 => format!("{:02x}", f),
+            2..=7 => format!("{:02x}", registers.read(REG8[register - 2])),
+            8 => hex_word(registers.sp.pointer.0),
+            _ => hex_word(registers.pc.pointer.0),
+        }
+    }
+
+    fn write_register<B: Bus>(cpu: &mut CPU<B>, register: usize, value: &str) {
+        let Some(bytes) = hex_bytes(value) else {
+            return;
+        };
+        let registers = cpu.registers();
+        let [a, f] = registers.read(Reg16::AF).to_be_bytes();
+        let byte = bytes.first().copied().unwrap_or(0);
+        let word = u16::from_le_bytes([byte, bytes.get(1).copied().unwrap_or(0)]);
+
+        match register {
+            0 => registers.write(Reg16::AF, u16::from_be_bytes([byte, f])),
+            1 => registers.write(Reg16::AF, u16::from_be_bytes([a, byte])),
+            2..=7 => registers.write(REG8[register - 2], byte),
+            8 => registers.sp.pointer.0 = word,
+            _ => registers.pc.pointer.0 = word,
+        }
+    }
+
+    // <addr>,<length> in hexadecimal
+    fn range(args: &str) -> Option<(u16, u16)> {
+        let (address, length) = args.split_once(',')?;
+
+        Some((
+            u16::from_str_radix(address, 16).ok()?,
+            u16::from_str_radix(length, 16).ok()?,
+        ))
+    }
+
+    // Packets look like $<data>#<checksum>, anything outside of them (acknowledgements, stray
+    // interrupts) is skipped. Returns None once the connection is closed.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+
+            if byte != b'$' {
+                continue;
+            }
+
+            let mut data = Vec::new();
+
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let (Some(high), Some(low)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let valid = std::str::from_utf8(&[high, low])
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum(&data));
+
+            if self.acks {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+
+        let mut byte = [0];
+
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+        self.stream.flush()
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex_word(value: u16) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,65 @@
+use std::io;
+use std::path::Path;
+
+use crate::cpu::CPU;
+use crate::movie::Movie;
+use crate::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::utils::png;
+
+// The DMG's four shades mapped to 8-bit grays, lightest first
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// Runs the emulator without any display for a number of frames, optionally saving the last
+/// frame as a PNG.
+pub fn run(cpu: &mut CPU, frames: u64, screenshot: Option<&Path>) -> io::Result<()> {
+    for _ in 0..frames {
+        cpu.run_frame();
+    }
+
+    finish(cpu, screenshot)
+}
+
+/// Like `run`, appending the buttons held during every frame to a movie.
+pub fn record(
+    cpu: &mut CPU,
+    frames: u64,
+    movie: &mut Movie,
+    screenshot: Option<&Path>,
+) -> io::Result<()> {
+    for _ in 0..frames {
+        movie.record(cpu);
+        cpu.run_frame();
+    }
+
+    finish(cpu, screenshot)
+}
+
+/// Runs every frame of a movie with its buttons held. The machine has to be where the movie
+/// starts, see `Movie::start`.
+pub fn play(cpu: &mut CPU, movie: &Movie, screenshot: Option<&Path>) -> io::Result<()> {
+    for frame in 0..movie.frames.len() {
+        movie.play_frame(cpu, frame);
+    }
+
+    finish(cpu, screenshot)
+}
+
+fn finish(cpu: &mut CPU, screenshot: Option<&Path>) -> io::Result<()> {
+    if let Some(path) = screenshot {
+        save_screenshot(cpu.bus().ppu(), path)?;
+    }
+
+    Ok(())
+}
+
+pub fn save_screenshot(ppu: &Ppu, path: &Path) -> io::Result<()> {
+    png::write_grayscale(path, SCREEN_WIDTH, SCREEN_HEIGHT, &grayscale(ppu))
+}
+
+/// The current frame as one 8-bit gray value per pixel.
+pub fn grayscale(ppu: &Ppu) -> Vec<u8> {
+    ppu.frame_buffer()
+        .iter()
+        .map(|shade| SHADES[*shade as usize])
+        .collect()
+}
@@ -0,0 +1,180 @@
+use crate::cpu::interrupts::Interrupt;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/Joypad_Input.html
+const P1: usize = 0xFF00;
+
+// Writing 0 to one of these bits selects the buttons wired to it
+const SELECT_DPAD: u8 = 0b0001_0000;
+const SELECT_BUTTONS: u8 = 0b0010_0000;
+const SELECT_MASK: u8 = SELECT_DPAD | SELECT_BUTTONS;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // The d-pad and the other buttons share the four input lines, in this order
+    fn mask(self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0b0001,
+            Button::Left | Button::B => 0b0010,
+            Button::Up | Button::Select => 0b0100,
+            Button::Down | Button::Start => 0b1000,
+        }
+    }
+
+    fn is_dpad(self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Joypad {
+    // Bits 4 and 5 of P1, which are the only ones that can be written
+    select: u8,
+    // Pressed buttons, one bit per input line, set when pressed
+    dpad: u8,
+    buttons: u8,
+
+    // Interrupts raised since the last check
+    interrupts: u8,
+}
+
+impl Storage<usize, u8> for Joypad {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            P1 => 0xC0 | self.select | self.lines(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        if dest == P1 {
+            self.update(|joypad| joypad.select = value & SELECT_MASK);
+        }
+    }
+}
+
+impl Snapshot for Joypad {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_u8(self.select);
+        state.write_u8(self.dpad);
+        state.write_u8(self.buttons);
+        // A button pressed in between frames only gets its interrupt through on the next tick
+        state.write_u8(self.interrupts);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.select = state.read_u8()? & SELECT_MASK;
+        self.dpad = state.read_u8()? & 0x0F;
+        self.buttons = state.read_u8()? & 0x0F;
+        self.interrupts = state.read_u8()? & Interrupt::Joypad.mask();
+
+        Ok(())
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self {
+            select: SELECT_MASK,
+            dpad: 0,
+            buttons: 0,
+            interrupts: 0,
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.update(|joypad| {
+            let group = match button.is_dpad() {
+                true => &mut joypad.dpad,
+                false => &mut joypad.buttons,
+            };
+
+            match pressed {
+                true => *group |= button.mask(),
+                false => *group &= !button.mask(),
+            }
+        });
+    }
+
+    /// Every button held, as a mask with one bit per button in `Button::ALL` order.
+    pub fn held(&self) -> u8 {
+        Button::ALL
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| self.is_pressed(**button))
+            .fold(0, |held, (i, _)| held | 1 << i)
+    }
+
+    /// Presses and releases buttons all at once, from a mask returned by `held`.
+    pub fn set_held(&mut self, held: u8) {
+        for (i, button) in Button::ALL.iter().enumerate() {
+            self.set_button(*button, held & 1 << i != 0);
+        }
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        let group = match button.is_dpad() {
+            true => self.dpad,
+            false => self.buttons,
+        };
+
+        group & button.mask() != 0
+    }
+
+    /// Returns the interrupts requested since the last call, as a mask of IF bits.
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.interrupts)
+    }
+
+    // The input lines as they read in P1, low while a button on a selected line is pressed
+    fn lines(&self) -> u8 {
+        let mut pressed = 0;
+
+        if self.select & SELECT_DPAD == 0 {
+            pressed |= self.dpad;
+        }
+
+        if self.select & SELECT_BUTTONS == 0 {
+            pressed |= self.buttons;
+        }
+
+        !pressed & 0x0F
+    }
+
+    // The interrupt fires when any of the input lines goes from high to low, which can be caused
+    // by pressing a button as well as by selecting a line on which one is already pressed
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let before = self.lines();
+        change(self);
+
+        if before & !self.lines() != 0 {
+            self.interrupts |= Interrupt::Joypad.mask();
+        }
+    }
+}
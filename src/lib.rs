@@ -0,0 +1,21 @@
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::new_without_default)]
+
+pub mod boot;
+pub mod cartridge;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
+pub mod headless;
+pub mod joypad;
+pub mod memory;
+pub mod movie;
+pub mod ppu;
+pub mod rewind;
+pub mod serial;
+pub mod signal;
+pub mod state;
+pub mod symbols;
+pub mod timer;
+pub mod utils;
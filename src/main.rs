@@ -1,43 +1,191 @@
-#![allow(dead_code)]
-
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
 use std::path::Path;
 
-mod cartridge;
-mod cpu;
-mod memory;
-mod utils;
+use emulator::boot::{BootRom, Model};
+use emulator::cartridge::header::CartridgeHeader;
+use emulator::cartridge::mbc;
+use emulator::cartridge::save::SaveFile;
+use emulator::cpu::registers::Registers;
+use emulator::cpu::trace::Trace;
+use emulator::cpu::CPU;
+use emulator::debugger::Debugger;
+use emulator::memory::bus::MemoryBus;
+use emulator::movie::Movie;
+use emulator::symbols::Symbols;
+use emulator::{disasm, gdb, headless, signal, state};
+
+mod cli;
+
+use cli::{Command, Options};
+
+// How often battery backed RAM gets written back to disk, about once a second
+const SAVE_INTERVAL: u64 = 60;
 
-use cartridge::header::{CartridgeError, CartridgeHeader};
-use cpu::CPU;
-use memory::bus::MemoryBus;
-use utils::traits::Storage;
+fn main() -> Result<(), Box<dyn Error>> {
+    let command = match Command::parse(std::env::args()) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
 
-fn main() -> Result<(), Box<CartridgeError>> {
-    let args: Vec<String> = std::env::args().collect();
+    match command {
+        Command::Run(options) => run(options),
+        Command::Disassemble {
+            rom_path,
+            start,
+            end,
+        } => {
+            let header = CartridgeHeader::load(&rom_path)?;
+            let symbols = load_symbols(&rom_path)?;
+            disasm::run(&header, &symbols, start, end, &mut io::stdout().lock())?;
 
-    match args.len() {
-        2 => {
-            println!("Usage: {} <rom_path>", args[1]);
+            Ok(())
         }
-        _ => panic!("Invalid number of arguments"),
     }
+}
 
-    let rom_path = Path::new(&args[1]);
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let header = CartridgeHeader::load(&options.rom_path)?;
+    let battery = mbc::has_battery(header.cartridge_type);
+    let checksum = header.global_checksum;
+    let boot_rom = options.boot_rom.as_deref().map(BootRom::load).transpose()?;
+    let movie = options.movie.as_deref().map(Movie::load).transpose()?;
+    // Movies play back on the model they were recorded on, otherwise the boot ROM tells the DMG
+    // and CGB apart
+    let model = movie
+        .as_ref()
+        .map(|movie| movie.model)
+        .or(options.model)
+        .or(boot_rom.as_ref().map(BootRom::model))
+        .unwrap_or_default();
+
+    if let Some(boot_rom) = &boot_rom {
+        if !boot_rom.runs_on(model) {
+            return Err(format!("The boot ROM doesn't run on the {} model", model.name()).into());
+        }
+    }
+    let mut recording = options
+        .record
+        .as_ref()
+        .map(|_| Movie::new(&header, model, boot_rom.as_ref(), None));
+    let registers = match boot_rom {
+        Some(_) => Registers::power_on(),
+        None => Registers::post_boot(model, &header),
+    };
+    let cgb_mode = model == Model::Cgb && header.cgb_flag.is_some();
+    let mut cartridge = mbc::new(header)?;
 
-    let cartridge: Vec<u8> = CartridgeHeader::load(rom_path)?.into();
+    let mut save_file = if battery && recording.is_none() && movie.is_none() {
+        let mut save_file = SaveFile::new(&options.rom_path, options.save_dir.as_deref());
+        save_file.load(cartridge.as_mut())?;
+        Some(save_file)
+    } else {
+        None
+    };
 
-    let mut memory_bus = MemoryBus::new();
+    let mut memory_bus = MemoryBus::new(cartridge);
+    memory_bus.set_cgb_mode(cgb_mode);
+    memory_bus.set_doctor_mode(options.doctor);
 
-    for (i, byte) in cartridge.iter().enumerate() {
-        memory_bus.write(i, *byte);
+    match boot_rom {
+        Some(boot_rom) => memory_bus.set_boot_rom(boot_rom),
+        None => memory_bus.skip_boot(model),
     }
 
+    // Test ROMs report their results over the link cable
+    memory_bus.serial_mut().set_sink(|byte| {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+    });
+
+    let symbols = load_symbols(&options.rom_path)?;
     let mut cpu = CPU::new(&mut memory_bus);
+    *cpu.registers() = registers;
+
+    if let Some(path) = &options.trace {
+        let mut trace = Trace::new(BufWriter::new(File::create(path)?));
+        if options.trace_labels {
+            trace.set_symbols(symbols.clone());
+        }
+        cpu.set_trace(trace);
+    }
+
+    if let Some(slot) = options.load_state {
+        let path = state::slot_path(&options.rom_path, options.save_dir.as_deref(), slot);
+        let state = fs::read(path)?;
+        cpu.load_state(&state, checksum)?;
+
+        if let Some(recording) = recording.as_mut() {
+            recording.state = Some(state);
+        }
+    }
+
+    if let Some(movie) = &movie {
+        movie.start(&mut cpu, checksum)?;
+    }
+
+    if options.debug {
+        // Ctrl-C drops back to the prompt when continuing
+        signal::install();
+        let mut debugger = Debugger::new();
+        debugger.set_symbols(symbols);
+        debugger.run(&mut cpu, io::stdin().lock(), &mut io::stdout())?;
+    } else if let Some(port) = options.gdb {
+        gdb::serve(&mut cpu, port)?;
+    } else if let Some(movie) = &movie {
+        headless::play(&mut cpu, movie, options.screenshot.as_deref())?;
+    } else if let (Some(recording), Some(path)) = (recording.as_mut(), &options.record) {
+        headless::record(
+            &mut cpu,
+            options.frames,
+            recording,
+            options.screenshot.as_deref(),
+        )?;
+        recording.save(path)?;
+    } else if options.headless {
+        headless::run(&mut cpu, options.frames, options.screenshot.as_deref())?;
+    } else {
+        // Ctrl-C stops the emulator, falling through to the saves below
+        signal::install();
+        let mut frames: u64 = 0;
+
+        while !signal::take() {
+            cpu.run_frame();
+            frames += 1;
+
+            if let Some(save_file) = save_file.as_mut() {
+                if frames.is_multiple_of(SAVE_INTERVAL) {
+                    save_file.flush(cpu.bus().cartridge())?;
+                }
+            }
+        }
+    }
+
+    cpu.flush_trace()?;
+
+    if let Some(slot) = options.save_state {
+        let path = state::slot_path(&options.rom_path, options.save_dir.as_deref(), slot);
+        state::write(&path, &cpu.save_state(checksum))?;
+    }
+
+    if let Some(save_file) = save_file.as_mut() {
+        save_file.flush(cpu.bus().cartridge())?;
+    }
+
+    Ok(())
+}
 
-    loop {
-        cpu.step();
+// Symbols are optional, and only picked up when there's a .sym file next to the ROM
+fn load_symbols(rom_path: &Path) -> io::Result<Symbols> {
+    let path = Symbols::path(rom_path);
 
-        // // Uncomment to step through the program
-        // std::io::stdin().read_line(&mut String::new()).unwrap();
+    match path.exists() {
+        true => Symbols::load(&path),
+        false => Ok(Symbols::new()),
     }
 }
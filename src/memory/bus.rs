@@ -1,17 +1,218 @@
+use std::fmt::Debug;
+
+use super::io::IoRegisters;
+use super::ram::Ram;
+use crate::boot::{BootRom, Model};
+use crate::cartridge::mbc::Mapper;
+use crate::cpu::interrupts::Interrupt;
+use crate::joypad::{Button, Joypad};
+use crate::ppu::Ppu;
+use crate::serial::Serial;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::timer::Timer;
 use crate::utils::traits::Storage;
 
+// The memory map is described here:
+// https://gbdev.io/pandocs/Memory_Map.html
+const ROM_END: usize = 0x7FFF;
+const VRAM_START: usize = 0x8000;
+const VRAM_END: usize = 0x9FFF;
+const EXTERNAL_RAM_START: usize = 0xA000;
+const EXTERNAL_RAM_END: usize = 0xBFFF;
+const WRAM_START: usize = 0xC000;
+const WRAM_END: usize = 0xDFFF;
+const ECHO_RAM_START: usize = 0xE000;
+const ECHO_RAM_END: usize = 0xFDFF;
+const OAM_START: usize = 0xFE00;
+const OAM_END: usize = 0xFE9F;
+const UNUSABLE_START: usize = 0xFEA0;
+const UNUSABLE_END: usize = 0xFEFF;
+const IO_START: usize = 0xFF00;
+const IO_END: usize = 0xFF7F;
+const HRAM_START: usize = 0xFF80;
+const HRAM_END: usize = 0xFFFE;
+const INTERRUPT_ENABLE: usize = 0xFFFF;
+
+const JOYPAD: usize = 0xFF00;
+const INTERRUPT_FLAG: usize = 0xFF0F;
+const DMA: usize = 0xFF46;
+const SERIAL_START: usize = 0xFF01;
+const SERIAL_END: usize = 0xFF02;
+const TIMER_START: usize = 0xFF04;
+const TIMER_END: usize = 0xFF07;
+const LCD_START: usize = 0xFF40;
+const LCD_END: usize = 0xFF4B;
+const LY: usize = 0xFF44;
+const KEY1: usize = 0xFF4D;
+const BOOT_ROM_DISABLE: usize = 0xFF50;
+const SC: usize = 0xFF02;
+const NR52: usize = 0xFF26;
+
+// What the boot ROM leaves in the registers every model agrees on, see
+// https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers
+const POST_BOOT_REGISTERS: [(usize, u8); 35] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+];
+
+/// What the CPU is connected to. Every access takes one M-cycle, during which the rest of the
+/// system gets ticked.
+pub trait Bus: Storage<usize, u8> + Debug {
+    /// Advances every component driven by the system clock by a number of T-cycles.
+    fn tick(&mut self, cycles: u32);
+
+    /// The ROM bank mapped at `addr`, or 0 outside of ROM. Symbols are told apart by it.
+    fn rom_bank(&self, addr: u16) -> u16 {
+        match addr {
+            0x4000..=0x7FFF => 1,
+            _ => 0,
+        }
+    }
+
+    /// The interrupts which are both requested in IF and enabled in IE. The CPU is wired to these
+    /// directly, so on hardware checking them doesn't show up as a memory access.
+    fn pending_interrupts(&mut self) -> u8 {
+        let requests: u8 = self.read(INTERRUPT_FLAG);
+        let enabled: u8 = self.read(INTERRUPT_ENABLE);
+
+        requests & enabled & 0x1F
+    }
+
+    /// Clears the request of an interrupt the CPU started servicing.
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        let requests: u8 = self.read(INTERRUPT_FLAG);
+        self.write(INTERRUPT_FLAG, requests & !interrupt.mask());
+    }
+
+    /// Switches between normal and double speed if it was armed through KEY1, which is what STOP
+    /// does on the CGB instead of stopping. Returns whether it happened.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+}
+
 #[derive(Debug)]
 pub struct MemoryBus {
-    ram: [u8; 0xFFFF],
+    cartridge: Box<dyn Mapper>,
+    boot_rom: Option<BootRom>,
+    boot_rom_mapped: bool,
+    cgb_mode: bool,
+    doctor_mode: bool,
+    joypad: Joypad,
+    ppu: Ppu,
+    serial: Serial,
+    timer: Timer,
+    wram: Ram<0x2000>,
+    io: IoRegisters,
+    hram: Ram<0x7F>,
+    interrupt_enable: u8,
 }
 
+// P1 is at the very start of the I/O range, which clippy takes for an overlap
+#[allow(clippy::match_overlapping_arm)]
 impl Storage<usize, u8> for MemoryBus {
     fn read(&mut self, src: usize) -> u8 {
-        self.ram[src]
+        match src {
+            0..=ROM_END if self.boot_rom_mapped => {
+                match self
+                    .boot_rom
+                    .as_ref()
+                    .and_then(|boot_rom| boot_rom.read(src))
+                {
+                    Some(value) => value,
+                    None => self.cartridge.read(src),
+                }
+            }
+            0..=ROM_END | EXTERNAL_RAM_START..=EXTERNAL_RAM_END => self.cartridge.read(src),
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END => self.ppu.read(src),
+            WRAM_START..=WRAM_END => self.wram.read(src - WRAM_START),
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram.read(src - ECHO_RAM_START),
+            // The DMG returns 0x00 from the unusable region while OAM is accessible
+            UNUSABLE_START..=UNUSABLE_END => 0x00,
+            JOYPAD => self.joypad.read(src),
+            SERIAL_START..=SERIAL_END => self.serial.read(src),
+            TIMER_START..=TIMER_END => self.timer.read(src),
+            DMA => self.io.read(src - IO_START),
+            // The speed switch only exists in CGB mode
+            KEY1 if !self.cgb_mode => 0xFF,
+            LY if self.doctor_mode => 0x90,
+            LCD_START..=LCD_END => self.ppu.read(src),
+            IO_START..=IO_END => self.io.read(src - IO_START),
+            HRAM_START..=HRAM_END => self.hram.read(src - HRAM_START),
+            INTERRUPT_ENABLE => self.interrupt_enable,
+            _ => panic!("Address out of range: {:#06X}", src),
+        }
     }
 
     fn write(&mut self, dest: usize, value: u8) {
-        self.ram[dest] = value;
+        match dest {
+            // Writes to the ROM area go to the memory bank controller's registers
+            0..=ROM_END | EXTERNAL_RAM_START..=EXTERNAL_RAM_END => {
+                self.cartridge.write(dest, value)
+            }
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END => self.ppu.write(dest, value),
+            WRAM_START..=WRAM_END => self.wram.write(dest - WRAM_START, value),
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram.write(dest - ECHO_RAM_START, value),
+            UNUSABLE_START..=UNUSABLE_END => (),
+            JOYPAD => self.joypad.write(dest, value),
+            DMA => {
+                self.io.write(dest - IO_START, value);
+                self.oam_dma(value);
+            }
+            SERIAL_START..=SERIAL_END => self.serial.write(dest, value),
+            TIMER_START..=TIMER_END => self.timer.write(dest, value),
+            LCD_START..=LCD_END => self.ppu.write(dest, value),
+            KEY1 if !self.cgb_mode => (),
+            // Bit 7 is the current speed, only the switch itself can change it
+            KEY1 => {
+                let speed = self.io.read(KEY1 - IO_START) & 0x80;
+                self.io.write(KEY1 - IO_START, speed | value & 0x01);
+            }
+            // Only bit 0 is wired, setting it unmaps the boot ROM until the next power on
+            BOOT_ROM_DISABLE => {
+                self.io.write(dest - IO_START, value);
+                if value & 0x01 != 0 {
+                    self.boot_rom_mapped = false;
+                }
+            }
+            IO_START..=IO_END => self.io.write(dest - IO_START, value),
+            HRAM_START..=HRAM_END => self.hram.write(dest - HRAM_START, value),
+            INTERRUPT_ENABLE => self.interrupt_enable = value,
+            _ => panic!("Address out of range: {:#06X}", dest),
+        }
     }
 }
 
@@ -31,8 +232,203 @@ impl Storage<usize, u16> for MemoryBus {
     }
 }
 
+impl Bus for MemoryBus {
+    fn tick(&mut self, cycles: u32) {
+        self.cartridge.tick(cycles);
+
+        let requests = self.ppu.tick(cycles)
+            | self.timer.tick(cycles)
+            | self.serial.tick(cycles)
+            | self.joypad.take_interrupts();
+        if requests != 0 {
+            let interrupt_requests: u8 = self.read(INTERRUPT_FLAG);
+            self.write(INTERRUPT_FLAG, interrupt_requests | requests);
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> u16 {
+        match addr as usize {
+            0..=ROM_END => self.cartridge.rom_bank(addr as usize) as u16,
+            _ => 0,
+        }
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        let key1 = self.io.read(KEY1 - IO_START);
+        if !self.cgb_mode || key1 & 0x01 == 0 {
+            return false;
+        }
+
+        self.io.write(KEY1 - IO_START, (key1 ^ 0x80) & 0x80);
+        true
+    }
+}
+
+impl Snapshot for MemoryBus {
+    fn save(&self, state: &mut StateWriter) {
+        self.cartridge.save(state);
+        self.ppu.save(state);
+        self.serial.save(state);
+        self.timer.save(state);
+        self.wram.save(state);
+        self.io.save(state);
+        self.hram.save(state);
+        state.write_u8(self.interrupt_enable);
+        self.joypad.save(state);
+        // The boot ROM itself isn't saved, like the cartridge ROM
+        state.write_bool(self.boot_rom_mapped);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.cartridge.load(state)?;
+        self.ppu.load(state)?;
+        self.serial.load(state)?;
+        self.timer.load(state)?;
+        self.wram.load(state)?;
+        self.io.load(state)?;
+        self.hram.load(state)?;
+        self.interrupt_enable = state.read_u8()?;
+
+        // Version 1 didn't have the joypad, nothing was ever pressed back then
+        if state.version() >= 2 {
+            self.joypad.load(state)?;
+        }
+
+        // Nor did it support boot ROMs
+        self.boot_rom_mapped = match state.version() >= 3 {
+            true => state.read_bool()?,
+            false => false,
+        };
+        if self.boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(StateError::InvalidValue(
+                "boot ROM mapped without one loaded",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 impl MemoryBus {
-    pub fn new() -> Self {
-        Self { ram: [0; 0xFFFF] }
+    pub fn new(cartridge: Box<dyn Mapper>) -> Self {
+        Self {
+            cartridge,
+            boot_rom: None,
+            boot_rom_mapped: false,
+            cgb_mode: false,
+            doctor_mode: false,
+            joypad: Joypad::new(),
+            ppu: Ppu::new(),
+            serial: Serial::new(),
+            timer: Timer::new(),
+            wram: Ram::new(),
+            io: IoRegisters::new(),
+            hram: Ram::new(),
+            interrupt_enable: 0,
+        }
+    }
+
+    /// Maps a boot ROM over the cartridge ROM, for the CPU to run from 0x0000 on power on.
+    pub fn set_boot_rom(&mut self, boot_rom: BootRom) {
+        self.boot_rom = Some(boot_rom);
+        self.boot_rom_mapped = true;
+    }
+
+    pub fn boot_rom(&self) -> Option<&BootRom> {
+        self.boot_rom.as_ref()
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom_mapped
+    }
+
+    /// Enables the CGB only registers, for a CGB running a cartridge which supports it.
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+    }
+
+    /// Makes LY always read 0x90, as it did when Gameboy Doctor's logs were made, so that a trace
+    /// of the CPU can be compared against them.
+    pub fn set_doctor_mode(&mut self, doctor_mode: bool) {
+        self.doctor_mode = doctor_mode;
+    }
+
+    /// Puts the hardware in the state the boot ROM of a model leaves it in, to start a cartridge
+    /// without running one.
+    pub fn skip_boot(&mut self, model: Model) {
+        for (address, value) in POST_BOOT_REGISTERS {
+            self.write(address, value);
+        }
+
+        // Only the DMG and MGB boot ROMs leave the first sound channel on after the logo's chime,
+        // and DIV tells how long it took to get there. The SGB and CGB boot ROMs take more or less
+        // time depending on the cartridge, their DIV isn't set in stone.
+        let (nr52, div): (u8, u16) = match model {
+            Model::Dmg0 => (0xF1, 0x1800),
+            Model::Dmg | Model::Mgb => (0xF1, 0xABCC),
+            Model::Sgb => (0xF0, 0x0000),
+            Model::Cgb => (0xF1, 0x0000),
+        };
+        self.write(NR52, nr52);
+        self.timer.set_counter(div);
+
+        // Written directly, going through the bus would start a transfer
+        let dma = if model == Model::Cgb { 0x00 } else { 0xFF };
+        self.io.write(DMA - IO_START, dma);
+
+        // The CGB boot ROM leaves the serial port on its internal clock
+        if model == Model::Cgb {
+            self.write(SC, 0x7Fu8);
+        }
+
+        self.boot_rom_mapped = false;
+    }
+
+    pub fn cartridge(&self) -> &dyn Mapper {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut dyn Mapper {
+        self.cartridge.as_mut()
+    }
+
+    pub fn joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.joypad.set_button(button, pressed);
+    }
+
+    pub fn set_held(&mut self, held: u8) {
+        self.joypad.set_held(held);
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    pub fn serial_mut(&mut self) -> &mut Serial {
+        &mut self.serial
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        let interrupt_requests: u8 = self.read(INTERRUPT_FLAG);
+        self.write(INTERRUPT_FLAG, interrupt_requests | interrupt.mask());
+    }
+
+    // Writing XX to 0xFF46 copies 0xXX00..0xXX9F into OAM. The transfer is done all at once rather
+    // than one byte per M-cycle.
+    fn oam_dma(&mut self, page: u8) {
+        let source = (page as usize) << 8;
+
+        for i in 0..0xA0 {
+            let value: u8 = self.read(source + i);
+            self.ppu.write_oam(i, value);
+        }
     }
 }
@@ -0,0 +1,99 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// Bits which aren't backed by anything in a register read back as 1, and registers which don't
+// exist at all read back as 0xFF. This table holds the bits which are actually implemented for
+// every address in 0xFF00..0xFF80, see https://gbdev.io/pandocs/Hardware_Reg_List.html
+const IMPLEMENTED_BITS: [u8; 0x80] = {
+    let mut bits = [0x00; 0x80];
+
+    bits[0x00] = 0x3F; // P1/JOYP
+    bits[0x01] = 0xFF; // SB
+    bits[0x02] = 0x81; // SC
+    bits[0x04] = 0xFF; // DIV
+    bits[0x05] = 0xFF; // TIMA
+    bits[0x06] = 0xFF; // TMA
+    bits[0x07] = 0x07; // TAC
+    bits[0x0F] = 0x1F; // IF
+
+    // Audio registers
+    bits[0x10] = 0x7F; // NR10
+    bits[0x11] = 0xFF; // NR11
+    bits[0x12] = 0xFF; // NR12
+    bits[0x13] = 0xFF; // NR13
+    bits[0x14] = 0xC7; // NR14
+    bits[0x16] = 0xFF; // NR21
+    bits[0x17] = 0xFF; // NR22
+    bits[0x18] = 0xFF; // NR23
+    bits[0x19] = 0xC7; // NR24
+    bits[0x1A] = 0x80; // NR30
+    bits[0x1B] = 0xFF; // NR31
+    bits[0x1C] = 0x60; // NR32
+    bits[0x1D] = 0xFF; // NR33
+    bits[0x1E] = 0xC7; // NR34
+    bits[0x20] = 0x3F; // NR41
+    bits[0x21] = 0xFF; // NR42
+    bits[0x22] = 0xFF; // NR43
+    bits[0x23] = 0xC0; // NR44
+    bits[0x24] = 0xFF; // NR50
+    bits[0x25] = 0xFF; // NR51
+    bits[0x26] = 0x8F; // NR52
+
+    // Wave RAM
+    let mut i = 0x30;
+    while i < 0x40 {
+        bits[i] = 0xFF;
+        i += 1;
+    }
+
+    // LCD registers
+    bits[0x40] = 0xFF; // LCDC
+    bits[0x41] = 0x7F; // STAT
+    bits[0x42] = 0xFF; // SCY
+    bits[0x43] = 0xFF; // SCX
+    bits[0x44] = 0xFF; // LY
+    bits[0x45] = 0xFF; // LYC
+    bits[0x46] = 0xFF; // DMA
+    bits[0x47] = 0xFF; // BGP
+    bits[0x48] = 0xFF; // OBP0
+    bits[0x49] = 0xFF; // OBP1
+    bits[0x4A] = 0xFF; // WY
+    bits[0x4B] = 0xFF; // WX
+
+    bits[0x4D] = 0x81; // KEY1
+    bits[0x50] = 0x01; // BANK
+
+    bits
+};
+
+/// The I/O registers in 0xFF00..0xFF80 which aren't owned by a dedicated component.
+#[derive(Debug)]
+pub struct IoRegisters {
+    data: [u8; 0x80],
+}
+
+impl Storage<usize, u8> for IoRegisters {
+    fn read(&mut self, src: usize) -> u8 {
+        self.data[src] | !IMPLEMENTED_BITS[src]
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        self.data[dest] = value & IMPLEMENTED_BITS[dest];
+    }
+}
+
+impl Snapshot for IoRegisters {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.data)
+    }
+}
+
+impl IoRegisters {
+    pub fn new() -> Self {
+        Self { data: [0; 0x80] }
+    }
+}
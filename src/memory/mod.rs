@@ -1 +1,3 @@
 pub mod bus;
+pub mod io;
+pub mod ram;
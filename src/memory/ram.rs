@@ -0,0 +1,39 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+/// A plain block of read/write memory, addressed relative to the start of the region it is
+/// mapped to.
+#[derive(Debug)]
+pub struct Ram<const SIZE: usize> {
+    data: [u8; SIZE],
+}
+
+impl<const SIZE: usize> Storage<usize, u8> for Ram<SIZE> {
+    fn read(&mut self, src: usize) -> u8 {
+        self.data[src]
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        self.data[dest] = value;
+    }
+}
+
+impl<const SIZE: usize> Snapshot for Ram<SIZE> {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_into(&mut self.data)
+    }
+}
+
+impl<const SIZE: usize> Ram<SIZE> {
+    pub fn new() -> Self {
+        Self { data: [0; SIZE] }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+}
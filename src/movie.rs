@@ -0,0 +1,349 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::Path;
+
+use crate::boot::{BootRom, Model};
+use crate::cartridge::header::CartridgeHeader;
+use crate::cpu::CPU;
+use crate::joypad::Button;
+use crate::state::StateError;
+
+// Movies are plain text, laid out like the input log of BizHawk's BK2 movies so they can be
+// diffed and edited by hand:
+//
+//   [Header]
+//   Title TETRIS
+//   GlobalChecksum 16BF
+//   Model dmg
+//   BootRom None
+//   StartsFromSavestate False
+//   [/Header]
+//   [Input]
+//   LogKey:#Up|Down|Left|Right|Start|Select|B|A|
+//   |........|
+//   |...R...A|
+//   [/Input]
+//
+// Every input line is one frame, with a letter for each button held and a dot for the others.
+// BootRom is the CRC-32 of the boot ROM the movie was recorded with, since one that differs
+// takes a different number of cycles to get to the cartridge.
+// Movies starting from a save state have it in a [SaveState] section, as hexadecimal.
+const LOG_KEY: &str = "LogKey:#Up|Down|Left|Right|Start|Select|B|A|";
+
+// The buttons in the order of the log key, and the letters marking them as held
+const COLUMNS: [(Button, char); 8] = [
+    (Button::Up, 'U'),
+    (Button::Down, 'D'),
+    (Button::Left, 'L'),
+    (Button::Right, 'R'),
+    (Button::Start, 'S'),
+    (Button::Select, 's'),
+    (Button::B, 'B'),
+    (Button::A, 'A'),
+];
+
+const STATE_BYTES_PER_LINE: usize = 32;
+
+#[derive(Debug)]
+pub struct MovieError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for MovieError {}
+
+/// The buttons held during every frame of a recording, starting either from power on or from a
+/// save state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub title: String,
+    pub checksum: [u8; 2],
+    // The hardware the movie was recorded on, which playback has to start as too
+    pub model: Model,
+    pub boot_rom: Option<u32>,
+    pub state: Option<Vec<u8>>,
+    // One mask per frame, as returned by `Joypad::held`
+    pub frames: Vec<u8>,
+}
+
+impl Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[Header]")?;
+        writeln!(f, "Title {}", self.title)?;
+        writeln!(
+            f,
+            "GlobalChecksum {:04X}",
+            u16::from_be_bytes(self.checksum)
+        )?;
+        writeln!(f, "Model {}", self.model.name())?;
+        match self.boot_rom {
+            Some(checksum) => writeln!(f, "BootRom {:08X}", checksum)?,
+            None => writeln!(f, "BootRom None")?,
+        }
+        let from_state = if self.state.is_some() {
+            "True"
+        } else {
+            "False"
+        };
+        writeln!(f, "StartsFromSavestate {}", from_state)?;
+        writeln!(f, "[/Header]")?;
+
+        if let Some(state) = &self.state {
+            writeln!(f, "[SaveState]")?;
+            for line in state.chunks(STATE_BYTES_PER_LINE) {
+                for byte in line {
+                    write!(f, "{:02X}", byte)?;
+                }
+                writeln!(f)?;
+            }
+            writeln!(f, "[/SaveState]")?;
+        }
+
+        writeln!(f, "[Input]")?;
+        writeln!(f, "{}", LOG_KEY)?;
+        for held in &self.frames {
+            let buttons: String = COLUMNS
+                .iter()
+                .map(|(button, letter)| match held & mask(*button) {
+                    0 => '.',
+                    _ => *letter,
+                })
+                .collect();
+            writeln!(f, "|{}|", buttons)?;
+        }
+        writeln!(f, "[/Input]")
+    }
+}
+
+impl Movie {
+    /// An empty movie for a ROM, starting from power on or from a save state of it.
+    pub fn new(
+        header: &CartridgeHeader,
+        model: Model,
+        boot_rom: Option<&BootRom>,
+        state: Option<Vec<u8>>,
+    ) -> Self {
+        Self {
+            title: header.name(),
+            checksum: header.global_checksum,
+            model,
+            boot_rom: boot_rom.map(BootRom::checksum),
+            state,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Movie::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Parses a movie. Header keys it doesn't know about are skipped, like BizHawk does, and
+    /// movies from before the model and boot ROM were recorded are taken to be from a DMG
+    /// without one.
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut title = String::new();
+        let mut checksum = None;
+        let mut model = Model::default();
+        let mut boot_rom = None;
+        let mut from_state = false;
+        let mut state = Vec::new();
+        let mut frames = Vec::new();
+        let mut section = "";
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            let error = |message: String| MovieError {
+                line: i + 1,
+                message,
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("[/") {
+                if name.strip_suffix(']') != Some(section) {
+                    return Err(error(format!("Unexpected {}", line)));
+                }
+                section = "";
+                continue;
+            }
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                match (section, name) {
+                    ("", "Header" | "SaveState" | "Input") => section = name,
+                    _ => return Err(error(format!("Unexpected {}", line))),
+                }
+                continue;
+            }
+
+            match section {
+                "Header" => {
+                    let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+                    match key {
+                        "Title" => title = value.to_string(),
+                        "GlobalChecksum" => {
+                            let value = u16::from_str_radix(value, 16)
+                                .map_err(|_| error(format!("Invalid checksum: {}", value)))?;
+                            checksum = Some(value.to_be_bytes());
+                        }
+                        "Model" => {
+                            model = Model::parse(value)
+                                .ok_or_else(|| error(format!("Invalid model: {}", value)))?;
+                        }
+                        "BootRom" if value == "None" => boot_rom = None,
+                        "BootRom" => {
+                            let value = u32::from_str_radix(value, 16).map_err(|_| {
+                                error(format!("Invalid boot ROM checksum: {}", value))
+                            })?;
+                            boot_rom = Some(value);
+                        }
+                        "StartsFromSavestate" => from_state = value == "True",
+                        _ => (),
+                    }
+                }
+                "SaveState" => {
+                    let digits = line.as_bytes();
+                    if digits.len() % 2 != 0 {
+                        return Err(error("Odd number of digits".to_string()));
+                    }
+
+                    for pair in digits.chunks(2) {
+                        let pair = std::str::from_utf8(pair).unwrap_or_default();
+                        let byte = u8::from_str_radix(pair, 16)
+                            .map_err(|_| error(format!("Invalid byte: {}", pair)))?;
+                        state.push(byte);
+                    }
+                }
+                "Input" if line.starts_with("LogKey:") => {
+                    if line != LOG_KEY {
+                        return Err(error(format!("Unsupported log key: {}", line)));
+                    }
+                }
+                "Input" => frames.push(Movie::parse_frame(line).map_err(error)?),
+                _ => {
+                    return Err(error(format!(
+                        "Unexpected line outside of a section: {}",
+                        line
+                    )))
+                }
+            }
+        }
+
+        if !section.is_empty() {
+            return Err(MovieError {
+                line: text.lines().count(),
+                message: format!("Missing [/{}]", section),
+            });
+        }
+
+        let checksum = checksum.ok_or_else(|| MovieError {
+            line: 1,
+            message: "Missing GlobalChecksum".to_string(),
+        })?;
+
+        let state = match (from_state, state.is_empty()) {
+            (true, false) => Some(state),
+            (false, true) => None,
+            (true, true) => {
+                return Err(MovieError {
+                    line: text.lines().count(),
+                    message: "Missing [SaveState]".to_string(),
+                })
+            }
+            (false, false) => {
+                return Err(MovieError {
+                    line: 1,
+                    message: "Has a [SaveState] but doesn't start from it".to_string(),
+                })
+            }
+        };
+
+        Ok(Self {
+            title,
+            checksum,
+            model,
+            boot_rom,
+            state,
+            frames,
+        })
+    }
+
+    // Any character other than a dot or a space counts as held, the same way BizHawk reads them
+    fn parse_frame(line: &str) -> Result<u8, String> {
+        let columns = line
+            .strip_prefix('|')
+            .and_then(|line| line.strip_suffix('|'))
+            .filter(|columns| columns.chars().count() == COLUMNS.len())
+            .ok_or_else(|| format!("Invalid frame: {}", line))?;
+
+        let held = columns
+            .chars()
+            .zip(COLUMNS)
+            .filter(|(letter, _)| !matches!(letter, '.' | ' '))
+            .fold(0, |held, (_, (button, _))| held | mask(button));
+
+        Ok(held)
+    }
+
+    /// Appends a frame, with the buttons currently held.
+    pub fn record(&mut self, cpu: &mut CPU) {
+        self.frames.push(cpu.bus().joypad().held());
+    }
+
+    /// Puts the machine where the movie starts. It has to be freshly powered on as the movie's
+    /// model unless the movie starts from a save state, and the ROM and boot ROM have to be the
+    /// ones the movie was made with.
+    pub fn start(&self, cpu: &mut CPU, checksum: [u8; 2]) -> Result<(), StateError> {
+        if self.checksum != checksum {
+            return Err(StateError::WrongRom {
+                expected: checksum,
+                found: self.checksum,
+            });
+        }
+
+        let boot_rom = cpu.bus().boot_rom().map(BootRom::checksum);
+        if self.boot_rom != boot_rom {
+            return Err(StateError::WrongBootRom {
+                expected: boot_rom,
+                found: self.boot_rom,
+            });
+        }
+
+        match &self.state {
+            Some(state) => cpu.load_state(state, checksum),
+            None => Ok(()),
+        }
+    }
+
+    /// Holds the buttons of a frame and runs it.
+    pub fn play_frame(&self, cpu: &mut CPU, frame: usize) -> u64 {
+        cpu.set_held(self.frames.get(frame).copied().unwrap_or(0));
+        cpu.run_frame()
+    }
+}
+
+// The bit of a button in the masks returned by `Joypad::held`
+fn mask(button: Button) -> u8 {
+    let index = Button::ALL
+        .iter()
+        .position(|other| *other == button)
+        .unwrap_or_default();
+
+    1 << index
+}
@@ -0,0 +1,549 @@
+pub mod sprite;
+
+use self::sprite::Sprite;
+use crate::cpu::interrupts::Interrupt;
+use crate::memory::ram::Ram;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// Timings are in dots, one dot is one T-cycle in normal speed mode.
+// See https://gbdev.io/pandocs/Rendering.html
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const MIN_DRAWING_DOTS: u32 = 172;
+const SPRITE_PENALTY_DOTS: u32 = 6;
+const VBLANK_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+const MAX_SPRITES_PER_LINE: usize = 10;
+
+const VRAM_START: usize = 0x8000;
+const VRAM_END: usize = 0x9FFF;
+const OAM_START: usize = 0xFE00;
+const OAM_END: usize = 0xFE9F;
+
+const LCDC: usize = 0xFF40;
+const STAT: usize = 0xFF41;
+const SCY: usize = 0xFF42;
+const SCX: usize = 0xFF43;
+const LY: usize = 0xFF44;
+const LYC: usize = 0xFF45;
+const BGP: usize = 0xFF47;
+const OBP0: usize = 0xFF48;
+const OBP1: usize = 0xFF49;
+const WY: usize = 0xFF4A;
+const WX: usize = 0xFF4B;
+
+// LCDC bits
+const LCD_ENABLE: u8 = 0b1000_0000;
+const WINDOW_TILE_MAP: u8 = 0b0100_0000;
+const WINDOW_ENABLE: u8 = 0b0010_0000;
+const TILE_DATA: u8 = 0b0001_0000;
+const BG_TILE_MAP: u8 = 0b0000_1000;
+const OBJ_SIZE: u8 = 0b0000_0100;
+const OBJ_ENABLE: u8 = 0b0000_0010;
+const BG_ENABLE: u8 = 0b0000_0001;
+
+// STAT bits
+const LYC_INTERRUPT: u8 = 0b0100_0000;
+const OAM_INTERRUPT: u8 = 0b0010_0000;
+const VBLANK_INTERRUPT: u8 = 0b0001_0000;
+const HBLANK_INTERRUPT: u8 = 0b0000_1000;
+const LYC_EQUAL: u8 = 0b0000_0100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+#[derive(Debug)]
+pub struct Ppu {
+    vram: Ram<0x2000>,
+    oam: Ram<0xA0>,
+
+    lcdc: u8,
+    // Only the interrupt select bits, the rest of STAT is derived from the PPU's state
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+
+    mode: Mode,
+    // Dots elapsed since the start of the current line
+    dots: u32,
+    // Length of the drawing mode on the current line, which grows with every sprite fetched
+    drawing_dots: u32,
+    // Up to 10 sprites picked during the OAM scan of the current line
+    sprites: Vec<Sprite>,
+
+    // The window keeps its own line counter which only advances on lines where it was drawn
+    window_line: u8,
+    // Set once LY matched WY during the current frame
+    window_triggered: bool,
+
+    // The STAT interrupt fires on the rising edge of all of its sources OR'ed together
+    stat_line: bool,
+    // Interrupts raised since the last tick
+    interrupts: u8,
+
+    // One shade per pixel, 0 (white) through 3 (black)
+    frame_buffer: Vec<u8>,
+    frames: u64,
+}
+
+impl Storage<usize, u8> for Ppu {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            // The CPU can't reach VRAM while the PPU is drawing from it, or OAM while it is being
+            // scanned or drawn, in which case the reads return garbage (0xFF)
+            VRAM_START..=VRAM_END if self.mode == Mode::Drawing => 0xFF,
+            VRAM_START..=VRAM_END => self.vram.read(src - VRAM_START),
+            OAM_START..=OAM_END if matches!(self.mode, Mode::OamScan | Mode::Drawing) => 0xFF,
+            OAM_START..=OAM_END => self.oam.read(src - OAM_START),
+            LCDC => self.lcdc,
+            STAT => {
+                let lyc_equal = if self.ly == self.lyc { LYC_EQUAL } else { 0 };
+                0x80 | self.stat | lyc_equal | self.mode as u8
+            }
+            SCY => self.scy,
+            SCX => self.scx,
+            LY => self.ly,
+            LYC => self.lyc,
+            BGP => self.bgp,
+            OBP0 => self.obp0,
+            OBP1 => self.obp1,
+            WY => self.wy,
+            WX => self.wx,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        match dest {
+            VRAM_START..=VRAM_END if self.mode == Mode::Drawing => (),
+            VRAM_START..=VRAM_END => self.vram.write(dest - VRAM_START, value),
+            OAM_START..=OAM_END if matches!(self.mode, Mode::OamScan | Mode::Drawing) => (),
+            OAM_START..=OAM_END => self.oam.write(dest - OAM_START, value),
+            LCDC => self.write_lcdc(value),
+            STAT => self.stat = value & 0x78,
+            SCY => self.scy = value,
+            SCX => self.scx = value,
+            // LY is read only
+            LY => (),
+            LYC => self.lyc = value,
+            BGP => self.bgp = value,
+            OBP0 => self.obp0 = value,
+            OBP1 => self.obp1 = value,
+            WY => self.wy = value,
+            WX => self.wx = value,
+            _ => (),
+        }
+
+        self.update_stat_line();
+    }
+}
+
+impl Snapshot for Ppu {
+    fn save(&self, state: &mut StateWriter) {
+        self.vram.save(state);
+        self.oam.save(state);
+
+        for register in [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.bgp, self.obp0,
+            self.obp1, self.wy, self.wx,
+        ] {
+            state.write_u8(register);
+        }
+
+        state.write_u8(self.mode as u8);
+        state.write_u32(self.dots);
+        state.write_u32(self.drawing_dots);
+
+        // OAM may have changed since the scan, so the sprites picked for the line are kept too
+        state.write_u8(self.sprites.len() as u8);
+        for sprite in &self.sprites {
+            state.write_u8(sprite.y);
+            state.write_u8(sprite.x);
+            state.write_u8(sprite.tile);
+            state.write_u8(sprite.attributes);
+        }
+
+        state.write_u8(self.window_line);
+        state.write_bool(self.window_triggered);
+        state.write_bool(self.stat_line);
+        state.write_bytes(&self.frame_buffer);
+        state.write_u64(self.frames);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.vram.load(state)?;
+        self.oam.load(state)?;
+
+        for register in [
+            &mut self.lcdc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+            &mut self.wy,
+            &mut self.wx,
+        ] {
+            *register = state.read_u8()?;
+        }
+
+        self.mode = match state.read_u8()? {
+            0 => Mode::HBlank,
+            1 => Mode::VBlank,
+            2 => Mode::OamScan,
+            3 => Mode::Drawing,
+            _ => return Err(StateError::InvalidValue("PPU mode")),
+        };
+        self.dots = state.read_u32()?;
+        self.drawing_dots = state.read_u32()?;
+
+        let sprites = state.read_u8()? as usize;
+        if sprites > MAX_SPRITES_PER_LINE {
+            return Err(StateError::InvalidValue("sprite count"));
+        }
+
+        self.sprites.clear();
+        for _ in 0..sprites {
+            let bytes = [
+                state.read_u8()?,
+                state.read_u8()?,
+                state.read_u8()?,
+                state.read_u8()?,
+            ];
+            self.sprites.push(Sprite::from_bytes(&bytes));
+        }
+
+        self.window_line = state.read_u8()?;
+        self.window_triggered = state.read_bool()?;
+        self.stat_line = state.read_bool()?;
+        state.read_into(&mut self.frame_buffer)?;
+        self.frames = state.read_u64()?;
+        self.interrupts = 0;
+
+        Ok(())
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            vram: Ram::new(),
+            oam: Ram::new(),
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            mode: Mode::HBlank,
+            dots: 0,
+            drawing_dots: MIN_DRAWING_DOTS,
+            sprites: Vec::with_capacity(MAX_SPRITES_PER_LINE),
+            window_line: 0,
+            window_triggered: false,
+            stat_line: false,
+            interrupts: 0,
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frames: 0,
+        }
+    }
+
+    /// The last frame drawn, one shade (0-3) per pixel in row major order.
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+
+    /// Number of frames completed since power on.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Used by OAM DMA, which doesn't go through the CPU's access restrictions.
+    pub fn write_oam(&mut self, offset: usize, value: u8) {
+        self.oam.write(offset, value);
+    }
+
+    /// Advances the PPU by a number of dots and returns the interrupts it requested, as a mask
+    /// of IF bits.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if self.lcdc & LCD_ENABLE != 0 {
+            self.dots += cycles;
+
+            while self.advance_mode() {
+                self.update_stat_line();
+            }
+        }
+
+        std::mem::take(&mut self.interrupts)
+    }
+
+    // Moves on to the next mode if the current one is over, returns whether anything changed
+    fn advance_mode(&mut self) -> bool {
+        match self.mode {
+            Mode::OamScan if self.dots >= OAM_SCAN_DOTS => {
+                self.mode = Mode::Drawing;
+                self.drawing_dots = MIN_DRAWING_DOTS
+                    + (self.scx % 8) as u32
+                    + SPRITE_PENALTY_DOTS * self.sprites.len() as u32;
+            }
+            Mode::Drawing if self.dots >= OAM_SCAN_DOTS + self.drawing_dots => {
+                self.render_line();
+                self.mode = Mode::HBlank;
+            }
+            Mode::HBlank if self.dots >= DOTS_PER_LINE => {
+                self.dots -= DOTS_PER_LINE;
+                self.ly += 1;
+
+                if self.ly == VBLANK_LINE {
+                    self.mode = Mode::VBlank;
+                    self.frames += 1;
+                    self.interrupts |= Interrupt::VBlank.mask();
+                } else {
+                    self.start_line();
+                }
+            }
+            Mode::VBlank if self.dots >= DOTS_PER_LINE => {
+                self.dots -= DOTS_PER_LINE;
+                self.ly += 1;
+
+                if self.ly == LINES_PER_FRAME {
+                    self.ly = 0;
+                    self.window_line = 0;
+                    self.window_triggered = false;
+                    self.start_line();
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn start_line(&mut self) {
+        if self.ly == self.wy {
+            self.window_triggered = true;
+        }
+
+        self.mode = Mode::OamScan;
+        self.scan_oam();
+    }
+
+    fn write_lcdc(&mut self, value: u8) {
+        let was_enabled = self.lcdc & LCD_ENABLE != 0;
+        self.lcdc = value;
+
+        match (was_enabled, value & LCD_ENABLE != 0) {
+            (true, false) => {
+                // With the LCD off the PPU sits at the start of the frame in mode 0
+                self.ly = 0;
+                self.dots = 0;
+                self.mode = Mode::HBlank;
+                self.window_line = 0;
+                self.window_triggered = false;
+            }
+            (false, true) => self.start_line(),
+            _ => (),
+        }
+    }
+
+    fn update_stat_line(&mut self) {
+        let stat_line = self.lcdc & LCD_ENABLE != 0
+            && ((self.stat & LYC_INTERRUPT != 0 && self.ly == self.lyc)
+                || match self.mode {
+                    Mode::HBlank => self.stat & HBLANK_INTERRUPT != 0,
+                    Mode::VBlank => self.stat & VBLANK_INTERRUPT != 0,
+                    Mode::OamScan => self.stat & OAM_INTERRUPT != 0,
+                    Mode::Drawing => false,
+                });
+
+        if stat_line && !self.stat_line {
+            self.interrupts |= Interrupt::Stat.mask();
+        }
+
+        self.stat_line = stat_line;
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc & OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    // Picks the first 10 sprites in OAM order overlapping the current line
+    fn scan_oam(&mut self) {
+        let height = self.sprite_height();
+        let oam = self.oam.as_slice();
+
+        self.sprites.clear();
+        self.sprites.extend(
+            oam.chunks_exact(4)
+                .map(Sprite::from_bytes)
+                .filter(|sprite| sprite.on_line(self.ly, height))
+                .take(MAX_SPRITES_PER_LINE),
+        );
+    }
+
+    // Reads the color index (0-3) of a pixel in the background or window tile map
+    fn tile_map_pixel(&self, tile_map: usize, x: u8, y: u8) -> u8 {
+        let vram = self.vram.as_slice();
+        let tile = vram[tile_map + (y as usize / 8) * 32 + x as usize / 8];
+
+        // Tiles 0-127 come from 0x9000 instead of 0x8000 in the signed addressing mode
+        let tile_address = if self.lcdc & TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + (tile as i8 as isize) * 16) as usize
+        };
+
+        tile_pixel(vram, tile_address, x % 8, y % 8)
+    }
+
+    fn render_line(&mut self) {
+        let ly = self.ly;
+        let mut background = [0u8; SCREEN_WIDTH];
+
+        // On the DMG, clearing LCDC bit 0 blanks both the background and the window
+        if self.lcdc & BG_ENABLE != 0 {
+            let tile_map = if self.lcdc & BG_TILE_MAP != 0 {
+                0x1C00
+            } else {
+                0x1800
+            };
+            let y = ly.wrapping_add(self.scy);
+
+            for (x, pixel) in background.iter_mut().enumerate() {
+                *pixel = self.tile_map_pixel(tile_map, (x as u8).wrapping_add(self.scx), y);
+            }
+
+            if self.lcdc & WINDOW_ENABLE != 0 && self.window_triggered && self.wx <= 166 {
+                let tile_map = if self.lcdc & WINDOW_TILE_MAP != 0 {
+                    0x1C00
+                } else {
+                    0x1800
+                };
+                let start = self.wx as isize - 7;
+
+                for (x, pixel) in background.iter_mut().enumerate() {
+                    if x as isize >= start {
+                        let column = (x as isize - start) as u8;
+                        *pixel = self.tile_map_pixel(tile_map, column, self.window_line);
+                    }
+                }
+
+                self.window_line += 1;
+            }
+        }
+
+        let row = &mut self.frame_buffer[ly as usize * SCREEN_WIDTH..][..SCREEN_WIDTH];
+        for (pixel, color) in row.iter_mut().zip(background) {
+            *pixel = palette_shade(self.bgp, color);
+        }
+
+        if self.lcdc & OBJ_ENABLE != 0 {
+            self.render_sprites(&background);
+        }
+    }
+
+    fn render_sprites(&mut self, background: &[u8; SCREEN_WIDTH]) {
+        let ly = self.ly;
+        let height = self.sprite_height();
+        let vram = self.vram.as_slice();
+
+        // On the DMG the sprite with the smaller X coordinate wins, ties go to the one that comes
+        // first in OAM. The sort is stable, so the OAM order is kept for equal X coordinates.
+        let mut sprites = self.sprites.clone();
+        sprites.sort_by_key(|sprite| sprite.x);
+
+        // Once an opaque sprite pixel has been found, sprites with lower priority are hidden
+        // there even if the winning pixel itself ends up behind the background
+        let mut covered = [false; SCREEN_WIDTH];
+        let row = &mut self.frame_buffer[ly as usize * SCREEN_WIDTH..][..SCREEN_WIDTH];
+
+        for sprite in sprites {
+            let mut line = (ly as i16 - (sprite.y as i16 - 16)) as u8;
+            if sprite.y_flip() {
+                line = height - 1 - line;
+            }
+
+            // In 8x16 mode the lowest bit of the tile index is ignored
+            let tile = if height == 16 {
+                sprite.tile & 0xFE
+            } else {
+                sprite.tile
+            };
+            let palette = if sprite.second_palette() {
+                self.obp1
+            } else {
+                self.obp0
+            };
+
+            for column in 0..8u8 {
+                let x = sprite.x as isize - 8 + column as isize;
+                if !(0..SCREEN_WIDTH as isize).contains(&x) || covered[x as usize] {
+                    continue;
+                }
+
+                let column = if sprite.x_flip() { 7 - column } else { column };
+                let color = tile_pixel(vram, tile as usize * 16, column, line);
+
+                // Color 0 is transparent for sprites
+                if color == 0 {
+                    continue;
+                }
+
+                let x = x as usize;
+                covered[x] = true;
+
+                if !(sprite.behind_background() && background[x] != 0) {
+                    row[x] = palette_shade(palette, color);
+                }
+            }
+        }
+    }
+}
+
+// Tiles are 16 bytes, two per row of 8 pixels: the first holds the low bit of every pixel's
+// color index and the second one the high bit, with the leftmost pixel in bit 7.
+fn tile_pixel(vram: &[u8], tile_address: usize, x: u8, y: u8) -> u8 {
+    let address = tile_address + y as usize * 2;
+    let low = vram[address];
+    let high = vram[address + 1];
+    let bit = 7 - x;
+
+    ((high >> bit) & 0x01) << 1 | (low >> bit) & 0x01
+}
+
+// Palettes map each color index to a shade using two bits per color
+fn palette_shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
@@ -0,0 +1,51 @@
+// An entry in OAM, see https://gbdev.io/pandocs/OAM.html
+const PRIORITY: u8 = 0b1000_0000;
+const Y_FLIP: u8 = 0b0100_0000;
+const X_FLIP: u8 = 0b0010_0000;
+const PALETTE: u8 = 0b0001_0000;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Sprite {
+    // Position of the sprite's bottom right corner, so (8, 16) is the top left of the screen
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub attributes: u8,
+}
+
+impl Sprite {
+    pub fn from_bytes(bytes: &[u8]) -> Sprite {
+        Sprite {
+            y: bytes[0],
+            x: bytes[1],
+            tile: bytes[2],
+            attributes: bytes[3],
+        }
+    }
+
+    /// Whether the background and window colors 1-3 are drawn over this sprite.
+    pub fn behind_background(&self) -> bool {
+        self.attributes & PRIORITY != 0
+    }
+
+    pub fn y_flip(&self) -> bool {
+        self.attributes & Y_FLIP != 0
+    }
+
+    pub fn x_flip(&self) -> bool {
+        self.attributes & X_FLIP != 0
+    }
+
+    /// Whether the sprite uses OBP1 rather than OBP0.
+    pub fn second_palette(&self) -> bool {
+        self.attributes & PALETTE != 0
+    }
+
+    /// Whether any row of the sprite falls on scanline `ly`.
+    pub fn on_line(&self, ly: u8, height: u8) -> bool {
+        let top = self.y as i16 - 16;
+        let ly = ly as i16;
+
+        ly >= top && ly < top + height as i16
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::VecDeque;
+
+use crate::cpu::CPU;
+use crate::state::StateError;
+
+// Snapshots never leave the buffer, so which ROM they claim to belong to doesn't matter
+const CHECKSUM: [u8; 2] = [0, 0];
+
+// Every that many snapshots a new keyframe is stored in full, the others only store how they
+// differ from it. Deltas grow as the machine drifts away from its keyframe.
+const SNAPSHOTS_PER_KEYFRAME: usize = 30;
+
+// Fewer zeros in a row than this are kept along with the bytes around them
+const MIN_RUN: usize = 3;
+
+/// A save state XOR'ed with its keyframe and run-length encoded, which mostly leaves runs of
+/// zeros behind.
+#[derive(Debug)]
+struct Delta {
+    frame: u64,
+    length: usize,
+    data: Vec<u8>,
+}
+
+/// A keyframe along with the snapshots stored as deltas against it.
+#[derive(Debug)]
+struct Segment {
+    keyframe: Delta,
+    deltas: Vec<Delta>,
+}
+
+impl Segment {
+    fn size(&self) -> usize {
+        self.keyframe.data.len()
+            + self
+                .deltas
+                .iter()
+                .map(|delta| delta.data.len())
+                .sum::<usize>()
+    }
+
+    fn last(&self) -> &Delta {
+        self.deltas.last().unwrap_or(&self.keyframe)
+    }
+}
+
+/// A ring buffer of snapshots taken every few frames, so the machine can be stepped back one
+/// frame at a time. The oldest snapshots are dropped to stay within a memory budget.
+///
+/// Stepping back restores the closest snapshot and runs forward to the frame before the current
+/// one with the same buttons held, which replays exactly what happened since emulation is
+/// deterministic. Buttons should only change in between frames for that to hold.
+#[derive(Debug)]
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    segments: VecDeque<Segment>,
+    // The uncompressed state of the newest keyframe, which new snapshots are compared against
+    keyframe: Vec<u8>,
+    size: usize,
+    // The buttons held during every frame since the oldest snapshot
+    inputs: VecDeque<(u64, u8)>,
+    // Frames run since the buffer was created
+    frame: u64,
+}
+
+impl Rewind {
+    /// Takes a snapshot every `interval` frames, keeping at most about `budget` bytes of them.
+    /// The newest keyframe is always kept, even if it alone goes over the budget.
+    pub fn new(interval: u64, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            segments: VecDeque::new(),
+            keyframe: Vec::new(),
+            size: 0,
+            inputs: VecDeque::new(),
+            frame: 0,
+        }
+    }
+
+    /// The number of frames run, minus the ones rewound.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Memory taken by the snapshots, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The oldest frame that can be rewound to.
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.segments.front().map(|segment| segment.keyframe.frame)
+    }
+
+    /// Runs a frame, taking a snapshot beforehand when one is due. Use this in place of
+    /// `CPU::run_frame` to make the frames rewindable.
+    pub fn run_frame(&mut self, cpu: &mut CPU) -> u64 {
+        if self.frame.is_multiple_of(self.interval) {
+            self.capture(cpu);
+        }
+
+        let held = cpu.bus().joypad().held();
+        self.inputs.push_back((self.frame, held));
+
+        self.frame += 1;
+        cpu.run_frame()
+    }
+
+    /// Goes back to the previous frame. Returns false, leaving the machine alone, when there's
+    /// no snapshot old enough left.
+    pub fn step_back(&mut self, cpu: &mut CPU) -> Result<bool, StateError> {
+        let Some(target) = self.frame.checked_sub(1) else {
+            return Ok(false);
+        };
+
+        if self.oldest_frame().is_none_or(|oldest| oldest > target) {
+            return Ok(false);
+        }
+
+        // Everything after the target is about to be replayed or replaced
+        self.truncate(target);
+
+        let segment = self.segments.back().expect("a snapshot is old enough");
+        let (snapshot, base) = match segment.deltas.last() {
+            Some(delta) => (delta, self.keyframe.as_slice()),
+            None => (&segment.keyframe, [].as_slice()),
+        };
+        let state = decode(snapshot, base);
+        let frame = snapshot.frame;
+
+        // Frames start out with the buttons held during them, including the target itself
+        cpu.load_state(&state, CHECKSUM)?;
+        for (input, held) in self.inputs.iter().filter(|(input, _)| *input >= frame) {
+            cpu.set_held(*held);
+
+            if *input < target {
+                cpu.run_frame();
+            }
+        }
+
+        // The target's input gets recorded again when it runs
+        self.inputs.pop_back();
+        self.frame = target;
+
+        Ok(true)
+    }
+
+    fn capture(&mut self, cpu: &CPU) {
+        // Coming back to a frame after stepping back, its snapshot is still there
+        let last = self.segments.back().map(|segment| segment.last().frame);
+        if last == Some(self.frame) {
+            return;
+        }
+
+        let state = cpu.save_state(CHECKSUM);
+
+        match self.segments.back_mut() {
+            Some(segment) if segment.deltas.len() + 1 < SNAPSHOTS_PER_KEYFRAME => {
+                let delta = encode(self.frame, &state, &self.keyframe);
+                self.size += delta.data.len();
+                segment.deltas.push(delta);
+            }
+            _ => {
+                let keyframe = encode(self.frame, &state, &[]);
+                self.size += keyframe.data.len();
+                self.segments.push_back(Segment {
+                    keyframe,
+                    deltas: Vec::new(),
+                });
+                self.keyframe = state;
+            }
+        }
+
+        while self.size > self.budget && self.segments.len() > 1 {
+            if let Some(segment) = self.segments.pop_front() {
+                self.size -= segment.size();
+            }
+        }
+
+        if let Some(oldest) = self.oldest_frame() {
+            while self
+                .inputs
+                .front()
+                .is_some_and(|(frame, _)| *frame < oldest)
+            {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    // Drops the snapshots and inputs after a frame
+    fn truncate(&mut self, frame: u64) {
+        while self.inputs.back().is_some_and(|(input, _)| *input > frame) {
+            self.inputs.pop_back();
+        }
+
+        while let Some(segment) = self.segments.back_mut() {
+            if segment.keyframe.frame > frame {
+                self.size -= segment.size();
+                self.segments.pop_back();
+                continue;
+            }
+
+            while segment
+                .deltas
+                .last()
+                .is_some_and(|delta| delta.frame > frame)
+            {
+                if let Some(delta) = segment.deltas.pop() {
+                    self.size -= delta.data.len();
+                }
+            }
+
+            break;
+        }
+
+        self.keyframe = match self.segments.back() {
+            Some(segment) => decode(&segment.keyframe, &[]),
+            None => Vec::new(),
+        };
+    }
+}
+
+// States aren't all the same length (the PPU saves a varying number of sprites), the shorter
+// side is padded with zeros
+fn encode(frame: u64, state: &[u8], base: &[u8]) -> Delta {
+    let xored: Vec<u8> = state
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ base.get(i).copied().unwrap_or(0))
+        .collect();
+
+    Delta {
+        frame,
+        length: state.len(),
+        data: compress(&xored),
+    }
+}
+
+fn decode(delta: &Delta, base: &[u8]) -> Vec<u8> {
+    let mut state = decompress(&delta.data);
+    state.resize(delta.length, 0);
+
+    for (byte, base) in state.iter_mut().zip(base) {
+        *byte ^= base;
+    }
+
+    state
+}
+
+// XOR'ed states are mostly zeros, so they're stored as a run of zeros followed by the bytes up to
+// the next run, repeated, with both lengths as LEB128 varints
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let zeros = data[i..].iter().take_while(|byte| **byte == 0).count();
+        i += zeros;
+
+        // Zeros in between changed bytes cost less to store as they are
+        let start = i;
+        while i < data.len() && !data[i..].iter().take(MIN_RUN).all(|byte| *byte == 0) {
+            i += 1;
+        }
+
+        write_varint(&mut compressed, zeros);
+        write_varint(&mut compressed, i - start);
+        compressed.extend_from_slice(&data[start..i]);
+    }
+
+    compressed
+}
+
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    let mut data = data;
+
+    while !data.is_empty() {
+        let zeros = read_varint(&mut data);
+        decompressed.resize(decompressed.len() + zeros, 0);
+
+        let length = read_varint(&mut data).min(data.len());
+        decompressed.extend_from_slice(&data[..length]);
+        data = &data[length..];
+    }
+
+    decompressed
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    data.push(value as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    while let Some((byte, rest)) = data.split_first() {
+        *data = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    value
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+
+use crate::cpu::interrupts::Interrupt;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+const SB: usize = 0xFF01;
+const SC: usize = 0xFF02;
+
+const TRANSFER_ENABLE: u8 = 0b1000_0000;
+const INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+// The internal clock runs at 8192 Hz, so one bit is shifted out every 512 T-cycles
+const CYCLES_PER_BIT: u32 = 512;
+
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+
+    // Byte being shifted out, reported once the transfer completes
+    outgoing: u8,
+    bits_left: u8,
+    cycles: u32,
+
+    // Every byte sent so far, and an optional callback invoked with each of them
+    output: Vec<u8>,
+    sink: Option<Box<dyn FnMut(u8)>>,
+}
+
+impl fmt::Debug for Serial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Serial")
+            .field("sb", &self.sb)
+            .field("sc", &self.sc)
+            .field("bits_left", &self.bits_left)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+impl Storage<usize, u8> for Serial {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            SB => self.sb,
+            SC => 0x7E | self.sc,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        match dest {
+            SB => self.sb = value,
+            SC => {
+                self.sc = value & (TRANSFER_ENABLE | INTERNAL_CLOCK);
+
+                // With an external clock the transfer waits for a link partner which never comes
+                if self.sc == TRANSFER_ENABLE | INTERNAL_CLOCK {
+                    self.outgoing = self.sb;
+                    self.bits_left = 8;
+                    self.cycles = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// The bytes sent so far and the sink belong to whoever is listening, not to the machine
+impl Snapshot for Serial {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_u8(self.sb);
+        state.write_u8(self.sc);
+        state.write_u8(self.outgoing);
+        state.write_u8(self.bits_left);
+        state.write_u32(self.cycles);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.sb = state.read_u8()?;
+        self.sc = state.read_u8()? & (TRANSFER_ENABLE | INTERNAL_CLOCK);
+        self.outgoing = state.read_u8()?;
+        self.bits_left = state.read_u8()?.min(8);
+        self.cycles = state.read_u32()?;
+
+        Ok(())
+    }
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            sb: 0,
+            sc: 0,
+            outgoing: 0,
+            bits_left: 0,
+            cycles: 0,
+            output: Vec::new(),
+            sink: None,
+        }
+    }
+
+    /// Every byte transferred since power on.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Sets a callback which receives every byte as its transfer completes.
+    pub fn set_sink(&mut self, sink: impl FnMut(u8) + 'static) {
+        self.sink = Some(Box::new(sink));
+    }
+
+    /// Advances an ongoing transfer by a number of T-cycles and returns the interrupts it
+    /// requested, as a mask of IF bits.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if self.bits_left == 0 {
+            return 0;
+        }
+
+        self.cycles += cycles;
+
+        while self.cycles >= CYCLES_PER_BIT && self.bits_left > 0 {
+            self.cycles -= CYCLES_PER_BIT;
+            self.bits_left -= 1;
+
+            // Nothing is connected, so the bits shifted in are all 1s
+            self.sb = self.sb << 1 | 0x01;
+        }
+
+        if self.bits_left > 0 {
+            return 0;
+        }
+
+        self.sc &= !TRANSFER_ENABLE;
+        self.output.push(self.outgoing);
+
+        if let Some(sink) = self.sink.as_mut() {
+            sink(self.outgoing);
+        }
+
+        Interrupt::Serial.mask()
+    }
+}
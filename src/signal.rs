@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set from the signal handler, which can't do much more than that safely
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod unix {
+    const SIGINT: i32 = 2;
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    extern "C" fn handle(_signum: i32) {
+        super::interrupt();
+    }
+
+    pub fn install() {
+        // Nothing is lost if this fails, Ctrl-C just goes back to killing the process
+        unsafe {
+            signal(SIGINT, handle);
+        }
+    }
+}
+
+/// Catches Ctrl-C instead of letting it kill the process, so that long running loops can stop
+/// at a point where it's safe to, see `take`. Only supported on Unix.
+pub fn install() {
+    #[cfg(unix)]
+    unix::install();
+}
+
+/// Does what pressing Ctrl-C does once `install` has been called.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether Ctrl-C was pressed since the last call.
+pub fn take() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed) && INTERRUPTED.swap(false, Ordering::SeqCst)
+}
@@ -0,0 +1,260 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A save state is a small header followed by the state of every component, written one after
+// the other in a fixed order:
+//
+//   0x00  "GBST"
+//   0x04  format version, u16
+//   0x06  global checksum of the ROM the state belongs to, as stored in the header
+//   0x08  the components, starting with the CPU
+//
+// Numbers are little endian. Components check the version of the state they read, so states
+// written by older versions keep loading after the format grows. Sound isn't emulated yet, the
+// audio registers are only saved as part of the I/O registers, so an APU will need a new version.
+const MAGIC: [u8; 4] = *b"GBST";
+const HEADER_SIZE: usize = 8;
+
+/// The version written by this build, bumped whenever the layout of a component changes:
+///
+/// 1. The initial format
+/// 2. Adds the joypad after the interrupt enable register
+/// 3. Adds whether the boot ROM is mapped after the joypad
+pub const VERSION: u16 = 3;
+
+/// Number of save state slots, selected with `--save-state` and `--load-state`.
+pub const SLOTS: u8 = 10;
+
+#[derive(Debug)]
+pub enum StateError {
+    InvalidFormat,
+    UnsupportedVersion(u16),
+    WrongRom {
+        expected: [u8; 2],
+        found: [u8; 2],
+    },
+    WrongBootRom {
+        expected: Option<u32>,
+        found: Option<u32>,
+    },
+    Truncated,
+    InvalidValue(&'static str),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidFormat => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported save state version: {}", version)
+            }
+            StateError::WrongRom { expected, found } => write!(
+                f,
+                "Save state belongs to another ROM: checksum {:04X} instead of {:04X}",
+                u16::from_be_bytes(*found),
+                u16::from_be_bytes(*expected)
+            ),
+            StateError::WrongBootRom { expected, found } => {
+                let name = |checksum: &Option<u32>| match checksum {
+                    Some(checksum) => format!("boot ROM {:08X}", checksum),
+                    None => "no boot ROM".to_string(),
+                };
+
+                write!(
+                    f,
+                    "Recorded with {} instead of {}",
+                    name(found),
+                    name(expected)
+                )
+            }
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::InvalidValue(name) => write!(f, "Invalid {} in save state", name),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Something that goes into save states.
+pub trait Snapshot {
+    fn save(&self, state: &mut StateWriter);
+
+    /// Restores what `save` wrote. On error the component may be left partially restored.
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Debug)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a state for the ROM with the given global checksum.
+    pub fn new(checksum: [u8; 2]) -> Self {
+        let mut data = Vec::with_capacity(0x8000);
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&checksum);
+
+        Self { data }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a block of memory along with its length.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header of a state, which has to belong to the ROM with the given global
+    /// checksum and not come from a newer version.
+    pub fn new(data: &'a [u8], checksum: [u8; 2]) -> Result<Self, StateError> {
+        if data.len() < HEADER_SIZE || data[0..4] != MAGIC {
+            return Err(StateError::InvalidFormat);
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version == 0 || version > VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let found = [data[6], data[7]];
+        if found != checksum {
+            return Err(StateError::WrongRom {
+                expected: checksum,
+                found,
+            });
+        }
+
+        Ok(Self {
+            data: &data[HEADER_SIZE..],
+            version,
+        })
+    }
+
+    /// The version the state was written by.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Fails unless everything was read.
+    pub fn finish(self) -> Result<(), StateError> {
+        match self.data.is_empty() {
+            true => Ok(()),
+            false => Err(StateError::InvalidFormat),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue("flag")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads a block written by `write_bytes`.
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    /// Reads a block written by `write_bytes` into memory of the same size, e.g. cartridge RAM.
+    pub fn read_into(&mut self, dest: &mut [u8]) -> Result<(), StateError> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != dest.len() {
+            return Err(StateError::InvalidValue("memory size"));
+        }
+
+        dest.copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+
+        Ok(array)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < length {
+            return Err(StateError::Truncated);
+        }
+
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+
+        Ok(bytes)
+    }
+}
+
+/// The file for a save state slot, `game.ss0` for slot 0 of `game.gb`, either next to the ROM or
+/// in `save_dir` like battery saves.
+pub fn slot_path(rom_path: &Path, save_dir: Option<&Path>, slot: u8) -> PathBuf {
+    let extension = format!("ss{}", slot);
+
+    match (save_dir, rom_path.file_stem()) {
+        (Some(dir), Some(stem)) => dir.join(format!("{}.{}", stem.to_string_lossy(), extension)),
+        _ => rom_path.with_extension(extension),
+    }
+}
+
+pub fn write(path: &Path, state: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, state)
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Labels from a `.sym` file, as written by RGBDS and no$gmb. Each line maps a label to a bank
+/// and an address, e.g. `01:4A2F UpdateSprites`, see https://rgbds.gbdev.io/sym/
+#[derive(Debug, Clone)]
+pub struct Symbols {
+    labels: HashMap<(u16, u16), String>,
+    addresses: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self {
+            labels: HashMap::new(),
+            addresses: HashMap::new(),
+        }
+    }
+
+    /// Where the symbols for a ROM are looked for, right next to it.
+    pub fn path(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension("sym")
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Symbols::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the contents of a `.sym` file, skipping comments and lines it doesn't understand.
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Symbols::new();
+
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+
+            let (Some(location), Some(label)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let Some((bank, address)) = location.split_once(':') else {
+                continue;
+            };
+            let (Ok(bank), Ok(address)) = (
+                u16::from_str_radix(bank, 16),
+                u16::from_str_radix(address, 16),
+            ) else {
+                continue;
+            };
+
+            symbols.insert(bank, address, label);
+        }
+
+        symbols
+    }
+
+    /// Adds a label, unless the address already has one.
+    pub fn insert(&mut self, bank: u16, address: u16, label: &str) {
+        self.labels
+            .entry((bank, address))
+            .or_insert_with(|| label.to_string());
+        self.addresses.insert(label.to_string(), (bank, address));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The label at an address, when `bank` is mapped there.
+    pub fn label(&self, bank: u16, address: u16) -> Option<&str> {
+        self.labels.get(&(bank, address)).map(String::as_str)
+    }
+
+    /// The bank and address of a label.
+    pub fn address(&self, label: &str) -> Option<(u16, u16)> {
+        self.addresses.get(label).copied()
+    }
+}
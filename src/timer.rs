@@ -0,0 +1,160 @@
+use crate::cpu::interrupts::Interrupt;
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::utils::traits::Storage;
+
+// See https://gbdev.io/pandocs/Timer_and_Divider_Registers.html and
+// https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+const DIV: usize = 0xFF04;
+const TIMA: usize = 0xFF05;
+const TMA: usize = 0xFF06;
+const TAC: usize = 0xFF07;
+
+const TAC_ENABLE: u8 = 0b0000_0100;
+
+#[derive(Debug)]
+pub struct Timer {
+    // The 16-bit system counter, DIV is its upper byte
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+
+    // TIMA overflowed during the last M-cycle and reads 0 until it gets reloaded in this one
+    overflow: bool,
+    // TIMA was reloaded from TMA during the current M-cycle
+    reloading: bool,
+    // Interrupts raised since the last tick
+    interrupts: u8,
+}
+
+impl Storage<usize, u8> for Timer {
+    fn read(&mut self, src: usize) -> u8 {
+        match src {
+            DIV => (self.counter >> 8) as u8,
+            TIMA => self.tima,
+            TMA => self.tma,
+            TAC => 0xF8 | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        let signal = self.signal();
+
+        match dest {
+            // Resetting the counter can pull the selected bit low, which counts as a falling edge
+            DIV => self.counter = 0,
+            // Writing TIMA while it waits for the reload cancels it, writing it on the cycle
+            // of the reload is ignored since TMA wins
+            TIMA if self.reloading => (),
+            TIMA => {
+                self.overflow = false;
+                self.tima = value;
+            }
+            TMA => {
+                self.tma = value;
+
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            TAC => self.tac = value & 0x07,
+            _ => (),
+        }
+
+        if signal && !self.signal() {
+            self.increment();
+        }
+    }
+}
+
+// Interrupts are handed over at the end of every tick, so there are never any to save
+impl Snapshot for Timer {
+    fn save(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac);
+        state.write_bool(self.overflow);
+        state.write_bool(self.reloading);
+    }
+
+    fn load(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.counter = state.read_u16()?;
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.tac = state.read_u8()? & 0x07;
+        self.overflow = state.read_bool()?;
+        self.reloading = state.read_bool()?;
+        self.interrupts = 0;
+
+        Ok(())
+    }
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            overflow: false,
+            reloading: false,
+            interrupts: 0,
+        }
+    }
+
+    /// Sets the system counter without the side effects of writing to DIV.
+    pub fn set_counter(&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    /// Advances the timer by a number of T-cycles and returns the interrupts it requested, as a
+    /// mask of IF bits.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        for _ in 0..cycles / 4 {
+            self.step();
+        }
+
+        std::mem::take(&mut self.interrupts)
+    }
+
+    // Advances the timer by one M-cycle
+    fn step(&mut self) {
+        self.reloading = false;
+
+        if self.overflow {
+            self.overflow = false;
+            self.reloading = true;
+            self.tima = self.tma;
+            self.interrupts |= Interrupt::Timer.mask();
+        }
+
+        let signal = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+
+        if signal && !self.signal() {
+            self.increment();
+        }
+    }
+
+    // TIMA counts the falling edges of one of the counter's bits, AND'ed with the enable bit
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0x03 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+
+        self.tac & TAC_ENABLE != 0 && (self.counter >> bit) & 0x01 != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+
+        self.tima = tima;
+        self.overflow = overflow;
+    }
+}
@@ -0,0 +1,18 @@
+// The CRC-32 used by PNG and zip, see https://www.w3.org/TR/png/#D-CRCAppendix
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// CRC-32 of a sequence of bytes.
+pub fn crc32<'a>(data: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (POLYNOMIAL & mask);
+        }
+    }
+
+    !crc
+}
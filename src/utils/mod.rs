@@ -1 +1,3 @@
+pub mod crc;
+pub mod png;
 pub mod traits;
@@ -0,0 +1,172 @@
+mod common;
+
+use common::FlatBus;
+use emulator::asm;
+use emulator::cpu::assembler::{assemble, Program};
+use emulator::cpu::disassembler::disassemble;
+use emulator::cpu::registers::Reg8;
+use emulator::cpu::CPU;
+use emulator::utils::traits::Storage;
+
+const HALT: u8 = 0x76;
+
+// Upper bound on the instructions a program gets to run before it's assumed to be stuck
+const MAX_STEPS: usize = 10_000;
+
+// Runs the program from 0x0100, where execution starts after the boot ROM, until it halts
+fn run(program: &Program, check: impl FnOnce(&mut CPU<FlatBus>)) {
+    let mut bus = FlatBus::default();
+    program.load(&mut bus);
+
+    let mut cpu = CPU::new(&mut bus);
+
+    for _ in 0..MAX_STEPS {
+        let pc = cpu.registers().pc.pointer.0;
+
+        if cpu.bus().memory[pc as usize] == HALT {
+            return check(&mut cpu);
+        }
+
+        cpu.step();
+    }
+
+    panic!("program didn't halt");
+}
+
+#[test]
+fn reassembles_every_opcode() {
+    let address = 0x0200;
+    let mut opcodes: Vec<Vec<u8>> = (0..=0xFF).map(|opcode| vec![opcode]).collect();
+    opcodes.extend((0..=0xFF).map(|opcode| vec![0xCB, opcode]));
+
+    for opcode in opcodes {
+        let mut memory = opcode.clone();
+
+        // STOP's padding byte is always assembled as zero
+        if opcode != [0x10] {
+            memory.extend([0x80, 0xC1]);
+        }
+
+        let line = disassemble(address, |addr| {
+            memory
+                .get(addr.wrapping_sub(address) as usize)
+                .copied()
+                .unwrap_or(0)
+        });
+
+        if line.instruction.is_none() || opcode == [0xCB] {
+            continue;
+        }
+
+        let program = assemble(&format!("org ${:04X}\n{}", address, line))
+            .unwrap_or_else(|error| panic!("{} ({:02X?}): {}", line, opcode, error));
+
+        assert_eq!(program.origin, address, "{}", line);
+        assert_eq!(program.bytes, line.bytes, "{}", line);
+    }
+}
+
+#[test]
+fn runs_assembled_code() {
+    let program = asm!(
+        "org $100
+         ld a, $10
+         ld b, $22
+         add a, b
+         halt"
+    );
+
+    run(&program, |cpu| {
+        assert_eq!(cpu.registers().read(Reg8::A), 0x32);
+    });
+}
+
+#[test]
+fn resolves_labels() {
+    // Sums 1 through 10, with a forward call and a backward relative jump
+    let program = asm!(
+        "    org $0100
+             call sum
+             ld [result], a
+             halt
+
+         sum:
+             ld b, 10
+             xor a
+         .loop:
+             add b          ; shorthand for ADD A,B
+             dec b
+             jr nz, .loop
+             ret
+
+         result: db 0"
+    );
+
+    run(&program, |cpu| {
+        assert_eq!(cpu.registers().read(Reg8::A), 55);
+        assert_eq!(cpu.bus().memory[0x0100 + program.bytes.len() - 1], 55);
+    });
+}
+
+#[test]
+fn assembles_data() {
+    let program = asm!(
+        "org $C000
+         start:
+             db 1, $02, %11, -1, \"Hi, there\"
+             dw start, $1234
+         org $C014
+             db end - start
+         end:"
+    );
+
+    let mut expected = vec![1, 2, 3, 0xFF];
+    expected.extend(b"Hi, there");
+    expected.extend([0x00, 0xC0, 0x34, 0x12, 0x00, 0x00, 0x00, 0x15]);
+
+    assert_eq!(program.origin, 0xC000);
+    assert_eq!(program.bytes, expected);
+}
+
+#[test]
+fn accepts_alternative_syntax() {
+    let program = asm!(
+        "ld a, (hli)
+         ld [hld], a
+         ld a, ($ff00+c)
+         ldh ($FF44), a
+         ld hl, sp-2
+         jp (hl)
+         rst 56
+         bit 2 + 1, [hl]
+         Stop"
+    );
+
+    assert_eq!(
+        program.bytes,
+        [0x2A, 0x32, 0xF2, 0xE0, 0x44, 0xF8, 0xFE, 0xE9, 0xFF, 0xCB, 0x5E, 0x10, 0x00]
+    );
+}
+
+#[test]
+fn reports_errors() {
+    let cases = [
+        ("nop\nld a, (de+)", 2, "Unknown instruction: ld a, (de+)"),
+        ("jp nowhere", 1, "Undefined label: NOWHERE"),
+        ("ld a, 256", 1, "Value out of range: 256"),
+        ("org $200\nnop\norg $100", 3, "Invalid origin: $100"),
+        ("jr far\norg $1000\nfar: nop", 1, "Offset out of range: FAR"),
+        ("start: nop\nstart: nop", 2, "Duplicate label: start"),
+    ];
+
+    for (source, line, message) in cases {
+        let error = assemble(source).unwrap_err();
+
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (line, message),
+            "{}",
+            source
+        );
+    }
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::path::Path;
+
+use emulator::asm;
+use emulator::boot::{BootRom, Model};
+use emulator::cartridge::header::CartridgeHeader;
+use emulator::cpu::registers::{Reg16, Reg8, Registers};
+use emulator::cpu::CPU;
+use emulator::state::StateError;
+use emulator::utils::traits::Storage;
+
+const ROMS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/roms");
+
+// Marks its run in WRAM and unmaps itself with its last instruction, like the real ones do
+const BOOT_ROM: &str = "
+        org $0000
+        ld sp, $FFFE
+        ld a, $42
+        ld [$C000], a
+        jp $00FB
+
+        org $00FB
+        ld a, $01
+        ld [$FF50], a
+";
+
+fn boot_rom(size: usize) -> BootRom {
+    let program = asm!(BOOT_ROM);
+    let mut data = vec![0xFF; size];
+    data[..program.bytes.len()].copy_from_slice(&program.bytes);
+
+    BootRom::new(data).unwrap()
+}
+
+#[test]
+fn overlays_the_cartridge_until_unmapped() {
+    let mut bus = common::cartridge("");
+    bus.set_boot_rom(boot_rom(0x100));
+    let value: u8 = bus.read(0x0000);
+    assert_eq!(value, 0x31);
+    let value: u8 = bus.read(0x0100);
+    assert_eq!(value, 0x00);
+
+    let mut cpu = CPU::new(&mut bus);
+    *cpu.registers() = Registers::power_on();
+    while cpu.registers().pc.pointer.0 != 0x0100 {
+        cpu.step();
+    }
+
+    let bus = cpu.bus();
+    assert!(!bus.boot_rom_mapped());
+    let value: u8 = bus.read(0xC000);
+    assert_eq!(value, 0x42);
+    let value: u8 = bus.read(0x0000);
+    assert_eq!(value, 0x00);
+
+    // It can't be mapped back in
+    bus.write(0xFF50, 0x00u8);
+    let value: u8 = bus.read(0x0000);
+    assert_eq!(value, 0x00);
+}
+
+#[test]
+fn leaves_the_header_visible_with_a_cgb_boot_rom() {
+    let mut bus = common::cartridge("");
+    bus.set_boot_rom(boot_rom(0x900));
+
+    let value: u8 = bus.read(0x0000);
+    assert_eq!(value, 0x31);
+    let value: u8 = bus.read(0x0150);
+    assert_eq!(value, 0x00);
+    let value: u8 = bus.read(0x0200);
+    assert_eq!(value, 0xFF);
+    let value: u8 = bus.read(0x0900);
+    assert_eq!(value, 0x00);
+
+    // Only bit 0 unmaps it
+    bus.write(0xFF50, 0xFEu8);
+    assert!(bus.boot_rom_mapped());
+    bus.write(0xFF50, 0x01u8);
+    let value: u8 = bus.read(0x0200);
+    assert_eq!(value, 0x00);
+}
+
+#[test]
+fn tells_the_models_apart() {
+    let dmg = boot_rom(0x100);
+    assert_eq!(dmg.model(), Model::Dmg);
+    assert!(dmg.runs_on(Model::Sgb));
+    assert!(!dmg.runs_on(Model::Cgb));
+
+    let cgb = boot_rom(0x900);
+    assert_eq!(cgb.model(), Model::Cgb);
+    assert!(cgb.runs_on(Model::Cgb));
+    assert!(!cgb.runs_on(Model::Dmg));
+}
+
+#[test]
+fn rejects_other_sizes() {
+    assert!(BootRom::new(vec![0; 0x100]).is_ok());
+    assert!(BootRom::new(vec![0; 0x900]).is_ok());
+    assert!(BootRom::new(vec![0; 0x200]).is_err());
+    assert!(BootRom::new(Vec::new()).is_err());
+}
+
+#[test]
+fn saves_whether_it_is_mapped() {
+    let mut bus = common::cartridge("");
+    bus.set_boot_rom(boot_rom(0x100));
+    let mut cpu = CPU::new(&mut bus);
+    *cpu.registers() = Registers::power_on();
+    cpu.step();
+    let saved = cpu.save_state([0, 0]);
+
+    let mut other_bus = common::cartridge("");
+    other_bus.set_boot_rom(boot_rom(0x100));
+    other_bus.write(0xFF50, 0x01u8);
+    let mut other = CPU::new(&mut other_bus);
+    other.load_state(&saved, [0, 0]).unwrap();
+    assert!(other.bus().boot_rom_mapped());
+
+    // Without the boot ROM there's nothing to map back in
+    let mut other_bus = common::cartridge("");
+    let mut other = CPU::new(&mut other_bus);
+    assert!(matches!(
+        other.load_state(&saved, [0, 0]),
+        Err(StateError::InvalidValue(_))
+    ));
+}
+
+#[test]
+fn sets_up_the_registers_left_by_each_model() {
+    // Made for the CGB as well
+    let header = CartridgeHeader::load(&Path::new(ROMS).join("cpu_instrs.gb")).unwrap();
+    assert_ne!(header.header_checksum, 0);
+
+    let expected = [
+        (Model::Dmg0, [0x0100, 0xFF13, 0x00C1, 0x8403]),
+        (Model::Dmg, [0x01B0, 0x0013, 0x00D8, 0x014D]),
+        (Model::Mgb, [0xFFB0, 0x0013, 0x00D8, 0x014D]),
+        (Model::Sgb, [0x0100, 0x0014, 0x0000, 0xC060]),
+        (Model::Cgb, [0x1180, 0x0000, 0xFF56, 0x000D]),
+    ];
+
+    for (model, [af, bc, de, hl]) in expected {
+        let mut registers = Registers::post_boot(model, &header);
+        let actual: [u16; 4] =
+            [Reg16::AF, Reg16::BC, Reg16::DE, Reg16::HL].map(|register| registers.read(register));
+
+        assert_eq!(actual, [af, bc, de, hl], "{:?}", model);
+        assert_eq!(registers.pc.pointer.0, 0x0100);
+        assert_eq!(registers.sp.pointer.0, 0xFFFE);
+    }
+
+    // The CGB runs DMG only games in compatibility mode
+    let header = CartridgeHeader::load(&Path::new(ROMS).join("dmg-acid2.gb")).unwrap();
+    let mut registers = Registers::post_boot(Model::Cgb, &header);
+    let actual =
+        [Reg16::AF, Reg16::BC, Reg16::DE, Reg16::HL].map(|register| registers.read(register));
+    assert_eq!(actual, [0x1180, 0x0000, 0x0008, 0x007C]);
+
+    let mut registers = Registers::power_on();
+    assert_eq!(registers.read(Reg8::A), 0x00);
+    assert_eq!(registers.pc.pointer.0, 0x0000);
+}
+
+#[test]
+fn sets_up_the_hardware_left_by_each_model() {
+    // P1, SC, DIV, TAC, IF, NR52, LCDC, BGP and DMA
+    let addresses = [
+        0xFF00, 0xFF02, 0xFF04, 0xFF07, 0xFF0F, 0xFF26, 0xFF40, 0xFF47, 0xFF46,
+    ];
+    let expected = [
+        (
+            Model::Dmg0,
+            [0xCF, 0x7E, 0x18, 0xF8, 0xE1, 0xF1, 0x91, 0xFC, 0xFF],
+        ),
+        (
+            Model::Dmg,
+            [0xCF, 0x7E, 0xAB, 0xF8, 0xE1, 0xF1, 0x91, 0xFC, 0xFF],
+        ),
+        (
+            Model::Sgb,
+            [0xCF, 0x7E, 0x00, 0xF8, 0xE1, 0xF0, 0x91, 0xFC, 0xFF],
+        ),
+        (
+            Model::Cgb,
+            [0xCF, 0x7F, 0x00, 0xF8, 0xE1, 0xF1, 0x91, 0xFC, 0x00],
+        ),
+    ];
+
+    for (model, values) in expected {
+        let mut bus = common::cartridge("");
+        bus.skip_boot(model);
+
+        let actual = addresses.map(|address| -> u8 { bus.read(address) });
+        assert_eq!(actual, values, "{:?}", model);
+    }
+}
@@ -0,0 +1,122 @@
+// Helpers shared by the integration tests. Each test binary only uses some of them
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use emulator::asm;
+use emulator::boot::Model;
+use emulator::cartridge::header::CartridgeHeader;
+use emulator::cartridge::mbc;
+use emulator::cartridge::mbc::mbc3::MBC3;
+use emulator::cartridge::mbc::rom_only::RomOnly;
+use emulator::cartridge::mbc::ROM_BANK_SIZE;
+use emulator::memory::bus::{Bus, MemoryBus};
+use emulator::utils::traits::Storage;
+
+/// Emulated T-cycles in one second.
+pub const SECONDS: u64 = 4_194_304;
+
+#[derive(Debug)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Timeout,
+}
+
+/// 64 KiB of RAM with nothing mapped.
+#[derive(Debug)]
+pub struct FlatBus {
+    pub memory: Vec<u8>,
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self {
+            memory: vec![0; 0x10000],
+        }
+    }
+}
+
+impl Storage<usize, u8> for FlatBus {
+    fn read(&mut self, src: usize) -> u8 {
+        self.memory[src]
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        self.memory[dest] = value;
+    }
+}
+
+impl Bus for FlatBus {
+    fn tick(&mut self, _cycles: u32) {}
+}
+
+/// A flat bus with the program assembled into it.
+pub fn load(program: &str) -> FlatBus {
+    let mut bus = FlatBus::default();
+    asm!(program).load(&mut bus);
+    bus
+}
+
+/// A 32 KiB ROM only cartridge with the program assembled into it.
+pub fn cartridge(program: &str) -> MemoryBus {
+    let mut rom = vec![0; 0x8000];
+    let program = asm!(program);
+    let origin = program.origin as usize;
+    rom[origin..origin + program.bytes.len()].copy_from_slice(&program.bytes);
+
+    MemoryBus::new(Box::new(RomOnly::new(rom, 0)))
+}
+
+/// An MBC3 cartridge with 64 KiB of ROM, 8 KiB of RAM and a clock, with the program assembled
+/// into it.
+pub fn mbc3_cartridge(program: &str) -> MemoryBus {
+    let program = asm!(program);
+    let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+    let origin = program.origin as usize;
+    rom[origin..origin + program.bytes.len()].copy_from_slice(&program.bytes);
+
+    MemoryBus::new(Box::new(MBC3::new(rom, 0x2000, true)))
+}
+
+pub fn load_rom(path: &Path) -> MemoryBus {
+    let header = CartridgeHeader::load(path).expect("failed to load the ROM");
+    let cartridge = mbc::new(header).expect("unsupported cartridge");
+
+    // Test ROMs expect to be started the way the DMG boot ROM leaves things
+    let mut bus = MemoryBus::new(cartridge);
+    bus.skip_boot(Model::Dmg);
+    bus
+}
+
+/// Prints a pass/fail table along with the details of every failure, and returns how many ROMs
+/// didn't pass.
+pub fn report(results: &[(String, Outcome)]) -> usize {
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+
+    println!("{:<width$} RESULT", "ROM");
+    for (name, outcome) in results {
+        let result = match outcome {
+            Outcome::Passed => "pass",
+            Outcome::Failed(_) => "FAIL",
+            Outcome::Timeout => "TIMEOUT",
+        };
+        println!("{:<width$} {}", name, result);
+    }
+
+    for (name, outcome) in results {
+        if let Outcome::Failed(details) = outcome {
+            println!("\n{}:\n{}", name, details);
+        }
+    }
+
+    results
+        .iter()
+        .filter(|(_, outcome)| !matches!(outcome, Outcome::Passed))
+        .count()
+}
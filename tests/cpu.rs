@@ -0,0 +1,353 @@
+mod common;
+
+use emulator::cpu::instruction::Instruction;
+use emulator::cpu::registers::Reg8;
+use emulator::cpu::{Mode, CPU, CYCLES_PER_FRAME};
+use emulator::joypad::Button;
+use emulator::utils::traits::Storage;
+
+const HALT: u8 = 0x76;
+const IF: usize = 0xFF0F;
+const KEY1: usize = 0xFF4D;
+
+// Upper bound on the instructions a program gets to run before it's assumed to be stuck
+const MAX_STEPS: usize = 10_000;
+
+// Steps through the program from 0x0100 and returns the T-cycles taken by each instruction up to
+// the first HALT
+fn timings(program: &str) -> Vec<u32> {
+    let mut bus = common::load(program);
+    let mut cpu = CPU::new(&mut bus);
+    let mut timings = Vec::new();
+
+    loop {
+        let pc = cpu.registers().pc.pointer.0;
+
+        if cpu.bus().memory[pc as usize] == HALT {
+            break;
+        }
+
+        timings.push(cpu.step());
+    }
+
+    assert_eq!(cpu.cycles(), timings.iter().map(|&t| t as u64).sum::<u64>());
+    timings
+}
+
+#[test]
+fn counts_cycles_per_instruction() {
+    let timings = timings(
+        "    org $0038
+             reti
+
+             org $0100
+             nop
+             ld bc, $C000
+             ld hl, $C000
+             ld [hl], $12
+             ld a, [$C000]
+             push bc
+             pop de
+             call sub
+             rst $38
+             jr next
+         next:
+             jp done
+         done:
+             ld [$C002], sp
+             add sp, 2
+             ld hl, sp+2
+             bit 0, [hl]
+             set 0, [hl]
+             halt
+
+         sub:
+             ret",
+    );
+
+    assert_eq!(
+        timings,
+        [4, 12, 12, 12, 16, 16, 12, 24, 16, 16, 16, 12, 16, 20, 16, 12, 12, 16]
+    );
+}
+
+#[test]
+fn counts_taken_branches_longer() {
+    let timings = timings(
+        "    org $0100
+             xor a               ; Sets Z
+             jr z, jr_taken
+         jr_taken:
+             jr nz, jr_taken
+             jp z, jp_taken
+         jp_taken:
+             jp nz, jp_taken
+             call z, sub
+             call nz, sub
+             halt
+
+         sub:
+             ret nz
+             ret z",
+    );
+
+    assert_eq!(timings, [4, 12, 8, 16, 12, 24, 8, 20, 12]);
+}
+
+#[test]
+fn runs_until_an_instruction_boundary() {
+    // Each pass through the loop takes 4 + 4 + 12 cycles
+    let mut bus = common::load(
+        "    org $0100
+         loop:
+             nop
+             nop
+             jr loop",
+    );
+    let mut cpu = CPU::new(&mut bus);
+
+    cpu.run_until(100);
+    assert_eq!(cpu.cycles(), 100);
+
+    // Instructions aren't cut short, the JR finishes past the target
+    cpu.run_until(110);
+    assert_eq!(cpu.cycles(), 120);
+
+    // Nothing runs if the target has already been reached
+    cpu.run_until(120);
+    assert_eq!(cpu.cycles(), 120);
+}
+
+#[test]
+fn runs_a_frame_at_a_time() {
+    let mut bus = common::load(
+        "    org $0100
+         loop:
+             jr loop",
+    );
+    let mut cpu = CPU::new(&mut bus);
+
+    assert_eq!(CYCLES_PER_FRAME, 70224);
+    assert_eq!(cpu.run_frame(), CYCLES_PER_FRAME);
+    assert_eq!(cpu.run_frame(), CYCLES_PER_FRAME);
+    assert_eq!(cpu.cycles(), 2 * CYCLES_PER_FRAME);
+
+    // A frame overshoots by less than an instruction when the two don't line up
+    let mut bus = common::load(
+        "    org $0100
+         loop:
+             nop
+             nop
+             jr loop",
+    );
+    let mut cpu = CPU::new(&mut bus);
+
+    let first = cpu.run_frame();
+    let second = cpu.run_frame();
+    assert!((CYCLES_PER_FRAME..CYCLES_PER_FRAME + 12).contains(&first));
+    assert!((CYCLES_PER_FRAME..CYCLES_PER_FRAME + 12).contains(&second));
+    assert_eq!(cpu.cycles(), first + second);
+}
+
+// Runs until the CPU locks up on the undefined opcode the program ends with
+fn run_to_lock(cpu: &mut CPU) {
+    for _ in 0..MAX_STEPS {
+        if let Mode::Locked(_) = cpu.mode() {
+            return;
+        }
+
+        cpu.step();
+    }
+
+    panic!("program didn't lock up");
+}
+
+#[test]
+fn switches_speed_only_in_cgb_mode() {
+    let program = "
+             org $0100
+             ld a, $01
+             ld [$FF4D], a       ; Arm the speed switch
+             stop
+             db $DD";
+
+    // There's no KEY1 on the DMG, so STOP actually stops
+    let mut bus = common::cartridge(program);
+    let mut cpu = CPU::new(&mut bus);
+    cpu.run_frame();
+    assert!(matches!(cpu.mode(), Mode::Stopped));
+    let key1: u8 = cpu.bus().read(KEY1);
+    assert_eq!(key1, 0xFF);
+
+    let mut bus = common::cartridge(program);
+    bus.set_cgb_mode(true);
+    let mut cpu = CPU::new(&mut bus);
+    run_to_lock(&mut cpu);
+    let key1: u8 = cpu.bus().read(KEY1);
+    assert_eq!(key1, 0xFE);
+
+    // The current speed can't be written
+    cpu.bus().write(KEY1, 0x00u8);
+    let key1: u8 = cpu.bus().read(KEY1);
+    assert_eq!(key1, 0xFE);
+}
+
+#[test]
+fn enables_interrupts_after_the_next_instruction() {
+    let mut bus = common::cartridge(
+        "    org $0050
+             db $DD              ; Timer interrupt
+
+             org $0100
+             ld a, $04
+             ld [$FFFF], a
+             ld [$FF0F], a       ; Timer interrupt pending
+             ei
+             inc b               ; Still runs
+             inc b
+             db $DD",
+    );
+    let mut cpu = CPU::new(&mut bus);
+    run_to_lock(&mut cpu);
+
+    assert_eq!(cpu.registers().read(Reg8::B), 1);
+    assert_eq!(cpu.registers().pc.pointer.0, 0x0051);
+
+    // DI straight after EI cancels it
+    let mut bus = common::cartridge(
+        "    org $0050
+             db $DD
+
+             org $0100
+             ld a, $04
+             ld [$FFFF], a
+             ld [$FF0F], a
+             ei
+             di
+             inc b
+             inc b
+             db $DD",
+    );
+    let mut cpu = CPU::new(&mut bus);
+    run_to_lock(&mut cpu);
+
+    assert_eq!(cpu.registers().read(Reg8::B), 2);
+}
+
+#[test]
+fn repeats_the_byte_after_halt_with_an_interrupt_pending() {
+    let mut bus = common::cartridge(
+        "    org $0100
+             ld a, $04
+             ld [$FFFF], a
+             ld [$FF0F], a
+             halt                ; IME is off, so this doesn't halt
+             inc b
+             db $DD",
+    );
+    let mut cpu = CPU::new(&mut bus);
+    run_to_lock(&mut cpu);
+
+    assert_eq!(cpu.registers().read(Reg8::B), 2);
+}
+
+#[test]
+fn wakes_from_halt_without_ime() {
+    let mut bus = common::cartridge(
+        "    org $0050
+             ld c, $50
+             db $DD
+
+             org $0100
+             ld a, $04
+             ld [$FFFF], a
+             xor a
+             ld [$FF0F], a
+             ld a, $FF
+             ld [$FF05], a       ; TIMA overflows on the next increment
+             ld a, $05
+             ld [$FF07], a       ; Every 16 T-cycles
+             halt
+             inc b
+             db $DD",
+    );
+    let mut cpu = CPU::new(&mut bus);
+    run_to_lock(&mut cpu);
+
+    // Execution carries on after HALT, and the interrupt stays pending without a handler
+    assert_eq!(cpu.registers().read(Reg8::B), 1);
+    assert_eq!(cpu.registers().read(Reg8::C), 0x13);
+    let requests: u8 = cpu.bus().read(IF);
+    assert_eq!(requests & 0x04, 0x04);
+}
+
+#[test]
+fn stops_until_a_button_is_pressed() {
+    let mut bus = common::cartridge(
+        "    org $0100
+             ld a, $20
+             ld [$FF00], a       ; Select the d-pad
+             stop
+             inc b
+             db $DD",
+    );
+    let mut cpu = CPU::new(&mut bus);
+
+    cpu.run_frame();
+    assert!(matches!(cpu.mode(), Mode::Stopped));
+
+    // Buttons on the unselected line don't wake it
+    cpu.set_button(Button::A, true);
+    cpu.run_frame();
+    assert!(matches!(cpu.mode(), Mode::Stopped));
+
+    cpu.set_button(Button::Down, true);
+    run_to_lock(&mut cpu);
+    assert_eq!(cpu.registers().read(Reg8::B), 1);
+}
+
+#[test]
+fn stays_stopped_through_other_interrupts() {
+    let mut bus = common::cartridge(
+        "    org $0050
+             inc c
+             reti
+
+             org $0100
+             ld c, $00           ; Counts the timer interrupts
+             ld a, $05
+             ld [$FF07], a       ; Start the timer at 262144 Hz
+             ld a, $04
+             ld [$FFFF], a       ; Timer interrupt only
+             ld a, $20
+             ld [$FF00], a       ; Select the d-pad
+             ei
+             stop
+             inc b
+             db $DD",
+    );
+    let mut cpu = CPU::new(&mut bus);
+
+    cpu.run_frame();
+    assert!(matches!(cpu.mode(), Mode::Stopped));
+    let requests: u8 = cpu.bus().read(IF);
+    assert_eq!(requests & 0x04, 0x04);
+    assert_eq!(cpu.registers().read(Reg8::C), 0);
+
+    // The pending timer interrupt is taken once a button wakes it
+    cpu.set_button(Button::Down, true);
+    run_to_lock(&mut cpu);
+    assert_eq!(cpu.registers().read(Reg8::C), 1);
+    assert_eq!(cpu.registers().read(Reg8::B), 1);
+}
+
+#[test]
+fn decodes_every_cb_opcode() {
+    for opcode in 0..=0xFF {
+        assert!(
+            Instruction::from_byte_prefixed(opcode).is_some(),
+            "CB {:02X}",
+            opcode
+        );
+    }
+}
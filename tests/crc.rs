@@ -0,0 +1,7 @@
+use emulator::utils::crc::crc32;
+
+#[test]
+fn matches_the_check_value() {
+    assert_eq!(crc32(b"123456789".iter()), 0xCBF4_3926);
+    assert_eq!(crc32([].iter()), 0);
+}
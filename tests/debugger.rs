@@ -0,0 +1,124 @@
+mod common;
+
+use emulator::cpu::CPU;
+use emulator::debugger::Debugger;
+
+const PROGRAM: &str = "
+        org $0100
+        ld a, $10           ; 0100
+        call double         ; 0102
+        ld [$C000], a       ; 0105
+        ld b, b             ; 0108
+        db $DD              ; 0109
+
+    double:
+        add a, a            ; 010A
+        ld hl, $C001        ; 010B
+        ld [hl], a          ; 010E
+        ret                 ; 010F
+";
+
+// Feeds the commands to the debugger and returns everything it printed, minus the prompts
+fn debug(commands: &str) -> Vec<String> {
+    let mut bus = common::load(PROGRAM);
+
+    let mut cpu = CPU::new(&mut bus);
+    let mut out = Vec::new();
+
+    Debugger::new()
+        .run(&mut cpu, commands.as_bytes(), &mut out)
+        .expect("failed to write the output");
+
+    String::from_utf8(out)
+        .unwrap()
+        .split("> ")
+        .flat_map(|chunk| chunk.lines().map(str::to_string).collect::<Vec<_>>())
+        .collect()
+}
+
+#[test]
+fn steps() {
+    let output = debug("step\n\nstep 2\nregs");
+
+    assert_eq!(
+        output,
+        [
+            "0100  3E 10      LD A,$10",
+            "0102  CD 0A 01   CALL $010A",
+            "010A  87         ADD A,A",
+            "010E  77         LD (HL),A",
+            "A:20 F:00 B:00 C:13 D:00 E:D8 H:C0 L:01 SP:FFFC PC:010E",
+            "Flags: ----  IME: 0  Cycles: 48",
+        ]
+    );
+}
+
+#[test]
+fn stops_at_breakpoints() {
+    let output = debug("break 10e\nbreak\ncontinue\nfinish\ncontinue\ncontinue\nregs");
+
+    assert_eq!(
+        output,
+        [
+            "0100  3E 10      LD A,$10",
+            "Breakpoint at $010E",
+            "Breakpoint at $010E",
+            "010E  77         LD (HL),A",
+            "0105  EA 00 C0   LD ($C000),A",
+            "Software breakpoint (LD B,B)",
+            "0109  DD         DB $DD",
+            "Invalid opcode $DD, the CPU locked up",
+            "010A  87         ADD A,A",
+            "A:20 F:00 B:00 C:13 D:00 E:D8 H:C0 L:01 SP:FFFE PC:010A",
+            "Flags: ----  IME: 0  Cycles: 96",
+        ]
+    );
+}
+
+#[test]
+fn stops_at_watchpoints() {
+    let output =
+        debug("watch c001 r\nwatch c000 w\ncontinue\nmem c000 2\ndelete c000\ndelete c000");
+
+    assert_eq!(
+        output,
+        [
+            "0100  3E 10      LD A,$10",
+            "Wrote $20 to $C000",
+            "0108  40         LD B,B",
+            "C000  20 20",
+            "Nothing set on $C000",
+        ]
+    );
+}
+
+#[test]
+fn edits_registers() {
+    let output = debug("set pc 10a\nset a 7\nset hl $C000\nset b 100\nstep 3\nmem c001 1\nbogus");
+
+    assert_eq!(
+        output,
+        [
+            "0100  3E 10      LD A,$10",
+            "Value doesn't fit in B: $0100",
+            "010F  C9         RET",
+            "C001  0E",
+            "Invalid command: bogus, try `help`",
+        ]
+    );
+}
+
+#[test]
+fn disassembles() {
+    let output = debug("disasm 10a");
+
+    assert_eq!(
+        &output[1..5],
+        [
+            "010A  87         ADD A,A",
+            "010B  21 01 C0   LD HL,$C001",
+            "010E  77         LD (HL),A",
+            "010F  C9         RET",
+        ]
+    );
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream};
+use std::thread;
+
+use emulator::cpu::CPU;
+use emulator::gdb::GdbStub;
+
+const PROGRAM: &str = "
+        org $0100
+        ld a, $10           ; 0100
+        call double         ; 0102
+        ld [$C000], a       ; 0105
+        db $DD              ; 0108
+
+    double:
+        add a, a            ; 0109
+        ret                 ; 010A
+
+    spin:
+        jr spin             ; 010B
+";
+
+/// The debugger's end of the connection.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    // Sends a packet and returns the reply, acknowledging both ways
+    fn send(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+
+        assert_eq!(self.read_byte(), b'+', "{} wasn't acknowledged", data);
+        self.reply()
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+
+        // The checksum
+        self.read_byte();
+        self.read_byte();
+        self.stream.write_all(b"+").unwrap();
+
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+
+        byte[0]
+    }
+}
+
+// Runs the stub on the program while `session` talks to it, then detaches
+fn debug(session: impl FnOnce(&mut Client) + Send + 'static) {
+    attach(|client| {
+        session(client);
+        assert_eq!(client.send("D"), "OK");
+    });
+}
+
+// Runs the stub on the program until `session` is done with it
+fn attach(session: impl FnOnce(&mut Client) + Send + 'static) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || {
+        let mut client = Client {
+            stream: TcpStream::connect(address).unwrap(),
+        };
+
+        session(&mut client);
+    });
+
+    let mut bus = common::load(PROGRAM);
+
+    let mut cpu = CPU::new(&mut bus);
+    let (stream, _) = listener.accept().unwrap();
+
+    GdbStub::new(stream).run(&mut cpu).unwrap();
+    client.join().unwrap();
+}
+
+#[test]
+fn describes_the_target() {
+    debug(|client| {
+        assert!(client
+            .send("qSupported:multiprocess+;swbreak+")
+            .contains("qXfer:features:read+"));
+
+        let xml = client.send("qXfer:features:read:target.xml:0,1000");
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+
+        assert_eq!(client.send("?"), "S05");
+        assert_eq!(client.send("vMustReplyEmpty"), "");
+    });
+}
+
+#[test]
+fn accesses_registers_and_memory() {
+    debug(|client| {
+        assert_eq!(client.send("g"), "01b0001300d8014dfeff0001");
+        assert_eq!(client.send("P9=0901"), "OK");
+        assert_eq!(client.send("P0=42"), "OK");
+        assert_eq!(client.send("p9"), "0901");
+        assert_eq!(client.send("p1"), "b0");
+
+        assert_eq!(client.send("m100,3"), "3e10cd");
+        assert_eq!(client.send("Mc000,2:beef"), "OK");
+        assert_eq!(client.send("mc000,2"), "beef");
+
+        assert_eq!(client.send("G0000000000000000fecf0901"), "OK");
+        assert_eq!(client.send("s"), "S05");
+        assert_eq!(client.send("g"), "0080000000000000fecf0a01");
+    });
+}
+
+#[test]
+fn stops_at_breakpoints_and_watchpoints() {
+    debug(|client| {
+        assert_eq!(client.send("Z0,10a,1"), "OK");
+        assert_eq!(client.send("Z2,c000,1"), "OK");
+
+        assert_eq!(client.send("c"), "S05");
+        assert_eq!(client.send("p9"), "0a01");
+        assert_eq!(client.send("p0"), "20");
+
+        assert_eq!(client.send("c"), "T05watch:c000;");
+        assert_eq!(client.send("p9"), "0801");
+
+        // Undefined opcodes lock up the CPU
+        assert_eq!(client.send("z2,c000,1"), "OK");
+        assert_eq!(client.send("c"), "S04");
+    });
+}
+
+#[test]
+fn can_be_interrupted() {
+    debug(|client| {
+        assert_eq!(client.send("P9=0b01"), "OK");
+
+        write!(client.stream, "$c#63").unwrap();
+        assert_eq!(client.read_byte(), b'+');
+        client.stream.write_all(&[0x03]).unwrap();
+
+        assert_eq!(client.reply(), "S02");
+        assert_eq!(client.send("p9"), "0b01");
+    });
+}
+
+#[test]
+fn keeps_packets_sent_while_running() {
+    debug(|client| {
+        assert_eq!(client.send("P9=0b01"), "OK");
+
+        write!(client.stream, "$c#63").unwrap();
+        assert_eq!(client.read_byte(), b'+');
+        write!(client.stream, "$p9#a9").unwrap();
+        client.stream.write_all(&[0x03]).unwrap();
+
+        assert_eq!(client.reply(), "S02");
+        assert_eq!(client.read_byte(), b'+');
+        assert_eq!(client.reply(), "0b01");
+    });
+}
+
+#[test]
+fn stops_when_disconnected_while_running() {
+    attach(|client| {
+        assert_eq!(client.send("P9=0b01"), "OK");
+
+        write!(client.stream, "$c#63").unwrap();
+        assert_eq!(client.read_byte(), b'+');
+        client.stream.shutdown(Shutdown::Both).unwrap();
+    });
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use emulator::cpu::CPU;
+use emulator::joypad::Button;
+use emulator::memory::bus::Bus;
+use emulator::utils::traits::Storage;
+
+const P1: usize = 0xFF00;
+const IF: usize = 0xFF0F;
+
+// Waits for a joypad interrupt with the d-pad selected, and counts them at 0xC000
+const PROGRAM: &str = "
+        org $0060
+        ld hl, $C000
+        inc [hl]
+        reti
+
+        org $0100
+        ld a, $10
+        ld [$FFFF], a       ; Joypad interrupt only
+        ld a, $20
+        ld [$FF00], a       ; Select the d-pad
+        ei
+    wait:
+        halt
+        jr wait
+";
+
+#[test]
+fn reads_the_selected_lines() {
+    let mut bus = common::cartridge("nop");
+
+    // Nothing selected
+    let p1: u8 = bus.read(P1);
+    assert_eq!(p1, 0xFF);
+
+    bus.set_button(Button::Right, true);
+    bus.set_button(Button::Down, true);
+    bus.set_button(Button::A, true);
+
+    bus.write(P1, 0x20u8);
+    let p1: u8 = bus.read(P1);
+    assert_eq!(p1, 0xE6);
+
+    bus.write(P1, 0x10u8);
+    let p1: u8 = bus.read(P1);
+    assert_eq!(p1, 0xDE);
+
+    // Both groups pull the shared lines low
+    bus.write(P1, 0x00u8);
+    let p1: u8 = bus.read(P1);
+    assert_eq!(p1, 0xC6);
+
+    bus.set_button(Button::Down, false);
+    let p1: u8 = bus.read(P1);
+    assert_eq!(p1, 0xCE);
+    assert!(bus.joypad().is_pressed(Button::A));
+    assert!(!bus.joypad().is_pressed(Button::Down));
+}
+
+#[test]
+fn interrupts_on_falling_edges() {
+    let mut bus = common::cartridge("nop");
+    bus.write(IF, 0x00u8);
+
+    // Buttons on an unselected line don't change anything
+    bus.set_button(Button::A, true);
+    bus.tick(4);
+    let requests: u8 = bus.read(IF);
+    assert_eq!(requests & 0x10, 0);
+
+    // Selecting the line while A is held pulls it low
+    bus.write(P1, 0x10u8);
+    bus.tick(4);
+    let requests: u8 = bus.read(IF);
+    assert_eq!(requests & 0x10, 0x10);
+
+    // Releasing is a rising edge
+    bus.write(IF, 0x00u8);
+    bus.set_button(Button::A, false);
+    bus.tick(4);
+    let requests: u8 = bus.read(IF);
+    assert_eq!(requests & 0x10, 0);
+}
+
+#[test]
+fn wakes_the_cpu() {
+    let mut bus = common::cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    cpu.run_frame();
+
+    cpu.set_button(Button::Start, true);
+    cpu.run_frame();
+    let value: u8 = cpu.bus().read(0xC000);
+    assert_eq!(value, 0);
+
+    cpu.set_button(Button::Up, true);
+    cpu.run_frame();
+    let value: u8 = cpu.bus().read(0xC000);
+    assert_eq!(value, 1);
+
+    // Holding a button doesn't repeat the interrupt
+    cpu.run_frame();
+    cpu.set_button(Button::Up, false);
+    cpu.run_frame();
+    let value: u8 = cpu.bus().read(0xC000);
+    assert_eq!(value, 1);
+
+    cpu.set_button(Button::Left, true);
+    cpu.run_frame();
+    let value: u8 = cpu.bus().read(0xC000);
+    assert_eq!(value, 2);
+}
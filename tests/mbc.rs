@@ -0,0 +1,199 @@
+use emulator::cartridge::header::NINTENDO_LOGO;
+use emulator::cartridge::mbc::mbc1::MBC1;
+use emulator::cartridge::mbc::mbc2::MBC2;
+use emulator::cartridge::mbc::mbc5::MBC5;
+use emulator::cartridge::mbc::{Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use emulator::utils::traits::Storage;
+
+// Every bank starts with its own number, low byte first, so reads tell which one is mapped
+fn rom(banks: usize) -> Vec<u8> {
+    let mut rom = vec![0; banks * ROM_BANK_SIZE];
+
+    for bank in 0..banks {
+        let [low, high] = (bank as u16).to_le_bytes();
+        rom[bank * ROM_BANK_SIZE] = low;
+        rom[bank * ROM_BANK_SIZE + 1] = high;
+    }
+
+    rom
+}
+
+fn bank(mapper: &mut impl Mapper, addr: usize) -> u16 {
+    u16::from_le_bytes([mapper.read(addr), mapper.read(addr + 1)])
+}
+
+#[test]
+fn mbc1_maps_bank_1_instead_of_0() {
+    let mut mbc1 = MBC1::new(rom(128), 0);
+    assert_eq!(bank(&mut mbc1, 0x4000), 1);
+
+    mbc1.write(0x2000, 0x00);
+    assert_eq!(bank(&mut mbc1, 0x4000), 1);
+
+    mbc1.write(0x2000, 0x05);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x05);
+
+    // Only the 5 bits of the register are checked, so 0x20 gets remapped to 0x21
+    mbc1.write(0x4000, 0x01);
+    mbc1.write(0x2000, 0x00);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x21);
+    assert_eq!(mbc1.rom_bank(0x4000), 0x21);
+
+    // Larger values wrap around the ROM size
+    mbc1.write(0x2000, 0xE3);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x23);
+}
+
+#[test]
+fn mbc1_applies_the_upper_bits_everywhere_in_mode_1() {
+    let mut mbc1 = MBC1::new(rom(128), 4 * RAM_BANK_SIZE);
+    mbc1.write(0x0000, 0x0A);
+    mbc1.write(0x4000, 0x02);
+
+    for ram_bank in 0..4 {
+        mbc1.write(0x6000, 0x01);
+        mbc1.write(0x4000, ram_bank);
+        mbc1.write(0xA000, 0x10 + ram_bank);
+    }
+    mbc1.write(0x4000, 0x02);
+
+    // Mode 0 always maps bank 0 at 0x0000 and the first RAM bank
+    mbc1.write(0x6000, 0x00);
+    assert_eq!(bank(&mut mbc1, 0x0000), 0x00);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x41);
+    assert_eq!(mbc1.read(0xA000), 0x10);
+
+    mbc1.write(0x6000, 0x01);
+    assert_eq!(bank(&mut mbc1, 0x0000), 0x40);
+    assert_eq!(mbc1.rom_bank(0x0000), 0x40);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x41);
+    assert_eq!(mbc1.read(0xA000), 0x12);
+}
+
+#[test]
+fn mbc1_multicart_uses_four_bits_of_bank1() {
+    // Each game has its own header, the second one starting at bank 0x10
+    let mut rom = rom(64);
+    rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+    let offset = 0x10 * ROM_BANK_SIZE + 0x104;
+    rom[offset..offset + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+
+    let mut mbc1 = MBC1::new(rom, 0);
+    mbc1.write(0x4000, 0x01);
+    mbc1.write(0x2000, 0x12);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x12);
+
+    // Bit 4 isn't wired, so this is bank 0 of the second game
+    mbc1.write(0x2000, 0x10);
+    assert_eq!(bank(&mut mbc1, 0x4000), 0x10);
+
+    mbc1.write(0x6000, 0x01);
+    mbc1.write(0x4000, 0x03);
+    assert_eq!(bank(&mut mbc1, 0x0000), 0x30);
+}
+
+#[test]
+fn mbc2_has_4_bit_ram() {
+    let mut mbc2 = MBC2::new(rom(16));
+    mbc2.write(0x0000, 0x0A);
+
+    mbc2.write(0xA000, 0xAB);
+    assert_eq!(mbc2.read(0xA000), 0xFB);
+    assert_eq!(mbc2.ram()[0], 0x0B);
+
+    // The 512 half-bytes repeat all the way through the RAM area
+    assert_eq!(mbc2.read(0xA200), 0xFB);
+    assert_eq!(mbc2.read(0xBE00), 0xFB);
+}
+
+#[test]
+fn mbc2_selects_registers_with_a8() {
+    let mut mbc2 = MBC2::new(rom(16));
+
+    // With A8 set the write goes to the ROM bank, even if it looks like enabling RAM
+    mbc2.write(0x0100, 0x0A);
+    assert_eq!(bank(&mut mbc2, 0x4000), 0x0A);
+    mbc2.write(0xA000, 0x05);
+    assert_eq!(mbc2.read(0xA000), 0xFF);
+
+    // With A8 clear it goes to the RAM enable, anywhere up to 0x4000
+    mbc2.write(0x3E00, 0x0A);
+    assert_eq!(bank(&mut mbc2, 0x4000), 0x0A);
+    mbc2.write(0xA000, 0x05);
+    assert_eq!(mbc2.read(0xA000), 0xF5);
+
+    mbc2.write(0x2100, 0x00);
+    assert_eq!(bank(&mut mbc2, 0x4000), 0x01);
+    mbc2.write(0x3FFF, 0x03);
+    assert_eq!(bank(&mut mbc2, 0x4000), 0x03);
+}
+
+#[test]
+fn mbc5_has_a_9_bit_rom_bank() {
+    let mut mbc5 = MBC5::new(rom(512), 0, false);
+    assert_eq!(bank(&mut mbc5, 0x4000), 1);
+
+    mbc5.write(0x2000, 0x23);
+    mbc5.write(0x3000, 0x01);
+    assert_eq!(bank(&mut mbc5, 0x4000), 0x123);
+    assert_eq!(mbc5.rom_bank(0x4000), 0x123);
+
+    // Each half can be written on its own
+    mbc5.write(0x2000, 0xFF);
+    assert_eq!(bank(&mut mbc5, 0x4000), 0x1FF);
+    mbc5.write(0x3000, 0x00);
+    assert_eq!(bank(&mut mbc5, 0x4000), 0xFF);
+
+    // Bank 0 can be mapped twice
+    mbc5.write(0x2000, 0x00);
+    assert_eq!(bank(&mut mbc5, 0x4000), 0x00);
+}
+
+#[test]
+fn mbc5_drives_the_rumble_motor_with_bit_3() {
+    let mut mbc5 = MBC5::new(rom(4), 4 * RAM_BANK_SIZE, true);
+    mbc5.write(0x0000, 0x0A);
+    mbc5.write(0x4000, 0x03);
+    mbc5.write(0xA000, 0x33);
+    assert!(!mbc5.rumble());
+
+    // The motor bit doesn't change the RAM bank
+    mbc5.write(0x4000, 0x0B);
+    assert!(mbc5.rumble());
+    assert_eq!(mbc5.read(0xA000), 0x33);
+
+    mbc5.write(0x4000, 0x03);
+    assert!(!mbc5.rumble());
+
+    // Without a motor, bit 3 is part of the RAM bank
+    let mut mbc5 = MBC5::new(rom(4), 16 * RAM_BANK_SIZE, false);
+    mbc5.write(0x0000, 0x0A);
+    mbc5.write(0x4000, 0x0B);
+    mbc5.write(0xA000, 0x0B);
+    assert!(!mbc5.rumble());
+    assert_eq!(mbc5.ram()[0x0B * RAM_BANK_SIZE], 0x0B);
+}
+
+#[test]
+fn ignores_ram_while_disabled() {
+    let mappers: [Box<dyn Mapper>; 2] = [
+        Box::new(MBC1::new(rom(4), RAM_BANK_SIZE)),
+        Box::new(MBC5::new(rom(4), RAM_BANK_SIZE, false)),
+    ];
+
+    for mut mapper in mappers {
+        mapper.write(0xA000, 0x12);
+        assert_eq!(mapper.read(0xA000), 0xFF);
+        assert_eq!(mapper.ram()[0], 0x00);
+
+        mapper.write(0x0000, 0x0A);
+        mapper.write(0xA000, 0x12);
+        assert_eq!(mapper.read(0xA000), 0x12);
+
+        // Anything else disables it again, the data stays put
+        mapper.write(0x1FFF, 0x00);
+        assert_eq!(mapper.read(0xA000), 0xFF);
+        mapper.write(0xA000, 0x34);
+        assert_eq!(mapper.ram()[0], 0x12);
+    }
+}
@@ -0,0 +1,146 @@
+// Runs mooneye-gb style test ROMs, see https://github.com/Gekkio/mooneye-test-suite. The ROMs
+// aren't bundled: drop them in `roms/mooneye` (or point `MOONEYE_ROMS` at another directory)
+// and run `cargo test --test mooneye -- --ignored`, every `.gb` file found there gets run.
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use common::{Outcome, SECONDS};
+use emulator::cpu::registers::{Reg8, Registers};
+use emulator::cpu::CPU;
+use emulator::memory::bus::MemoryBus;
+use emulator::utils::traits::Storage;
+
+const ROMS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/roms/mooneye");
+
+// Emulated time each ROM gets before it's considered stuck
+const BUDGET: u64 = 60 * SECONDS;
+
+// A passing test loads the Fibonacci numbers into B/C/D/E/H/L before executing LD B,B, while a
+// failing one fills them with 0x42
+const REGISTERS: [Reg8; 6] = [Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L];
+const PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const FAIL: [u8; 6] = [0x42; 6];
+
+/// Tells apart a pass from a fail based on the registers at the time of the breakpoint.
+fn classify(registers: &mut Registers) -> Outcome {
+    let values = REGISTERS.map(|register| registers.read(register));
+
+    match values {
+        PASS => Outcome::Passed,
+        FAIL => Outcome::Failed("registers set to 0x42".to_string()),
+        _ => Outcome::Failed(format!("unexpected registers B/C/D/E/H/L {:02X?}", values)),
+    }
+}
+
+fn run(bus: &mut MemoryBus) -> Outcome {
+    let mut cpu = CPU::new(bus);
+
+    cpu.set_software_breakpoints(true);
+
+    while cpu.cycles() < BUDGET {
+        cpu.step();
+
+        if cpu.take_breakpoint() {
+            return classify(cpu.registers());
+        }
+    }
+
+    Outcome::Timeout
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs, see the top of tests/mooneye.rs"]
+fn test_mooneye() {
+    let dir = std::env::var_os("MOONEYE_ROMS").map_or_else(|| PathBuf::from(ROMS), PathBuf::from);
+
+    let mut roms = Vec::new();
+    find_roms(&dir, &mut roms);
+    roms.sort();
+
+    assert!(
+        !roms.is_empty(),
+        "no mooneye ROMs found in {}, set MOONEYE_ROMS to where they are",
+        dir.display()
+    );
+
+    let results: Vec<(String, Outcome)> = thread::scope(|scope| {
+        let handles: Vec<_> = roms
+            .iter()
+            .map(|path| (path, scope.spawn(move || run(&mut common::load_rom(path)))))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|(path, handle)| {
+                let name = path
+                    .strip_prefix(&dir)
+                    .unwrap_or(path)
+                    .display()
+                    .to_string();
+                let outcome = handle
+                    .join()
+                    .unwrap_or_else(|_| Outcome::Failed("the emulator panicked".to_string()));
+                (name, outcome)
+            })
+            .collect()
+    });
+
+    let failures = common::report(&results);
+    assert!(failures == 0, "{} mooneye ROM(s) failed", failures);
+}
+
+#[test]
+fn classifies_the_registers_at_ld_b_b() {
+    let mut bus = common::cartridge(
+        "    org $0100
+             ld b, 3
+             ld c, 5
+             ld d, 8
+             ld e, 13
+             ld h, 21
+             ld l, 34
+             ld b, b",
+    );
+    assert!(matches!(run(&mut bus), Outcome::Passed));
+
+    let mut bus = common::cartridge(
+        "    org $0100
+             ld a, $42
+             ld b, a
+             ld c, a
+             ld d, a
+             ld e, a
+             ld h, a
+             ld l, a
+             ld b, b",
+    );
+    assert!(matches!(run(&mut bus), Outcome::Failed(details) if details.contains("0x42")));
+
+    // Anything else is a failure too, rather than a pass that went unnoticed
+    let mut bus = common::cartridge(
+        "    org $0100
+             ld b, 3
+             ld c, 5
+             ld b, b",
+    );
+    assert!(matches!(run(&mut bus), Outcome::Failed(details) if details.contains("unexpected")));
+}
@@ -0,0 +1,212 @@
+mod common;
+
+use emulator::boot::{BootRom, Model};
+use emulator::cpu::CPU;
+use emulator::joypad::Button;
+use emulator::movie::Movie;
+use emulator::state::StateError;
+
+const CHECKSUM: [u8; 2] = [0x12, 0x34];
+
+// Counts joypad interrupts at 0xC000 and mixes the buttons read from P1 into cartridge RAM, so
+// the state depends on exactly when buttons were held
+const PROGRAM: &str = "
+        org $0060
+        push af
+        ld a, [$C000]
+        inc a
+        ld [$C000], a
+        pop af
+        reti
+
+        org $0100
+        ld a, $0A
+        ld [$0000], a       ; Enable RAM and the clock
+        ld a, $10
+        ld [$FFFF], a       ; Joypad interrupt only
+        ld a, $20
+        ld [$FF00], a       ; Select the d-pad
+        ei
+        ld hl, $A000
+
+    loop:
+        ld a, [$FF00]
+        add a, [hl]
+        ld [hl], a
+        inc l
+        jr loop
+";
+
+const MOVIE: &str = "[Header]
+Title TEST
+GlobalChecksum 1234
+Model dmg
+BootRom None
+StartsFromSavestate False
+[/Header]
+[Input]
+LogKey:#Up|Down|Left|Right|Start|Select|B|A|
+|........|
+|U..R...A|
+|...RSsB.|
+[/Input]
+";
+
+fn movie() -> Movie {
+    Movie {
+        title: "TEST".to_string(),
+        checksum: CHECKSUM,
+        model: Model::Dmg,
+        boot_rom: None,
+        state: None,
+        frames: Vec::new(),
+    }
+}
+
+// Holds a different set of buttons every few frames, recording them
+fn record(cpu: &mut CPU, movie: &mut Movie, frames: usize) {
+    for frame in 0..frames {
+        cpu.set_button(Button::Right, frame % 3 == 0);
+        cpu.set_button(Button::Up, frame % 5 == 0);
+        cpu.set_button(Button::Start, frame % 7 == 0);
+        movie.record(cpu);
+        cpu.run_frame();
+    }
+}
+
+#[test]
+fn reads_and_writes_text() {
+    let movie = Movie::parse(MOVIE).unwrap();
+    assert_eq!(movie.title, "TEST");
+    assert_eq!(movie.checksum, CHECKSUM);
+    assert_eq!(movie.state, None);
+    assert_eq!(movie.frames, vec![0x00, 0x15, 0xE1]);
+    assert_eq!(movie.to_string(), MOVIE);
+
+    // Any letter marks a button as held, and unknown header keys are skipped
+    let edited = MOVIE
+        .replace("Title TEST", "Title TEST\nAuthor someone")
+        .replace("|........|", "|xx......|");
+    let edited = Movie::parse(&edited).unwrap();
+    assert_eq!(edited.frames[0], 0x0C);
+
+    let mut with_state = movie.clone();
+    with_state.state = Some((0..=255).collect());
+    assert_eq!(Movie::parse(&with_state.to_string()).unwrap(), with_state);
+
+    let mut on_cgb = movie.clone();
+    on_cgb.model = Model::Cgb;
+    on_cgb.boot_rom = Some(0x0123ABCD);
+    let text = on_cgb.to_string();
+    assert!(text.contains("Model cgb\nBootRom 0123ABCD\n"));
+    assert_eq!(Movie::parse(&text).unwrap(), on_cgb);
+
+    // Movies from before the model and boot ROM were recorded
+    let older = MOVIE.replace("Model dmg\nBootRom None\n", "");
+    assert_eq!(Movie::parse(&older).unwrap(), movie);
+}
+
+#[test]
+fn reports_invalid_lines() {
+    let invalid = [
+        (MOVIE.replace("|U..R...A|", "|U..R..A|"), 11),
+        (
+            MOVIE.replace("GlobalChecksum 1234", "GlobalChecksum XYZ"),
+            3,
+        ),
+        (MOVIE.replace("Model dmg", "Model gba"), 4),
+        (MOVIE.replace("BootRom None", "BootRom XYZ"), 5),
+        (MOVIE.replace("Up|Down", "Down|Up"), 9),
+        (MOVIE.replace("[/Input]\n", ""), 12),
+        (
+            MOVIE.replace("StartsFromSavestate False", "StartsFromSavestate True"),
+            13,
+        ),
+    ];
+
+    for (text, line) in invalid {
+        let error = Movie::parse(&text).unwrap_err();
+        assert_eq!(error.line, line, "{}", error);
+    }
+}
+
+#[test]
+fn plays_back_deterministically() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut recording = movie();
+    record(&mut cpu, &mut recording, 60);
+    let expected = cpu.save_state(CHECKSUM);
+
+    // Played back through its text form on a freshly powered on machine
+    let movie = Movie::parse(&recording.to_string()).unwrap();
+    assert_eq!(movie.frames.len(), 60);
+
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    movie.start(&mut cpu, CHECKSUM).unwrap();
+    for frame in 0..movie.frames.len() {
+        movie.play_frame(&mut cpu, frame);
+    }
+
+    assert!(cpu.save_state(CHECKSUM) == expected);
+}
+
+#[test]
+fn starts_from_a_save_state() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut before = movie();
+    record(&mut cpu, &mut before, 20);
+
+    let mut recording = movie();
+    recording.state = Some(cpu.save_state(CHECKSUM));
+    record(&mut cpu, &mut recording, 20);
+    let expected = cpu.save_state(CHECKSUM);
+
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    recording.start(&mut cpu, CHECKSUM).unwrap();
+    for frame in 0..recording.frames.len() {
+        recording.play_frame(&mut cpu, frame);
+    }
+
+    assert!(cpu.save_state(CHECKSUM) == expected);
+}
+
+#[test]
+fn rejects_other_roms() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+
+    let result = movie().start(&mut cpu, [0x56, 0x78]);
+    assert!(matches!(result, Err(StateError::WrongRom { .. })));
+}
+
+#[test]
+fn rejects_other_boot_roms() {
+    let boot_rom = || BootRom::new(vec![0; 0x100]).unwrap();
+
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    bus.set_boot_rom(boot_rom());
+    let mut cpu = CPU::new(&mut bus);
+
+    let result = movie().start(&mut cpu, CHECKSUM);
+    assert!(matches!(
+        result,
+        Err(StateError::WrongBootRom { found: None, .. })
+    ));
+
+    let mut recording = movie();
+    recording.boot_rom = Some(boot_rom().checksum());
+    recording.start(&mut cpu, CHECKSUM).unwrap();
+
+    // Nor does one recorded with a boot ROM play back without it
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let result = recording.start(&mut cpu, CHECKSUM);
+    assert!(matches!(
+        result,
+        Err(StateError::WrongBootRom { expected: None, .. })
+    ));
+}
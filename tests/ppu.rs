@@ -0,0 +1,99 @@
+use emulator::cpu::interrupts::Interrupt;
+use emulator::ppu::{Mode, Ppu};
+use emulator::utils::traits::Storage;
+
+const LCDC: usize = 0xFF40;
+const STAT: usize = 0xFF41;
+const SCX: usize = 0xFF43;
+const LY: usize = 0xFF44;
+const LYC: usize = 0xFF45;
+
+const DOTS_PER_LINE: u32 = 456;
+
+fn lcd_on() -> Ppu {
+    let mut ppu = Ppu::new();
+    ppu.write(LCDC, 0x91);
+    ppu
+}
+
+#[test]
+fn goes_through_the_modes_of_a_line() {
+    let mut ppu = lcd_on();
+    assert_eq!(ppu.mode(), Mode::OamScan);
+    assert_eq!(ppu.read(STAT) & 0x03, 2);
+
+    ppu.tick(76);
+    assert_eq!(ppu.mode(), Mode::OamScan);
+    ppu.tick(4);
+    assert_eq!(ppu.mode(), Mode::Drawing);
+    assert_eq!(ppu.read(STAT) & 0x03, 3);
+
+    // Drawing takes 172 dots without scrolling or sprites
+    ppu.tick(168);
+    assert_eq!(ppu.mode(), Mode::Drawing);
+    ppu.tick(4);
+    assert_eq!(ppu.mode(), Mode::HBlank);
+    assert_eq!(ppu.read(STAT) & 0x03, 0);
+
+    ppu.tick(DOTS_PER_LINE - 256);
+    assert_eq!(ppu.mode(), Mode::HBlank);
+    ppu.tick(4);
+    assert_eq!(ppu.mode(), Mode::OamScan);
+    assert_eq!(ppu.read(LY), 1);
+}
+
+#[test]
+fn stretches_drawing_with_fine_scrolling() {
+    let mut ppu = lcd_on();
+    ppu.write(SCX, 0x03);
+
+    ppu.tick(80 + 172);
+    assert_eq!(ppu.mode(), Mode::Drawing);
+    ppu.tick(3);
+    assert_eq!(ppu.mode(), Mode::HBlank);
+}
+
+#[test]
+fn enters_vblank_after_144_lines() {
+    let mut ppu = lcd_on();
+
+    let mut requests = 0;
+    for _ in 0..144 {
+        requests |= ppu.tick(DOTS_PER_LINE);
+    }
+
+    assert_eq!(ppu.read(LY), 144);
+    assert_eq!(ppu.mode(), Mode::VBlank);
+    assert_eq!(requests, Interrupt::VBlank.mask());
+    assert_eq!(ppu.frames(), 1);
+
+    // Ten lines later the next frame starts over at line 0
+    for _ in 0..10 {
+        ppu.tick(DOTS_PER_LINE);
+    }
+    assert_eq!(ppu.read(LY), 0);
+    assert_eq!(ppu.mode(), Mode::OamScan);
+}
+
+#[test]
+fn interrupts_when_ly_matches_lyc() {
+    let mut ppu = lcd_on();
+    ppu.write(LYC, 2);
+    ppu.write(STAT, 0x40);
+
+    assert_eq!(ppu.tick(DOTS_PER_LINE), 0);
+    assert_eq!(ppu.read(STAT) & 0x04, 0);
+
+    assert_eq!(ppu.tick(DOTS_PER_LINE), Interrupt::Stat.mask());
+    assert_eq!(ppu.read(LY), 2);
+    assert_eq!(ppu.read(STAT) & 0x04, 0x04);
+
+    // The line stays high for the whole of LY 2, so HBlank doesn't interrupt again
+    ppu.write(STAT, 0x48);
+    assert_eq!(ppu.tick(DOTS_PER_LINE - 4), 0);
+
+    // Once LY moves on the HBlank interrupt is a new rising edge
+    ppu.tick(4);
+    assert_eq!(ppu.read(STAT) & 0x04, 0);
+    assert_eq!(ppu.tick(DOTS_PER_LINE - 4), Interrupt::Stat.mask());
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use emulator::cpu::CPU;
+use emulator::joypad::Button;
+use emulator::rewind::Rewind;
+
+// Keeps the timer, the PPU and the mapper busy, counting loops in cartridge RAM and d-pad
+// presses at 0xC000
+const PROGRAM: &str = "
+        org $0060
+        push af
+        ld a, [$C000]
+        inc a
+        ld [$C000], a
+        pop af
+        reti
+
+        org $0100
+        ld a, $0A
+        ld [$0000], a       ; Enable RAM and the clock
+        ld a, $10
+        ld [$FFFF], a       ; Joypad interrupt only
+        ld a, $20
+        ld [$FF00], a       ; Select the d-pad
+        ei
+        ld a, $05
+        ld [$FF07], a       ; Timer at 262144 Hz
+        ld a, $91
+        ld [$FF40], a       ; LCD on
+        ld hl, $A000
+
+    loop:
+        inc [hl]
+        ld a, [$FF05]
+        ld [$9800], a
+        jr nz, loop
+        inc l
+        jr loop
+";
+
+fn state(cpu: &CPU) -> Vec<u8> {
+    cpu.save_state([0, 0])
+}
+
+#[test]
+fn steps_back_frame_by_frame() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut rewind = Rewind::new(4, usize::MAX);
+
+    // The state at the start of every frame
+    let mut states = vec![state(&cpu)];
+    for _ in 0..10 {
+        rewind.run_frame(&mut cpu);
+        states.push(state(&cpu));
+    }
+
+    for frame in (0..10).rev() {
+        assert!(rewind.step_back(&mut cpu).unwrap());
+        assert_eq!(rewind.frame(), frame);
+        assert!(state(&cpu) == states[frame as usize], "frame {}", frame);
+    }
+
+    assert!(!rewind.step_back(&mut cpu).unwrap());
+    assert!(state(&cpu) == states[0]);
+}
+
+#[test]
+fn resumes_deterministically() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut rewind = Rewind::new(3, usize::MAX);
+
+    for _ in 0..20 {
+        rewind.run_frame(&mut cpu);
+    }
+    let expected = state(&cpu);
+
+    for _ in 0..7 {
+        assert!(rewind.step_back(&mut cpu).unwrap());
+    }
+    assert_eq!(rewind.frame(), 13);
+
+    for _ in 0..7 {
+        rewind.run_frame(&mut cpu);
+    }
+    assert!(state(&cpu) == expected);
+
+    // The snapshots taken on the way back forward can be rewound as well
+    for _ in 0..20 {
+        assert!(rewind.step_back(&mut cpu).unwrap());
+    }
+    assert_eq!(rewind.frame(), 0);
+}
+
+#[test]
+fn replays_inputs() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut rewind = Rewind::new(8, usize::MAX);
+
+    // The state at the start of every frame, with its buttons held
+    let mut states = Vec::new();
+    for frame in 0..16 {
+        cpu.set_button(Button::Right, frame % 3 == 0);
+        cpu.set_button(Button::Up, frame % 5 == 0);
+        states.push(state(&cpu));
+        rewind.run_frame(&mut cpu);
+    }
+
+    for frame in (0..16).rev() {
+        assert!(rewind.step_back(&mut cpu).unwrap());
+        assert!(state(&cpu) == states[frame], "frame {}", frame);
+    }
+}
+
+#[test]
+fn stays_within_budget() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut rewind = Rewind::new(1, 4 * 1024);
+
+    for _ in 0..300 {
+        rewind.run_frame(&mut cpu);
+        assert!(rewind.size() <= 4 * 1024);
+    }
+
+    // Not even one full state fits in the budget, but dozens of deltas do
+    let oldest = rewind.oldest_frame().unwrap();
+    assert!(state(&cpu).len() > 4 * 1024);
+    assert!(oldest > 0 && 300 - oldest >= 30);
+
+    while rewind.step_back(&mut cpu).unwrap() {}
+    assert_eq!(rewind.frame(), oldest);
+}
@@ -0,0 +1,115 @@
+// Runs the test ROMs in `roms/` headlessly and checks their results, see
+// https://github.com/retrio/gb-test-roms and https://github.com/mattcurrie/dmg-acid2
+mod common;
+
+use std::path::Path;
+use std::thread;
+
+use common::{Outcome, SECONDS};
+use emulator::cpu::{CPU, CYCLES_PER_FRAME};
+use emulator::utils::traits::Storage;
+
+const ROMS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/roms");
+
+// Emulated time each ROM gets before it's considered stuck
+const BLARGG_BUDGET: u64 = 30 * SECONDS;
+const CPU_INSTRS_BUDGET: u64 = 90 * SECONDS;
+const ACID2_FRAMES: u64 = 60;
+
+// FNV-1a hash of the frame buffer rendered by dmg-acid2, checked against the reference image
+const ACID2_HASH: u64 = 0xF272_A8FF_E3DB_4C16;
+
+// Newer blargg ROMs also report through cartridge RAM: 0xA000 holds the status while
+// 0xA001..0xA004 hold a signature, see the readme of the test ROMs
+const STATUS: usize = 0xA000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const RUNNING: u8 = 0x80;
+
+/// Runs a blargg ROM until it reports a result over the serial port or in cartridge RAM, or
+/// until `budget` T-cycles have elapsed.
+fn run_blargg(name: &str, budget: u64) -> Outcome {
+    let mut bus = common::load_rom(&Path::new(ROMS).join(name));
+    let mut cpu = CPU::new(&mut bus);
+
+    while cpu.cycles() < budget {
+        cpu.run_frame();
+
+        let output = String::from_utf8_lossy(cpu.bus().serial().output()).into_owned();
+        if output.contains("Passed") {
+            return Outcome::Passed;
+        }
+        if output.contains("Failed") {
+            return Outcome::Failed(output.trim().to_string());
+        }
+
+        let bus = cpu.bus();
+        let signature: Vec<u8> = (1..4).map(|i| bus.read(STATUS + i)).collect();
+        let status: u8 = bus.read(STATUS);
+
+        if signature == SIGNATURE && status != RUNNING {
+            return match status {
+                0 => Outcome::Passed,
+                code => Outcome::Failed(format!("{}\nresult code {}", output.trim(), code)),
+            };
+        }
+    }
+
+    Outcome::Timeout
+}
+
+fn run_acid2() -> Outcome {
+    let mut bus = common::load_rom(&Path::new(ROMS).join("dmg-acid2.gb"));
+    let mut cpu = CPU::new(&mut bus);
+
+    cpu.run_until(ACID2_FRAMES * CYCLES_PER_FRAME);
+
+    match fnv1a(cpu.bus().ppu().frame_buffer()) {
+        ACID2_HASH => Outcome::Passed,
+        hash => Outcome::Failed(format!("frame buffer hash {:016X}", hash)),
+    }
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[test]
+fn test_roms() {
+    let blargg = [
+        ("01-special.gb", BLARGG_BUDGET),
+        ("02-interrupts.gb", BLARGG_BUDGET),
+        ("03-op sp,hl.gb", BLARGG_BUDGET),
+        ("04-op r,imm.gb", BLARGG_BUDGET),
+        ("05-op rp.gb", BLARGG_BUDGET),
+        ("06-ld r,r.gb", BLARGG_BUDGET),
+        ("07-jr,jp,call,ret,rst.gb", BLARGG_BUDGET),
+        ("08-misc instrs.gb", BLARGG_BUDGET),
+        ("09-op r,r.gb", BLARGG_BUDGET),
+        ("10-bit ops.gb", BLARGG_BUDGET),
+        ("11-op a,(hl).gb", BLARGG_BUDGET),
+        ("cpu_instrs.gb", CPU_INSTRS_BUDGET),
+        ("mem_timing.gb", BLARGG_BUDGET),
+    ];
+
+    // The ROMs are independent of each other, so they all run at once
+    let results: Vec<(String, Outcome)> = thread::scope(|scope| {
+        let mut handles: Vec<_> = blargg
+            .iter()
+            .map(|(name, budget)| (*name, scope.spawn(move || run_blargg(name, *budget))))
+            .collect();
+        handles.push(("dmg-acid2.gb", scope.spawn(run_acid2)));
+
+        handles
+            .into_iter()
+            .map(|(name, handle)| {
+                let outcome = handle.join().expect("the emulator panicked");
+                (name.to_string(), outcome)
+            })
+            .collect()
+    });
+
+    let failures = common::report(&results);
+    assert!(failures == 0, "{} test ROM(s) failed", failures);
+}
@@ -0,0 +1,218 @@
+use std::fs;
+
+use emulator::cartridge::mbc::mbc3::MBC3;
+use emulator::cartridge::mbc::rtc::{Rtc, FOOTER_SIZE};
+use emulator::cartridge::mbc::{Mapper, ROM_BANK_SIZE};
+use emulator::cartridge::save::SaveFile;
+use emulator::utils::traits::Storage;
+
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+const SECONDS: u8 = 0x08;
+const MINUTES: u8 = 0x09;
+const HOURS: u8 = 0x0A;
+const DAYS_LOW: u8 = 0x0B;
+const DAYS_HIGH: u8 = 0x0C;
+
+fn cartridge() -> MBC3 {
+    let mut mbc3 = MBC3::new(vec![0; 2 * ROM_BANK_SIZE], 0x2000, true);
+    mbc3.write(0x0000, 0x0A);
+    mbc3
+}
+
+fn set(mbc3: &mut MBC3, register: u8, value: u8) {
+    mbc3.write(0x4000, register);
+    mbc3.write(0xA000, value);
+}
+
+fn get(mbc3: &mut MBC3, register: u8) -> u8 {
+    mbc3.write(0x4000, register);
+    mbc3.read(0xA000)
+}
+
+fn latch(mbc3: &mut MBC3) {
+    mbc3.write(0x6000, 0x00);
+    mbc3.write(0x6000, 0x01);
+}
+
+#[test]
+fn latches_on_0_then_1() {
+    let mut mbc3 = cartridge();
+    set(&mut mbc3, SECONDS, 5);
+    mbc3.tick(2 * CYCLES_PER_SECOND);
+
+    // The registers read back what was latched until the next latch
+    assert_eq!(get(&mut mbc3, SECONDS), 5);
+
+    mbc3.write(0x6000, 0x01);
+    assert_eq!(get(&mut mbc3, SECONDS), 5);
+
+    mbc3.write(0x7FFF, 0x00);
+    mbc3.write(0x7FFF, 0x01);
+    assert_eq!(get(&mut mbc3, SECONDS), 7);
+
+    // Anything in between disarms it
+    mbc3.tick(CYCLES_PER_SECOND);
+    mbc3.write(0x6000, 0x00);
+    mbc3.write(0x6000, 0x02);
+    mbc3.write(0x6000, 0x01);
+    assert_eq!(get(&mut mbc3, SECONDS), 7);
+}
+
+#[test]
+fn carries_into_the_day_counter() {
+    let mut mbc3 = cartridge();
+    set(&mut mbc3, SECONDS, 59);
+    set(&mut mbc3, MINUTES, 59);
+    set(&mut mbc3, HOURS, 23);
+    set(&mut mbc3, DAYS_LOW, 0xFF);
+    set(&mut mbc3, DAYS_HIGH, 0x00);
+
+    mbc3.tick(CYCLES_PER_SECOND);
+    latch(&mut mbc3);
+    assert_eq!(get(&mut mbc3, SECONDS), 0);
+    assert_eq!(get(&mut mbc3, MINUTES), 0);
+    assert_eq!(get(&mut mbc3, HOURS), 0);
+    assert_eq!(get(&mut mbc3, DAYS_LOW), 0x00);
+    assert_eq!(get(&mut mbc3, DAYS_HIGH), 0x01);
+
+    // Past day 511 the counter wraps and sets the carry, which stays until it's cleared
+    set(&mut mbc3, SECONDS, 59);
+    set(&mut mbc3, MINUTES, 59);
+    set(&mut mbc3, HOURS, 23);
+    set(&mut mbc3, DAYS_LOW, 0xFF);
+    set(&mut mbc3, DAYS_HIGH, 0x01);
+
+    mbc3.tick(CYCLES_PER_SECOND);
+    latch(&mut mbc3);
+    assert_eq!(get(&mut mbc3, DAYS_LOW), 0x00);
+    assert_eq!(get(&mut mbc3, DAYS_HIGH), 0x80);
+
+    mbc3.tick(60 * CYCLES_PER_SECOND);
+    latch(&mut mbc3);
+    assert_eq!(get(&mut mbc3, MINUTES), 1);
+    assert_eq!(get(&mut mbc3, DAYS_HIGH), 0x80);
+
+    set(&mut mbc3, DAYS_HIGH, 0x00);
+    assert_eq!(get(&mut mbc3, DAYS_HIGH), 0x00);
+}
+
+#[test]
+fn stops_while_halted() {
+    let mut mbc3 = cartridge();
+    set(&mut mbc3, SECONDS, 10);
+    set(&mut mbc3, DAYS_HIGH, 0x40);
+
+    mbc3.tick(3 * CYCLES_PER_SECOND);
+    latch(&mut mbc3);
+    assert_eq!(get(&mut mbc3, SECONDS), 10);
+    assert_eq!(get(&mut mbc3, DAYS_HIGH), 0x40);
+
+    // Neither does real time catch up
+    let rtc = mbc3.rtc_mut().unwrap();
+    rtc.advance(3600);
+    assert_eq!(rtc.registers(), [10, 0, 0, 0, 0x40]);
+
+    set(&mut mbc3, DAYS_HIGH, 0x00);
+    mbc3.tick(3 * CYCLES_PER_SECOND);
+    latch(&mut mbc3);
+    assert_eq!(get(&mut mbc3, SECONDS), 13);
+}
+
+#[test]
+fn ticks_with_emulated_time() {
+    let mut mbc3 = cartridge();
+
+    // The T-cycles add up across calls, however they're split
+    for _ in 0..CYCLES_PER_SECOND / 0x10000 - 1 {
+        mbc3.tick(0x10000);
+    }
+    mbc3.tick(0xFFFC);
+    assert_eq!(mbc3.rtc().unwrap().registers()[0], 0);
+
+    mbc3.tick(4);
+    assert_eq!(mbc3.rtc().unwrap().registers()[0], 1);
+
+    // Writing the seconds restarts the second in progress
+    mbc3.tick(CYCLES_PER_SECOND - 4);
+    set(&mut mbc3, SECONDS, 30);
+    mbc3.tick(4);
+    assert_eq!(mbc3.rtc().unwrap().registers()[0], 30);
+    mbc3.tick(CYCLES_PER_SECOND - 4);
+    assert_eq!(mbc3.rtc().unwrap().registers()[0], 31);
+}
+
+#[test]
+fn round_trips_the_footer() {
+    let mut rtc = Rtc::new();
+    rtc.write(SECONDS, 1);
+    rtc.write(MINUTES, 2);
+    rtc.write(HOURS, 3);
+    rtc.write(DAYS_LOW, 4);
+    rtc.write(DAYS_HIGH, 0x81);
+    rtc.write(SECONDS, 5);
+
+    let footer = rtc.to_footer(1_000_000);
+    assert_eq!(footer.len(), FOOTER_SIZE);
+    assert_eq!(footer[..4], [5, 0, 0, 0]);
+    assert_eq!(footer[40..], 1_000_000u64.to_le_bytes());
+
+    let mut loaded = Rtc::new();
+    loaded.load_footer(&footer, 1_000_000);
+    assert_eq!(loaded.registers(), [5, 2, 3, 4, 0x81]);
+    assert_eq!(loaded.to_footer(1_000_000), footer);
+
+    // The time spent away gets added on, without touching the latched registers
+    let mut loaded = Rtc::new();
+    loaded.load_footer(&footer, 1_000_000 + 3661);
+    assert_eq!(loaded.registers(), [6, 3, 4, 4, 0x81]);
+    assert_eq!(loaded.read(SECONDS), 5);
+
+    // Older footers only have 32 bits of timestamp
+    let mut short = footer[..44].to_vec();
+    short[40..44].copy_from_slice(&(1_000_000u32 - 60).to_le_bytes());
+    let mut loaded = Rtc::new();
+    loaded.load_footer(&short, 1_000_000);
+    assert_eq!(loaded.registers(), [5, 3, 3, 4, 0x81]);
+}
+
+#[test]
+fn only_saves_changes() {
+    let dir = std::env::temp_dir().join(format!("emulator-rtc-{}", std::process::id()));
+    let mut save_file = SaveFile::new(&dir.join("game.gb"), Some(&dir));
+    let path = save_file.path().to_path_buf();
+
+    let mut mbc3 = cartridge();
+    save_file.flush(&mbc3).unwrap();
+    assert_eq!(fs::read(&path).unwrap().len(), 0x2000 + FOOTER_SIZE);
+
+    // A running clock doesn't count as a change
+    fs::remove_file(&path).unwrap();
+    mbc3.tick(3 * CYCLES_PER_SECOND);
+    save_file.flush(&mbc3).unwrap();
+    assert!(!path.exists());
+
+    // Halting it does, and so does setting it while it's halted
+    set(&mut mbc3, DAYS_HIGH, 0x40);
+    save_file.flush(&mbc3).unwrap();
+    assert!(path.exists());
+
+    fs::remove_file(&path).unwrap();
+    set(&mut mbc3, SECONDS, 5);
+    save_file.flush(&mbc3).unwrap();
+    assert!(path.exists());
+
+    mbc3.write(0x4000, 0x00);
+    mbc3.write(0xA000, 0x12);
+    save_file.flush(&mbc3).unwrap();
+    assert_eq!(fs::read(&path).unwrap()[0], 0x12);
+
+    let mut loaded = cartridge();
+    SaveFile::new(&dir.join("game.gb"), Some(&dir))
+        .load(&mut loaded)
+        .unwrap();
+    assert_eq!(loaded.ram(), mbc3.ram());
+    assert_eq!(loaded.rtc().unwrap().registers(), [5, 0, 0, 0, 0x40]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,49 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use emulator::cpu::interrupts::Interrupt;
+use emulator::serial::Serial;
+use emulator::utils::traits::Storage;
+
+const SB: usize = 0xFF01;
+const SC: usize = 0xFF02;
+
+// Eight bits at 8192 Hz
+const TRANSFER_CYCLES: u32 = 8 * 512;
+
+#[test]
+fn completes_transfers_on_the_internal_clock() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&sent);
+
+    let mut serial = Serial::new();
+    serial.set_sink(move |byte| sink.borrow_mut().push(byte));
+    serial.write(SB, 0x41);
+    serial.write(SC, 0x81);
+
+    assert_eq!(serial.tick(TRANSFER_CYCLES - 4), 0);
+    assert_eq!(serial.read(SC), 0xFF);
+    assert!(serial.output().is_empty());
+
+    // Nothing is connected, so the byte coming back is all 1s
+    assert_eq!(serial.tick(4), Interrupt::Serial.mask());
+    assert_eq!(serial.read(SC), 0x7F);
+    assert_eq!(serial.read(SB), 0xFF);
+    assert_eq!(serial.output(), [0x41]);
+    assert_eq!(*sent.borrow(), [0x41]);
+
+    // Only once
+    assert_eq!(serial.tick(TRANSFER_CYCLES), 0);
+}
+
+#[test]
+fn waits_forever_on_the_external_clock() {
+    let mut serial = Serial::new();
+    serial.write(SB, 0x41);
+    serial.write(SC, 0x80);
+
+    assert_eq!(serial.tick(10 * TRANSFER_CYCLES), 0);
+    assert_eq!(serial.read(SC), 0xFE);
+    assert_eq!(serial.read(SB), 0x41);
+    assert!(serial.output().is_empty());
+}
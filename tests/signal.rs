@@ -0,0 +1,47 @@
+// Kept apart from tests/debugger.rs, since the Ctrl-C flag is shared by everything in a test
+// binary and would stop the other tests' `continue`s
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use emulator::cpu::CPU;
+use emulator::debugger::Debugger;
+use emulator::signal;
+
+#[test]
+fn interrupts_continue() {
+    let mut bus = common::load(
+        "
+        org $0100
+    spin:
+        jr spin
+    ",
+    );
+
+    let mut cpu = CPU::new(&mut bus);
+    let mut out = Vec::new();
+
+    let finished = AtomicBool::new(false);
+    thread::scope(|scope| {
+        // Keeps pressing Ctrl-C until the debugger gets back to the prompt
+        scope.spawn(|| {
+            while !finished.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(10));
+                signal::interrupt();
+            }
+        });
+
+        Debugger::new()
+            .run(&mut cpu, "continue".as_bytes(), &mut out)
+            .expect("failed to write the output");
+        finished.store(true, Ordering::SeqCst);
+    });
+
+    let output = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = output.split("> ").flat_map(str::lines).collect();
+
+    assert_eq!(lines[1], "Interrupted");
+    assert_eq!(lines[2], "0100  18 FE      JR $0100");
+}
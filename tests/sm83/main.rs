@@ -0,0 +1,326 @@
+// Checks every opcode against the SingleStepTests JSON vectors, see
+// https://github.com/SingleStepTests/sm83. The vectors aren't bundled: put the contents of its
+// `v1` directory in `roms/sm83` (or point `SM83_TESTS` at it) and run
+// `cargo test --test sm83 -- --ignored`.
+mod json;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use emulator::cpu::interrupts::Interrupt;
+use emulator::cpu::registers::{Reg16, Reg8};
+use emulator::cpu::CPU;
+use emulator::memory::bus::Bus;
+use emulator::utils::traits::Storage;
+use json::Value;
+
+const TESTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/roms/sm83");
+
+// Failing tests printed per file, the rest are only counted
+const MAX_REPORTED: usize = 5;
+
+const INTERRUPT_FLAG: usize = 0xFF0F;
+const INTERRUPT_ENABLE: usize = 0xFFFF;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+/// 64 KiB of RAM with nothing mapped, which records every access made during every M-cycle.
+#[derive(Debug)]
+struct TestBus {
+    memory: Vec<u8>,
+    cycles: Vec<Vec<Access>>,
+    // Accesses made before the first M-cycle started, which shouldn't happen
+    untimed: Vec<Access>,
+}
+
+impl TestBus {
+    fn new() -> Self {
+        Self {
+            memory: vec![0; 0x10000],
+            cycles: Vec::new(),
+            untimed: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, access: Access) {
+        match self.cycles.last_mut() {
+            Some(accesses) => accesses.push(access),
+            None => self.untimed.push(access),
+        }
+    }
+}
+
+impl Storage<usize, u8> for TestBus {
+    fn read(&mut self, src: usize) -> u8 {
+        let value = self.memory[src];
+        self.record(Access::Read(src as u16, value));
+
+        value
+    }
+
+    fn write(&mut self, dest: usize, value: u8) {
+        self.memory[dest] = value;
+        self.record(Access::Write(dest as u16, value));
+    }
+}
+
+impl Bus for TestBus {
+    fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            self.cycles.push(Vec::new());
+        }
+    }
+
+    // The CPU's interrupt lines aren't on the bus, so these aren't recorded
+    fn pending_interrupts(&mut self) -> u8 {
+        self.memory[INTERRUPT_FLAG] & self.memory[INTERRUPT_ENABLE] & 0x1F
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.memory[INTERRUPT_FLAG] &= !interrupt.mask();
+    }
+}
+
+const REGISTERS: [(&str, Reg8); 7] = [
+    ("a", Reg8::A),
+    ("b", Reg8::B),
+    ("c", Reg8::C),
+    ("d", Reg8::D),
+    ("e", Reg8::E),
+    ("h", Reg8::H),
+    ("l", Reg8::L),
+];
+
+fn field(state: &Value, key: &str) -> u64 {
+    state
+        .get(key)
+        .and_then(Value::as_u64)
+        .unwrap_or_else(|| panic!("missing field {}", key))
+}
+
+// The `ram` field holds a list of [address, value] pairs
+fn ram(state: &Value) -> Vec<(usize, u8)> {
+    state
+        .get("ram")
+        .and_then(Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|entry| {
+            let entry = entry.as_array()?;
+            let address = entry.first()?.as_u64()?;
+            let value = entry.get(1)?.as_u64()?;
+
+            Some((address as usize, value as u8))
+        })
+        .collect()
+}
+
+// Cycles are [address, value, activity] where activity reads like "r-m" or "-wm", anything
+// without a read or a write being an internal cycle
+fn expected_cycles(test: &Value) -> Vec<Vec<Access>> {
+    let cycles = test
+        .get("cycles")
+        .and_then(Value::as_array)
+        .unwrap_or_default();
+
+    cycles
+        .iter()
+        .map(|cycle| {
+            let cycle = cycle.as_array()?;
+            let address = cycle.first()?.as_u64()? as u16;
+            let value = cycle.get(1)?.as_u64()? as u8;
+            let activity = cycle.get(2)?.as_str()?.as_bytes();
+
+            match activity {
+                [b'r', ..] => Some(Access::Read(address, value)),
+                [_, b'w', ..] => Some(Access::Write(address, value)),
+                _ => None,
+            }
+        })
+        .map(|access| access.into_iter().collect())
+        .collect()
+}
+
+/// Runs a single test and returns a description of every field which doesn't match.
+fn run(test: &Value) -> Vec<String> {
+    let initial = test.get("initial").expect("missing initial state");
+    let expected = test.get("final").expect("missing final state");
+
+    let mut bus = TestBus::new();
+    bus.memory[INTERRUPT_ENABLE] = initial.get("ie").and_then(Value::as_u64).unwrap_or(0) as u8;
+    for (address, value) in ram(initial) {
+        bus.memory[address] = value;
+    }
+
+    let mut cpu = CPU::new(&mut bus);
+
+    let registers = cpu.registers();
+    for (name, register) in REGISTERS {
+        registers.write(register, field(initial, name) as u8);
+    }
+    let a = registers.read(Reg8::A);
+    registers.write(
+        Reg16::AF,
+        u16::from_be_bytes([a, field(initial, "f") as u8]),
+    );
+    registers.pc.pointer.0 = field(initial, "pc") as u16;
+    registers.sp.pointer.0 = field(initial, "sp") as u16;
+    cpu.set_ime(field(initial, "ime") != 0);
+
+    cpu.step();
+
+    let mut mismatches = Vec::new();
+    let mut compare = |name: &str, expected: u64, actual: u64| {
+        if expected != actual {
+            mismatches.push(format!(
+                "{}: expected {:#04X}, got {:#04X}",
+                name, expected, actual
+            ));
+        }
+    };
+
+    let registers = cpu.registers();
+    for (name, register) in REGISTERS {
+        compare(name, field(expected, name), registers.read(register) as u64);
+    }
+    let [_, f] = registers.read(Reg16::AF).to_be_bytes();
+    compare("f", field(expected, "f"), f as u64);
+    compare("pc", field(expected, "pc"), registers.pc.pointer.0 as u64);
+    compare("sp", field(expected, "sp"), registers.sp.pointer.0 as u64);
+    compare("ime", field(expected, "ime"), cpu.ime() as u64);
+    if let Some(ei) = expected.get("ei").and_then(Value::as_u64) {
+        compare("ei", ei, cpu.ime_pending() as u64);
+    }
+
+    let bus = cpu.bus();
+    if let Some(ie) = expected.get("ie").and_then(Value::as_u64) {
+        compare("ie", ie, bus.memory[INTERRUPT_ENABLE] as u64);
+    }
+    for (address, value) in ram(expected) {
+        compare(
+            &format!("ram[{:#06X}]", address),
+            value as u64,
+            bus.memory[address] as u64,
+        );
+    }
+
+    if !bus.untimed.is_empty() {
+        mismatches.push(format!(
+            "accesses outside of an M-cycle: {:X?}",
+            bus.untimed
+        ));
+    }
+
+    let cycles = expected_cycles(test);
+    if cycles.len() != bus.cycles.len() {
+        mismatches.push(format!(
+            "cycles: expected {} M-cycles, got {}",
+            cycles.len(),
+            bus.cycles.len()
+        ));
+    }
+    for (i, (expected, actual)) in cycles.iter().zip(&bus.cycles).enumerate() {
+        if expected != actual {
+            mismatches.push(format!(
+                "cycle {}: expected {:X?}, got {:X?}",
+                i, expected, actual
+            ));
+        }
+    }
+
+    mismatches
+}
+
+/// Runs every test in a file, returning the number of tests and a report of the failures.
+fn run_file(path: &Path) -> (usize, Vec<String>) {
+    let text = fs::read_to_string(path).expect("failed to read the test file");
+    let tests = json::parse(&text).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+    let tests = tests.as_array().expect("expected an array of tests");
+
+    let mut failures = Vec::new();
+
+    for test in tests {
+        let mismatches = run(test);
+        if mismatches.is_empty() {
+            continue;
+        }
+
+        let name = test.get("name").and_then(Value::as_str).unwrap_or("?");
+        failures.push(format!("  {}\n    {}", name, mismatches.join("\n    ")));
+    }
+
+    (tests.len(), failures)
+}
+
+#[test]
+#[ignore = "needs the SM83 test vectors, see the top of tests/sm83/main.rs"]
+fn test_sm83() {
+    let dir = std::env::var_os("SM83_TESTS").map_or_else(|| PathBuf::from(TESTS), PathBuf::from);
+
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == "json")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    assert!(
+        !files.is_empty(),
+        "no SM83 test vectors found in {}, set SM83_TESTS to where they are",
+        dir.display()
+    );
+
+    // Files are handed out to one worker per core
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+    let workers = thread::available_parallelism().map_or(1, |count| count.get());
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let (count, failures) = run_file(path);
+                    results.lock().unwrap().push((path, count, failures));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort();
+
+    let mut total = 0;
+    let mut failed = 0;
+
+    for (path, count, failures) in &results {
+        total += count;
+        failed += failures.len();
+
+        if failures.is_empty() {
+            continue;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        println!("{}: {} of {} tests failed", name, failures.len(), count);
+        for failure in failures.iter().take(MAX_REPORTED) {
+            println!("{}", failure);
+        }
+    }
+
+    println!("{} of {} tests passed", total - failed, total);
+    assert!(failed == 0, "{} SM83 tests failed", failed);
+}
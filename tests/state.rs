@@ -0,0 +1,159 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use emulator::cpu::CPU;
+use emulator::state::{self, StateError, VERSION};
+
+const CHECKSUM: [u8; 2] = [0x12, 0x34];
+const STATES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/states");
+
+// Keeps the timer, the PPU, the mapper and its clock busy, logging TIMA to cartridge RAM
+const PROGRAM: &str = "
+        org $0100
+        ld a, $0A
+        ld [$0000], a       ; Enable RAM and the clock
+        ld a, $02
+        ld [$2000], a       ; ROM bank 2
+        ld a, $05
+        ld [$FF07], a       ; Timer at 262144 Hz
+        ld a, $91
+        ld [$FF40], a       ; LCD on
+
+    restart:
+        ld hl, $A000
+    loop:
+        ld a, [$FF05]
+        ld [hl+], a
+        ld a, [$FF44]
+        ld [$C000], a
+        ld a, h
+        cp $C0
+        jr nz, loop
+        jr restart
+";
+
+fn run(cpu: &mut CPU, frames: u64) {
+    for _ in 0..frames {
+        cpu.run_frame();
+    }
+}
+
+#[test]
+fn resumes_where_it_left_off() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    run(&mut cpu, 3);
+
+    let saved = cpu.save_state(CHECKSUM);
+    run(&mut cpu, 2);
+    let expected = cpu.save_state(CHECKSUM);
+
+    let mut other_bus = common::mbc3_cartridge(PROGRAM);
+    let mut other = CPU::new(&mut other_bus);
+    other.load_state(&saved, CHECKSUM).unwrap();
+    assert_eq!(other.save_state(CHECKSUM), saved);
+
+    run(&mut other, 2);
+    assert_eq!(other.save_state(CHECKSUM), expected);
+    assert_eq!(other.cycles(), cpu.cycles());
+    assert_eq!(
+        other.bus().ppu().frame_buffer(),
+        cpu.bus().ppu().frame_buffer()
+    );
+}
+
+#[test]
+fn rejects_other_roms_and_versions() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let saved = cpu.save_state(CHECKSUM);
+
+    assert!(matches!(
+        cpu.load_state(&saved, [0xAB, 0xCD]),
+        Err(StateError::WrongRom {
+            expected: [0xAB, 0xCD],
+            found: CHECKSUM,
+        })
+    ));
+
+    let mut newer = saved.clone();
+    newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        cpu.load_state(&newer, CHECKSUM),
+        Err(StateError::UnsupportedVersion(version)) if version == VERSION + 1
+    ));
+
+    assert!(matches!(
+        cpu.load_state(b"not a state", CHECKSUM),
+        Err(StateError::InvalidFormat)
+    ));
+}
+
+#[test]
+fn loads_older_versions() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    run(&mut cpu, 1);
+    let saved = cpu.save_state(CHECKSUM);
+
+    // Version 1 ended before the joypad, version 2 before the boot ROM flag
+    for (version, missing) in [(1u16, 5), (2, 1)] {
+        let mut old = saved[..saved.len() - missing].to_vec();
+        old[4..6].copy_from_slice(&version.to_le_bytes());
+
+        let mut other_bus = common::mbc3_cartridge(PROGRAM);
+        let mut other = CPU::new(&mut other_bus);
+        other.load_state(&old, CHECKSUM).unwrap();
+        assert_eq!(other.save_state(CHECKSUM), saved, "version {}", version);
+    }
+}
+
+// Written by the builds which introduced versions 1 and 2, three frames into PROGRAM
+#[test]
+fn loads_states_from_older_builds() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    run(&mut cpu, 3);
+    let expected = cpu.save_state(CHECKSUM);
+
+    for version in [1, 2] {
+        let path = format!("{}/v{}.state", STATES, version);
+        let old = fs::read(&path).unwrap();
+
+        let mut other_bus = common::mbc3_cartridge(PROGRAM);
+        let mut other = CPU::new(&mut other_bus);
+        other.load_state(&old, CHECKSUM).unwrap();
+        assert_eq!(other.save_state(CHECKSUM), expected, "version {}", version);
+    }
+}
+
+#[test]
+fn leaves_the_machine_alone_on_errors() {
+    let mut bus = common::mbc3_cartridge(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    run(&mut cpu, 1);
+    let saved = cpu.save_state(CHECKSUM);
+
+    run(&mut cpu, 1);
+    let current = cpu.save_state(CHECKSUM);
+
+    assert!(matches!(
+        cpu.load_state(&saved[..saved.len() - 1], CHECKSUM),
+        Err(StateError::Truncated)
+    ));
+    assert_eq!(cpu.save_state(CHECKSUM), current);
+}
+
+#[test]
+fn names_slots_after_the_rom() {
+    assert_eq!(
+        state::slot_path(Path::new("roms/game.gb"), None, 3),
+        Path::new("roms/game.ss3")
+    );
+    assert_eq!(
+        state::slot_path(Path::new("roms/game.gb"), Some(Path::new("saves")), 0),
+        Path::new("saves/game.ss0")
+    );
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use emulator::cpu::disassembler::disassemble_with;
+use emulator::cpu::trace::Trace;
+use emulator::cpu::CPU;
+use emulator::debugger::Debugger;
+use emulator::symbols::Symbols;
+
+/// A writer the test keeps a handle on after giving it away.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+const PROGRAM: &str = "
+        org $0100
+        ld a, $10           ; 0100
+        call double         ; 0102
+        ld [$C000], a       ; 0105
+        db $DD              ; 0108
+
+        org $4000
+    double:
+        add a, a            ; 4000
+        ret                 ; 4001
+";
+
+const SYMBOLS: &str = "
+; File generated by rgblink
+00:0100 Start
+00:0105 Store
+01:4000 Double
+02:4000 Elsewhere
+00:C000 wResult
+garbage
+";
+
+#[test]
+fn parses_sym_files() {
+    let symbols = Symbols::parse(SYMBOLS);
+
+    assert_eq!(symbols.label(0, 0x0100), Some("Start"));
+    assert_eq!(symbols.label(1, 0x4000), Some("Double"));
+    assert_eq!(symbols.label(2, 0x4000), Some("Elsewhere"));
+    assert_eq!(symbols.label(1, 0x0100), None);
+    assert_eq!(symbols.address("wResult"), Some((0, 0xC000)));
+    assert_eq!(symbols.address("garbage"), None);
+}
+
+#[test]
+fn labels_disassembly() {
+    let symbols = Symbols::parse(SYMBOLS);
+    let code = [0xCD, 0x00, 0x40, 0xEA, 0x00, 0xC0, 0x18, 0xF8];
+    let label = |bank| {
+        let symbols = symbols.clone();
+        move |target| symbols.label(bank, target).map(str::to_string)
+    };
+
+    let call = disassemble_with(0x0100, |addr| code[addr as usize - 0x100], label(1));
+    assert_eq!(call.to_string(), "CALL Double");
+
+    let call = disassemble_with(0x0100, |addr| code[addr as usize - 0x100], label(2));
+    assert_eq!(call.to_string(), "CALL Elsewhere");
+
+    let store = disassemble_with(0x0103, |addr| code[addr as usize - 0x100], label(0));
+    assert_eq!(store.to_string(), "LD (wResult),A");
+
+    let jump = disassemble_with(0x0106, |addr| code[addr as usize - 0x100], label(0));
+    assert_eq!(jump.to_string(), "JR Start");
+}
+
+#[test]
+fn labels_the_trace() {
+    let mut bus = common::load(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+
+    let output = SharedBuffer::default();
+    let mut trace = Trace::new(output.clone());
+    trace.set_symbols(Symbols::parse(SYMBOLS));
+    cpu.set_trace(trace);
+
+    for _ in 0..3 {
+        cpu.step();
+    }
+
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+
+    assert!(lines[0].ends_with("PCMEM:3E,10,CD,00 ; Start"));
+    assert!(lines[1].ends_with("PCMEM:CD,00,40,EA"));
+    assert!(lines[2].ends_with("PCMEM:87,C9,00,00 ; Double"));
+}
+
+#[test]
+fn breaks_on_labels() {
+    let mut bus = common::load(PROGRAM);
+    let mut cpu = CPU::new(&mut bus);
+    let mut out = Vec::new();
+
+    let mut debugger = Debugger::new();
+    debugger.set_symbols(Symbols::parse(SYMBOLS));
+    debugger
+        .run(
+            &mut cpu,
+            "break Elsewhere\nbreak Double\nbreak\ncontinue\nfinish\nwatch wResult w\ncontinue\n"
+                .as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+
+    let output: Vec<String> = String::from_utf8(out)
+        .unwrap()
+        .split("> ")
+        .flat_map(|chunk| chunk.lines().map(str::to_string).collect::<Vec<_>>())
+        .collect();
+
+    assert_eq!(
+        output,
+        [
+            "Start:",
+            "0100  3E 10      LD A,$10",
+            "Breakpoint at $4000 in bank 02",
+            "Breakpoint at $4000 in bank 01",
+            "Breakpoint at $4000",
+            "Double:",
+            "4000  87         ADD A,A",
+            "Store:",
+            "0105  EA 00 C0   LD (wResult),A",
+            "Wrote $20 to $C000",
+            "0108  DD         DB $DD",
+        ]
+    );
+}
@@ -0,0 +1,96 @@
+use emulator::cpu::interrupts::Interrupt;
+use emulator::timer::Timer;
+use emulator::utils::traits::Storage;
+
+const DIV: usize = 0xFF04;
+const TIMA: usize = 0xFF05;
+const TMA: usize = 0xFF06;
+const TAC: usize = 0xFF07;
+
+// Enabled at 262144 Hz, TIMA counts the falling edges of bit 3 of the system counter
+fn fast_timer() -> Timer {
+    let mut timer = Timer::new();
+    timer.write(TAC, 0x05);
+    timer
+}
+
+#[test]
+fn counts_falling_edges() {
+    let mut timer = fast_timer();
+
+    timer.tick(12);
+    assert_eq!(timer.read(TIMA), 0);
+    timer.tick(4);
+    assert_eq!(timer.read(TIMA), 1);
+
+    timer.tick(16 * 10);
+    assert_eq!(timer.read(TIMA), 11);
+    assert_eq!(timer.read(DIV), 0);
+
+    timer.tick(256 - 16 * 11);
+    assert_eq!(timer.read(DIV), 1);
+}
+
+#[test]
+fn increments_when_div_is_reset_with_the_bit_set() {
+    let mut timer = fast_timer();
+
+    // Bit 3 is set, resetting the counter makes it fall
+    timer.tick(8);
+    timer.write(DIV, 0x12);
+    assert_eq!(timer.read(TIMA), 1);
+    assert_eq!(timer.read(DIV), 0);
+
+    // Counting starts over from the reset
+    timer.tick(12);
+    assert_eq!(timer.read(TIMA), 1);
+    timer.tick(4);
+    assert_eq!(timer.read(TIMA), 2);
+
+    // With the bit clear nothing happens
+    timer.tick(4);
+    timer.write(DIV, 0x00);
+    assert_eq!(timer.read(TIMA), 2);
+}
+
+#[test]
+fn increments_when_disabled_with_the_bit_set() {
+    let mut timer = fast_timer();
+    timer.tick(8);
+
+    timer.write(TAC, 0x01);
+    assert_eq!(timer.read(TIMA), 1);
+}
+
+#[test]
+fn reloads_from_tma_one_cycle_after_overflowing() {
+    let mut timer = fast_timer();
+    timer.write(TMA, 0x42);
+    timer.write(TIMA, 0xFF);
+
+    // TIMA reads 0 for the M-cycle after the overflow
+    assert_eq!(timer.tick(16), 0);
+    assert_eq!(timer.read(TIMA), 0x00);
+
+    assert_eq!(timer.tick(4), Interrupt::Timer.mask());
+    assert_eq!(timer.read(TIMA), 0x42);
+
+    // During the reload cycle TIMA writes are ignored, and TMA writes go through to TIMA
+    timer.write(TIMA, 0x10);
+    assert_eq!(timer.read(TIMA), 0x42);
+    timer.write(TMA, 0x50);
+    assert_eq!(timer.read(TIMA), 0x50);
+}
+
+#[test]
+fn cancels_the_reload_when_tima_is_written() {
+    let mut timer = fast_timer();
+    timer.write(TMA, 0x42);
+    timer.write(TIMA, 0xFF);
+
+    timer.tick(16);
+    timer.write(TIMA, 0x10);
+
+    assert_eq!(timer.tick(4), 0);
+    assert_eq!(timer.read(TIMA), 0x10);
+}
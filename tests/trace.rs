@@ -0,0 +1,86 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+
+use emulator::boot::Model;
+use emulator::cpu::trace::Trace;
+use emulator::cpu::CPU;
+use emulator::memory::bus::{Bus, MemoryBus};
+use emulator::utils::traits::Storage;
+
+const LY: usize = 0xFF44;
+
+const DOTS_PER_LINE: u32 = 456;
+
+/// A writer the test keeps a handle on after giving it away.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Started the way the DMG boot ROM leaves things, with the LCD on
+fn cartridge(program: &str) -> MemoryBus {
+    let mut bus = common::cartridge(program);
+    bus.skip_boot(Model::Dmg);
+    bus
+}
+
+#[test]
+fn pins_ly_in_doctor_mode() {
+    let mut bus = cartridge("");
+    bus.tick(3 * DOTS_PER_LINE);
+    let value: u8 = bus.read(LY);
+    assert_eq!(value, 3);
+
+    bus.set_doctor_mode(true);
+    let value: u8 = bus.read(LY);
+    assert_eq!(value, 0x90);
+    bus.tick(DOTS_PER_LINE);
+    let value: u8 = bus.read(LY);
+    assert_eq!(value, 0x90);
+
+    bus.set_doctor_mode(false);
+    let value: u8 = bus.read(LY);
+    assert_eq!(value, 4);
+}
+
+#[test]
+fn flushes_the_trace() {
+    let mut bus = cartridge(
+        "
+        org $0100
+        ld a, $10
+        ldh a, [$44]
+    ",
+    );
+    bus.set_doctor_mode(true);
+    let mut cpu = CPU::new(&mut bus);
+
+    let output = SharedBuffer::default();
+    cpu.set_trace(Trace::new(BufWriter::new(output.clone())));
+
+    for _ in 0..3 {
+        cpu.step();
+    }
+    assert!(output.0.borrow().is_empty());
+
+    cpu.flush_trace().unwrap();
+
+    let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].ends_with("PC:0102 PCMEM:F0,44,00,00"));
+    assert!(lines[2].starts_with("A:90 "));
+}